@@ -0,0 +1,73 @@
+// rooster-os/common/runtime/runtime_core/assert.rs
+//! `kassert!`: a lighter `debug_assert!` that bypasses the panic handler.
+//!   • On failure prints `kassert failed: file:line: msg` on the arch's
+//!     early console (COM1 / PL011) and halts the core
+//!   • No `core::fmt`: the line number is converted by hand and the
+//!     message is a plain `&str`, so nothing on the path can fail to format
+//!   • Like `debug_assert!`, compiled out without `debug_assertions`
+
+/// `n` in decimal, written to the end of `buf` (u32::MAX has 10 digits)
+fn decimal(n: u32, buf: &mut [u8; 10]) -> &str {
+    let mut pos = buf.len();
+    let mut n = n;
+    loop {
+        pos -= 1;
+        buf[pos] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    // SAFETY: only ASCII digits were written
+    unsafe { core::str::from_utf8_unchecked(&buf[pos..]) }
+}
+
+/// Report a failed `kassert!` and halt. Not for direct use.
+#[doc(hidden)]
+#[cold]
+pub fn fail(file: &str, line: u32, msg: &str) -> ! {
+    let mut digits = [0u8; 10];
+    let line = decimal(line, &mut digits);
+
+    for part in ["kassert failed: ", file, ":", line, ": ", msg, "\n"] {
+        crate::early_write(part);
+    }
+    crate::halt()
+}
+
+/// Assert `cond` in debug builds; on failure print file, line and `msg`
+/// (a `&str`, not a format string; defaults to the condition's source)
+/// and halt
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        $crate::kassert!($cond, stringify!($cond))
+    };
+    ($cond:expr, $msg:expr $(,)?) => {
+        if cfg!(debug_assertions) && !$cond {
+            $crate::runtime_core::assert::fail(file!(), line!(), $msg)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn line_numbers_are_printed_in_decimal() {
+        let mut buf = [0; 10];
+        assert_eq!(decimal(0, &mut buf), "0");
+        assert_eq!(decimal(42, &mut buf), "42");
+        assert_eq!(decimal(1000, &mut buf), "1000");
+        assert_eq!(decimal(u32::MAX, &mut buf), "4294967295");
+    }
+
+    #[test_case]
+    fn passing_kassert_continues() {
+        let mut checked = 0;
+        kassert!(1 + 1 == 2);
+        kassert!({ checked += 1; checked == 1 }, "evaluated once");
+        assert_eq!(checked, if cfg!(debug_assertions) { 1 } else { 0 });
+    }
+}
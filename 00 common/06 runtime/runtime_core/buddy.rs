@@ -0,0 +1,293 @@
+// rooster-os/common/runtime/runtime_core/buddy.rs
+//! Power-of-two page-block allocator for frames and page tables.
+//!   • `BuddyAllocator` hands out blocks of `1 << order` pages of 4 KiB,
+//!     `order` 0..=`MAX_ORDER`, aligned to their size within its range
+//!   • `free_pages` merges a block with its buddy for as long as the
+//!     buddy is free too
+//!   • Free lists live in the free pages themselves, so it needs no heap;
+//!     it is separate from the `GlobalAlloc` heaps
+//!   • The lock is taken with interrupts masked (`lock_irqsave`)
+
+use core::ptr::NonNull;
+
+use super::sync::Spinlock;
+
+/// Size of an order-0 block
+pub const PAGE_SIZE: usize = 4096;
+
+/// Largest block order: `1 << MAX_ORDER` pages (4 MiB)
+pub const MAX_ORDER: usize = 10;
+
+/// Header written into the first page of every free block
+struct FreePage {
+    next: *mut FreePage,
+}
+
+struct BuddyState {
+    /// Free blocks of each order, unordered
+    heads: [*mut FreePage; MAX_ORDER + 1],
+    /// Page-aligned range managed; buddies are paired relative to `base`
+    base:  usize,
+    end:   usize,
+    free:  usize, // free pages
+}
+
+// SAFETY: the lists only point into pages owned by the allocator
+unsafe impl Send for BuddyState {}
+
+impl BuddyState {
+    unsafe fn push(&mut self, addr: usize, order: usize) {
+        let page = addr as *mut FreePage;
+        page.write(FreePage { next: self.heads[order] });
+        self.heads[order] = page;
+        self.free += 1 << order;
+    }
+
+    unsafe fn pop(&mut self, order: usize) -> Option<usize> {
+        let page = self.heads[order];
+        if page.is_null() {
+            return None;
+        }
+        self.heads[order] = (*page).next;
+        self.free -= 1 << order;
+        Some(page as usize)
+    }
+
+    /// Unlink the free block at `addr` from the order's list; false if it
+    /// is not there (allocated, or split into smaller blocks)
+    unsafe fn remove(&mut self, addr: usize, order: usize) -> bool {
+        let mut link: *mut *mut FreePage = &mut self.heads[order];
+        while !(*link).is_null() {
+            if *link as usize == addr {
+                *link = (**link).next;
+                self.free -= 1 << order;
+                return true;
+            }
+            link = &mut (**link).next;
+        }
+        false
+    }
+
+    /// The block paired with `[addr, addr + block size)` at `order`
+    fn buddy_of(&self, addr: usize, order: usize) -> usize {
+        self.base + ((addr - self.base) ^ (PAGE_SIZE << order))
+    }
+}
+
+/// Buddy allocator over a page-aligned physical range
+pub struct BuddyAllocator {
+    state: Spinlock<BuddyState>,
+}
+
+impl BuddyAllocator {
+    pub const fn new() -> Self {
+        BuddyAllocator {
+            state: Spinlock::new(BuddyState {
+                heads: [core::ptr::null_mut(); MAX_ORDER + 1],
+                base:  0,
+                end:   0,
+                free:  0,
+            }),
+        }
+    }
+
+    /// (Re)initialize over the whole pages inside `[start, end)`, carved
+    /// into the largest blocks that fit. Partial pages at either end are
+    /// left out.
+    ///
+    /// SAFETY: the range must be RAM owned by this allocator from now on.
+    pub unsafe fn init(&self, start: usize, end: usize) {
+        let base = start.checked_add(PAGE_SIZE - 1).map_or(end, |a| a & !(PAGE_SIZE - 1));
+        let end  = end & !(PAGE_SIZE - 1);
+        let mut state = self.state.lock_irqsave();
+        state.heads = [core::ptr::null_mut(); MAX_ORDER + 1];
+        state.base  = base;
+        state.end   = end.max(base);
+        state.free  = 0;
+
+        let mut addr = base;
+        while addr < state.end {
+            // the largest order aligned at `addr` (relative to base) that fits
+            let mut order = MAX_ORDER;
+            while (addr - base) % (PAGE_SIZE << order) != 0
+                || addr + (PAGE_SIZE << order) > state.end
+            {
+                order -= 1;
+            }
+            state.push(addr, order);
+            addr += PAGE_SIZE << order;
+        }
+    }
+
+    /// A block of `1 << order` pages, aligned to its size relative to the
+    /// start of the range; `None` if `order > MAX_ORDER` or no block that
+    /// large is left
+    pub fn alloc_pages(&self, order: usize) -> Option<NonNull<u8>> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        let mut state = self.state.lock_irqsave();
+        // SAFETY: every list entry is a free block of its order
+        unsafe {
+            let mut from = order;
+            let addr = loop {
+                if let Some(addr) = state.pop(from) {
+                    break addr;
+                }
+                from += 1;
+                if from > MAX_ORDER {
+                    return None;
+                }
+            };
+            // keep the lower half, free the upper half, down to `order`
+            while from > order {
+                from -= 1;
+                state.push(addr + (PAGE_SIZE << from), from);
+            }
+            NonNull::new(addr as *mut u8)
+        }
+    }
+
+    /// Return a block from `alloc_pages(order)`, merging it with its buddy
+    /// as long as that is free. Panics if `ptr` is not a block of this
+    /// range at that order.
+    ///
+    /// SAFETY: the block must be allocated here with the same `order` and
+    /// no longer in use.
+    pub unsafe fn free_pages(&self, ptr: NonNull<u8>, order: usize) {
+        let mut addr = ptr.as_ptr() as usize;
+        let mut state = self.state.lock_irqsave();
+        if order > MAX_ORDER
+            || addr < state.base
+            || (addr - state.base) % (PAGE_SIZE << order) != 0
+            || addr + (PAGE_SIZE << order) > state.end
+        {
+            panic!("buddy: {:#x} is not an order-{} block of this allocator", addr, order);
+        }
+
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = state.buddy_of(addr, order);
+            if buddy + (PAGE_SIZE << order) > state.end || !state.remove(buddy, order) {
+                break;
+            }
+            addr = addr.min(buddy);
+            order += 1;
+        }
+        state.push(addr, order);
+    }
+
+    /// Pages currently free, across all orders
+    pub fn free_page_count(&self) -> usize {
+        self.state.lock_irqsave().free
+    }
+
+    /// Range managed since the last `init`, as (start, end)
+    pub fn bounds(&self) -> (usize, usize) {
+        let state = self.state.lock_irqsave();
+        (state.base, state.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::UnsafeCell;
+
+    const PAGES: usize = 16;
+
+    #[repr(align(4096))]
+    struct Arena(UnsafeCell<[u8; PAGES * PAGE_SIZE]>);
+
+    // SAFETY: tests run one at a time on the boot CPU
+    unsafe impl Sync for Arena {}
+
+    static ARENA: Arena = Arena(UnsafeCell::new([0; PAGES * PAGE_SIZE]));
+
+    /// A buddy allocator over the whole arena: one order-4 block
+    fn buddy() -> (BuddyAllocator, usize) {
+        let heap = BuddyAllocator::new();
+        let base = ARENA.0.get() as usize;
+        unsafe { heap.init(base, base + PAGES * PAGE_SIZE) };
+        (heap, base)
+    }
+
+    fn addr(block: Option<NonNull<u8>>) -> usize {
+        block.unwrap().as_ptr() as usize
+    }
+
+    #[test_case]
+    fn alloc_splits_the_smallest_larger_block() {
+        let (heap, base) = buddy();
+        assert_eq!(addr(heap.alloc_pages(0)), base);
+        // the split left one free block of each order 0..=3
+        assert_eq!(heap.free_page_count(), PAGES - 1);
+        assert_eq!(addr(heap.alloc_pages(0)), base + PAGE_SIZE);
+        assert_eq!(addr(heap.alloc_pages(1)), base + 2 * PAGE_SIZE);
+        assert_eq!(addr(heap.alloc_pages(3)), base + 8 * PAGE_SIZE);
+        assert_eq!(addr(heap.alloc_pages(1)), base + 4 * PAGE_SIZE);
+        assert_eq!(heap.free_page_count(), 2);
+    }
+
+    #[test_case]
+    fn free_merges_buddies_back_into_one_block() {
+        let (heap, base) = buddy();
+        let mut pages = [None; PAGES];
+        for page in pages.iter_mut() {
+            *page = heap.alloc_pages(0);
+        }
+        assert_eq!(heap.alloc_pages(0), None);
+        // free out of order, so merges happen at every level
+        for i in [5, 0, 15, 2, 9, 1, 3, 14, 4, 8, 6, 11, 7, 10, 13, 12] {
+            unsafe { heap.free_pages(pages[i].unwrap(), 0) };
+        }
+        assert_eq!(heap.free_page_count(), PAGES);
+        assert_eq!(addr(heap.alloc_pages(4)), base);
+    }
+
+    #[test_case]
+    fn free_merges_only_with_the_buddy() {
+        let (heap, base) = buddy();
+        let blocks = [heap.alloc_pages(1), heap.alloc_pages(1), heap.alloc_pages(1)];
+        assert_eq!(addr(blocks[2]), base + 4 * PAGE_SIZE);
+        unsafe {
+            // pages 2-3 touch 4-5 but are not its buddy; 6-7 is, and is free
+            heap.free_pages(blocks[1].unwrap(), 1);
+            heap.free_pages(blocks[2].unwrap(), 1);
+        }
+        assert_eq!(heap.free_page_count(), PAGES - 2);
+        let merged = heap.alloc_pages(2);
+        assert_eq!(addr(merged), base + 4 * PAGE_SIZE);
+        assert_eq!(addr(heap.alloc_pages(1)), base + 2 * PAGE_SIZE);
+        unsafe {
+            heap.free_pages(NonNull::new(base as *mut u8).unwrap(), 1);
+            heap.free_pages(NonNull::new((base + 2 * PAGE_SIZE) as *mut u8).unwrap(), 1);
+            heap.free_pages(merged.unwrap(), 2);
+        }
+        assert_eq!(addr(heap.alloc_pages(4)), base);
+    }
+
+    #[test_case]
+    fn exhaustion_and_bad_orders_return_none() {
+        let (heap, base) = buddy();
+        assert_eq!(heap.alloc_pages(MAX_ORDER + 1), None);
+        assert_eq!(heap.alloc_pages(5), None); // larger than the range
+        assert_eq!(addr(heap.alloc_pages(4)), base);
+        assert_eq!(heap.alloc_pages(0), None);
+        assert_eq!(heap.free_page_count(), 0);
+    }
+
+    #[test_case]
+    fn init_drops_partial_pages_and_carves_aligned_blocks() {
+        let heap = BuddyAllocator::new();
+        let base = ARENA.0.get() as usize;
+        // six whole pages: an order-2 block, then an order-1 block
+        unsafe { heap.init(base + PAGE_SIZE - 8, base + 7 * PAGE_SIZE + 8) };
+        assert_eq!(heap.bounds(), (base + PAGE_SIZE, base + 7 * PAGE_SIZE));
+        assert_eq!(heap.free_page_count(), 6);
+        assert_eq!(heap.alloc_pages(3), None);
+        assert_eq!(addr(heap.alloc_pages(1)), base + 5 * PAGE_SIZE);
+        assert_eq!(addr(heap.alloc_pages(2)), base + PAGE_SIZE);
+        assert_eq!(heap.free_page_count(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+// rooster-os/common/runtime/runtime_core/console.rs
+//! Pluggable console sink behind `print!`/`println!` and the panic report.
+//!   • The kernel installs its sink with `set_console` (serial, VGA
+//!     text, a framebuffer console, ...) and may swap it later, e.g. from
+//!     serial to the framebuffer once that is up
+//!   • Until then everything printed is silently discarded
+//!   • `TeeConsole` fans one sink out to several (e.g. serial + VGA)
+//!
+//! `fmt::Write` needs `&mut self`, which a shared `&'static dyn Console`
+//! cannot provide, so sinks write through `&self` (doing their own
+//! locking, if any) and `ConsoleWriter` adapts the installed one back to
+//! `fmt::Write`.
+
+use core::fmt;
+
+use super::sync::Spinlock;
+
+/// An output sink for `print!`/`println!`
+pub trait Console: Sync {
+    fn write_str(&self, s: &str) -> fmt::Result;
+}
+
+/// A console that forwards everything to each of `sinks` in turn, e.g.
+/// `static TEE: TeeConsole = TeeConsole::new(&[&COM, &vga::WRITER])`
+/// and then `set_console(&TEE)`
+pub struct TeeConsole {
+    sinks: &'static [&'static dyn Console],
+}
+
+impl TeeConsole {
+    pub const fn new(sinks: &'static [&'static dyn Console]) -> Self {
+        TeeConsole { sinks }
+    }
+}
+
+/// Every sink gets the whole string even if an earlier one failed; the
+/// first error is reported once all have been tried
+impl Console for TeeConsole {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        let mut result = Ok(());
+        for sink in self.sinks {
+            result = result.and(sink.write_str(s));
+        }
+        result
+    }
+}
+
+/// Held (with interrupts masked) only to copy the pointer in or out, so
+/// a print never waits on a slow sink and a swap never waits on a print
+static CONSOLE: Spinlock<Option<&'static dyn Console>> = Spinlock::new(None);
+
+/// Install `console` in place of the current one, which is returned.
+/// Output already being written goes on to the old console.
+pub fn set_console(console: &'static dyn Console) -> Option<&'static dyn Console> {
+    CONSOLE.lock_irqsave().replace(console)
+}
+
+/// The installed console, if any
+pub fn console() -> Option<&'static dyn Console> {
+    *CONSOLE.lock_irqsave()
+}
+
+/// `fmt::Write` handle to the installed console; discards output while
+/// none is installed
+pub struct ConsoleWriter;
+
+impl fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match console() {
+            Some(console) => console.write_str(s),
+            None          => Ok(()),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut ConsoleWriter, args);
+}
+
+/// Print to the installed console
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::runtime_core::console::_print(format_args!($($arg)*)));
+}
+
+/// Print a line to the installed console
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_core::sync::Spinlock;
+    use crate::runtime_core::FmtBuf;
+
+    /// A sink that keeps what it was sent
+    struct Recorder(Spinlock<FmtBuf<64>>);
+
+    impl Console for Recorder {
+        fn write_str(&self, s: &str) -> fmt::Result {
+            fmt::Write::write_str(&mut *self.0.lock(), s)
+        }
+    }
+
+    /// A sink that always fails
+    struct Broken;
+
+    impl Console for Broken {
+        fn write_str(&self, _: &str) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    static FIRST:   Recorder = Recorder(Spinlock::new(FmtBuf::new()));
+    static SECOND:  Recorder = Recorder(Spinlock::new(FmtBuf::new()));
+    static BROKEN:  Broken = Broken;
+    static TEE:     TeeConsole = TeeConsole::new(&[&FIRST, &BROKEN, &SECOND]);
+    static HEALTHY: TeeConsole = TeeConsole::new(&[&FIRST, &SECOND]);
+
+    #[test_case]
+    fn tee_reaches_every_sink_past_a_failing_one() {
+        assert_eq!(TEE.write_str("serial+vga"), Err(fmt::Error));
+        assert_eq!(TEE.write_str("\n"), Err(fmt::Error));
+        assert_eq!(FIRST.0.lock().as_str(), "serial+vga\n");
+        assert_eq!(SECOND.0.lock().as_str(), "serial+vga\n");
+        // without the failing sink everything succeeds
+        assert_eq!(HEALTHY.write_str("!"), Ok(()));
+        assert_eq!(SECOND.0.lock().as_str(), "serial+vga\n!");
+    }
+
+    #[test_case]
+    fn set_console_swaps_the_sink() {
+        static EARLY: Recorder = Recorder(Spinlock::new(FmtBuf::new()));
+        static LATER: Recorder = Recorder(Spinlock::new(FmtBuf::new()));
+        // whatever the runner installed goes back at the end
+        let original = *CONSOLE.lock();
+
+        set_console(&EARLY);
+        crate::print!("serial ");
+        let previous = set_console(&LATER).unwrap();
+        assert!(core::ptr::addr_eq(previous, &EARLY));
+        crate::print!("framebuffer");
+        *CONSOLE.lock() = original;
+
+        assert_eq!(EARLY.0.lock().as_str(), "serial ");
+        assert_eq!(LATER.0.lock().as_str(), "framebuffer");
+    }
+
+    #[test_case]
+    fn output_without_a_console_is_discarded() {
+        let original = CONSOLE.lock().take();
+        crate::print!("nobody hears this");
+        assert!(console().is_none());
+        *CONSOLE.lock() = original;
+    }
+}
@@ -0,0 +1,2072 @@
+// rooster-os/common/runtime/runtime_core/heap.rs
+//! Architecture-neutral heap allocators and out-of-memory policy.
+//!   • `BumpAllocator` (`alloc-bump`, the default), `LinkedListAllocator`
+//!     (`alloc-freelist`) and `SlabAllocator` (`alloc-slab`), all `GlobalAlloc`
+//!   • The free list is first-fit unless `best-fit` picks the smallest
+//!     block that fits, leaving fewer slivers under mixed sizes
+//!   • OOM callback with `Retry`/`Abort`, fresh/freed-memory poisoning
+//!   • `track-allocs` hooks call into the arch crate's `alloc_track`
+//!   • Owns the crate's `#[global_allocator]` and `#[alloc_error_handler]`
+//!     (which reports the layout and the heap's `HeapStats`); under
+//!     `no-heap` there are neither, and any use of `alloc` fails to link
+//!   • `alloc_aligned`/`free_aligned`: size + alignment wrappers over it
+//!   • `try_alloc` on every heap: `alloc` as a `Result` for callers that
+//!     handle OOM themselves instead of reaching `alloc_error`
+//!   • `reserve(start, end)` keeps a range (boot data) out of the heap
+//!   • `MIN_ALIGN` (`min-align-64`): a floor on every allocation's alignment
+//!   • `BumpAllocator::mark_zeroed` lets `alloc_zeroed` skip clearing RAM
+//!     that is known to be zero already
+//!   • Every allocator lock is taken with interrupts masked
+//!     (`Spinlock::lock_irqsave`), so interrupt handlers may allocate
+//!   • `EMERGENCY_HEAP`: a fixed 4 KiB arena for the panic path only
+//!   • `PerCpuHeap` (`percpu-heap`): lock-free per-CPU arenas in front of
+//!     the shared heap; needs the arch's `crate::cpu_id()`
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ptr::NonNull;
+use core::intrinsics::copy_nonoverlapping;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::panic;
+use super::sync::Spinlock;
+#[cfg(feature = "track-allocs")]
+use crate::alloc_track;
+
+/// What the allocator should do after an out-of-memory callback returns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OomAction {
+    /// The callback freed memory; try the allocation again
+    Retry,
+    /// Give up: `alloc` returns null and `alloc_error` halts
+    Abort,
+}
+
+/// Installed OOM callback as a raw `fn(Layout) -> OomAction`; 0 = none
+static OOM_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Install a callback invoked whenever an allocation fails. It runs with
+/// no allocator lock held, so it may free caches before asking to `Retry`.
+pub fn set_oom_handler(f: fn(Layout) -> OomAction) {
+    OOM_HANDLER.store(f as usize, Ordering::Release);
+}
+
+fn oom_handler() -> Option<fn(Layout) -> OomAction> {
+    match OOM_HANDLER.load(Ordering::Acquire) {
+        0 => None,
+        // SAFETY: only ever stored from a valid fn pointer in `set_oom_handler`
+        f => Some(unsafe { core::mem::transmute::<usize, fn(Layout) -> OomAction>(f) }),
+    }
+}
+
+/// A `try_alloc` failed: the heap (after any OOM handler retries) had no
+/// room for the layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+/// `heap.alloc(layout)` with null turned into `Err`. Zero-size layouts get
+/// a dangling, aligned pointer without touching the heap (`alloc` may not
+/// be called with them).
+fn try_alloc_from(heap: &impl GlobalAlloc, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+    if layout.size() == 0 {
+        // SAFETY: an alignment is never zero
+        return Ok(unsafe { NonNull::new_unchecked(layout.align() as *mut u8) });
+    }
+    // SAFETY: the layout has a non-zero size
+    NonNull::new(unsafe { heap.alloc(layout) }).ok_or(AllocError)
+}
+
+/// Pattern written over fresh allocations by the `poison` feature
+const POISON_ALLOC: u8 = 0xAA;
+/// Pattern written over freed blocks by the `poison` feature
+const POISON_FREE:  u8 = 0xDE;
+
+/// Fill `[ptr, ptr + len)` with `pattern` when the `poison` feature is on.
+/// Debug builds only: release builds compile this to nothing.
+#[inline]
+unsafe fn poison(ptr: *mut u8, len: usize, pattern: u8) {
+    #[cfg(all(feature = "poison", debug_assertions))]
+    core::ptr::write_bytes(ptr, pattern, len);
+    #[cfg(not(all(feature = "poison", debug_assertions)))]
+    let _ = (ptr, len, pattern);
+}
+
+/// Record a free for the `track-allocs` feature (no-op otherwise)
+#[inline]
+fn track_free(ptr: *mut u8) {
+    #[cfg(feature = "track-allocs")]
+    alloc_track::record_free(ptr);
+    #[cfg(not(feature = "track-allocs"))]
+    let _ = ptr;
+}
+
+/// Run `attempt` until it succeeds or the OOM handler (if any) says `Abort`.
+/// Kept out of line under `track-allocs` so the frame walk finds the
+/// code that called `GlobalAlloc::alloc` one frame up.
+#[cfg_attr(feature = "track-allocs", inline(never))]
+fn with_oom_retry(layout: Layout, mut attempt: impl FnMut() -> *mut u8) -> *mut u8 {
+    if cfg!(debug_assertions) {
+        if let Err(err) = check_not_in_panic() {
+            panic!("{}", err);
+        }
+    }
+    loop {
+        let ptr = attempt();
+        if !ptr.is_null() {
+            unsafe { poison(ptr, layout.size(), POISON_ALLOC) };
+            #[cfg(feature = "track-allocs")]
+            alloc_track::record_alloc(ptr, layout.size(), alloc_track::caller_address(1));
+            return ptr;
+        }
+        match oom_handler() {
+            Some(handler) if handler(layout) == OomAction::Retry => continue,
+            _ => return ptr,
+        }
+    }
+}
+
+/// Smallest alignment the heap allocators hand out, whatever the `Layout`
+/// asks for: a 64-byte cache line under `min-align-64` (e.g. so DMA
+/// buffers never share a line with other data), otherwise 1 (no floor).
+/// The price is memory: every allocation may start up to `MIN_ALIGN - 1`
+/// bytes further on, so many small allocations waste most of their
+/// space, and slab classes below `MIN_ALIGN` go unused.
+pub const MIN_ALIGN: usize = if cfg!(feature = "min-align-64") { 64 } else { 1 };
+
+/// `layout` with its alignment raised to at least `MIN_ALIGN`; only the
+/// placement changes, so frees and resizes may keep passing the original
+#[inline]
+fn min_aligned(layout: Layout) -> Layout {
+    // fails only for sizes no heap could satisfy anyway
+    layout.align_to(MIN_ALIGN).unwrap_or(layout)
+}
+
+/// Round `addr` up to `align`; `None` if `align` is not a power of two
+/// or the rounded address would overflow `usize`
+#[inline]
+fn align_up(addr: usize, align: usize) -> Option<usize> {
+    if !align.is_power_of_two() {
+        return None;
+    }
+    addr.checked_add(align - 1).map(|a| a & !(align - 1))
+}
+
+/// An empty or inverted range handed to a heap's `init`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EmptyHeap {
+    start: usize,
+    end:   usize,
+}
+
+impl fmt::Display for EmptyHeap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "heap: empty or inverted range [{:#x}, {:#x})", self.start, self.end)
+    }
+}
+
+/// Reject an empty or inverted heap at `init`: otherwise every allocation
+/// just returns null, far from the linker script that caused it
+fn check_heap_range(start: usize, end: usize) -> Result<(), EmptyHeap> {
+    if end <= start {
+        return Err(EmptyHeap { start, end });
+    }
+    Ok(())
+}
+
+/// An allocation from the global heap after `panic::begin`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AllocInPanic;
+
+impl fmt::Display for AllocInPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("heap allocation in the panic handler")
+    }
+}
+
+/// The debug-build check `with_oom_retry` runs before every allocation:
+/// the panic path must not touch the heap, which may be what failed
+fn check_not_in_panic() -> Result<(), AllocInPanic> {
+    if panic::in_progress() {
+        return Err(AllocInPanic);
+    }
+    Ok(())
+}
+
+/// Snapshot of a heap's usage, e.g. for the allocation-error report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapStats {
+    pub used_bytes:     usize,
+    pub free_bytes:     usize,
+    pub high_watermark: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "used_bytes={} free_bytes={} high_watermark={}",
+            self.used_bytes, self.free_bytes, self.high_watermark
+        )
+    }
+}
+
+/// `BumpAllocator::init` trims its primary range inward to this boundary
+const HEAP_ALIGN: usize = core::mem::size_of::<usize>();
+const _: () = assert!(HEAP_ALIGN.is_power_of_two(), "heap alignment must be a power of two");
+
+/// Maximum number of discontiguous regions one bump allocator manages
+pub const MAX_HEAP_REGIONS: usize = 8;
+
+/// One contiguous bump arena `[start, end)` with its own cursor
+#[derive(Clone, Copy)]
+struct BumpRegion {
+    start: usize,
+    next:  usize,
+    end:   usize,
+    clean: usize, // [clean, end) is known to still be zero; `end` if unknown
+}
+
+impl BumpRegion {
+    const EMPTY: BumpRegion = BumpRegion { start: 0, next: 0, end: 0, clean: 0 };
+
+    const fn new(start: usize, end: usize) -> Self {
+        BumpRegion { start, next: start, end, clean: end }
+    }
+
+    fn used(&self) -> usize {
+        self.next - self.start
+    }
+
+    fn free(&self) -> usize {
+        self.end - self.next
+    }
+
+    /// Carve `layout` out of this region; `None` if it does not fit
+    fn bump(&mut self, layout: Layout) -> Option<usize> {
+        let ptr = align_up(self.next, layout.align())?;
+        let new_next = ptr.checked_add(layout.size()).filter(|&n| n <= self.end)?;
+        self.advance(new_next);
+        Some(ptr)
+    }
+
+    /// Move `next` to `new_next`; whatever lies below may now be written
+    fn advance(&mut self, new_next: usize) {
+        self.next  = new_next;
+        self.clean = self.clean.max(new_next);
+    }
+}
+
+/// Mutable state of the bump allocator, kept behind a `Spinlock`
+struct BumpState {
+    regions: [BumpRegion; MAX_HEAP_REGIONS],
+    count:   usize,
+    peak:    usize,
+    padding: usize, // alignment padding skipped since init/reset
+    trimmed: usize, // bytes `init` cut off to word-align the range
+}
+
+impl BumpState {
+    fn regions(&self) -> &[BumpRegion] {
+        &self.regions[..self.count]
+    }
+
+    fn regions_mut(&mut self) -> &mut [BumpRegion] {
+        &mut self.regions[..self.count]
+    }
+
+    fn used(&self) -> usize {
+        self.regions().iter().map(BumpRegion::used).sum()
+    }
+
+    fn note_usage(&mut self) {
+        self.peak = self.peak.max(self.used());
+    }
+}
+
+/// A simple bump‐pointer allocator over one or more heap regions
+pub struct BumpAllocator {
+    state: Spinlock<BumpState>,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        BumpAllocator {
+            state: Spinlock::new(BumpState {
+                regions: [BumpRegion::EMPTY; MAX_HEAP_REGIONS],
+                count:   0,
+                peak:    0,
+                padding: 0,
+                trimmed: 0,
+            }),
+        }
+    }
+
+    /// (Re)initialize with a single primary region, dropping any others.
+    /// The range is narrowed to whole words (start rounded up, end down)
+    /// in case the linker script left it misaligned; `alignment_loss`
+    /// tells how much that cost. Panics if `heap_end <= heap_start`.
+    pub unsafe fn init(&self, heap_start: usize, heap_end: usize) {
+        if let Err(err) = check_heap_range(heap_start, heap_end) {
+            panic!("{}", err);
+        }
+        let start = align_up(heap_start, HEAP_ALIGN).unwrap_or(heap_end);
+        let end   = (heap_end & !(HEAP_ALIGN - 1)).max(start);
+        let mut state = self.state.lock_irqsave();
+        state.regions[0] = BumpRegion::new(start, end);
+        state.count   = 1;
+        state.peak    = 0;
+        state.padding = 0;
+        state.trimmed = (heap_end - heap_start) - (end - start);
+    }
+
+    /// Bytes the last `init` cut off the ends of its range to word-align it
+    pub fn alignment_loss(&self) -> usize {
+        self.state.lock_irqsave().trimmed
+    }
+
+    /// Add another discontiguous region after `init`. Returns false if the
+    /// range is empty or `MAX_HEAP_REGIONS` are already in use.
+    ///
+    /// SAFETY: `[start, end)` must be unused RAM owned by the allocator.
+    pub unsafe fn add_region(&self, start: usize, end: usize) -> bool {
+        let mut state = self.state.lock_irqsave();
+        if end <= start || state.count == MAX_HEAP_REGIONS {
+            return false;
+        }
+        let i = state.count;
+        state.regions[i] = BumpRegion::new(start, end);
+        state.count += 1;
+        true
+    }
+
+    /// Declare that every region's free part already reads as zero (e.g.
+    /// pages the loader zeroed), so `alloc_zeroed` can skip clearing it
+    /// until it is handed out. Off by default: without it every
+    /// `alloc_zeroed` block is cleared.
+    ///
+    /// SAFETY: each region's bytes from `next` to its end must really be
+    /// zero; call it right after `init` / `add_region`.
+    pub unsafe fn mark_zeroed(&self) {
+        let mut state = self.state.lock_irqsave();
+        for region in state.regions_mut() {
+            region.clean = region.next;
+        }
+    }
+
+    /// Primary heap range passed to the last `init` (word-aligned, and its
+    /// end pulled in if `reserve` split it), as (start, end)
+    pub fn bounds(&self) -> (usize, usize) {
+        let state = self.state.lock_irqsave();
+        (state.regions[0].start, state.regions[0].end)
+    }
+
+    /// `alloc` as a `Result`: `Err` instead of null when the heap is
+    /// exhausted, so the caller can recover (the OOM handler still runs)
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        try_alloc_from(self, layout)
+    }
+
+    /// Never hand out `[start, end)`: each region's free part is split
+    /// around it (the part above becomes a new region). Space already
+    /// handed out is not affected. Returns false if a split needed a
+    /// region slot and none was left; the part above is then dropped, so
+    /// the range is still never allocated.
+    ///
+    /// SAFETY: must not race with allocations that assume the old layout
+    /// (call it during init).
+    pub unsafe fn reserve(&self, start: usize, end: usize) -> bool {
+        let mut state = self.state.lock_irqsave();
+        let mut ok = true;
+        for i in 0..state.count {
+            let r = &mut state.regions[i];
+            let (lo, hi) = (start.max(r.next), end.min(r.end));
+            if lo >= hi {
+                continue;
+            }
+            let tail = BumpRegion { clean: r.clean.max(hi), ..BumpRegion::new(hi, r.end) };
+            r.end = lo;
+            if tail.end > tail.start {
+                if state.count == MAX_HEAP_REGIONS {
+                    ok = false;
+                } else {
+                    let n = state.count;
+                    state.regions[n] = tail;
+                    state.count += 1;
+                }
+            }
+        }
+        ok
+    }
+
+    /// Bytes handed out since `init` (or the last `reset`) across all
+    /// regions, padding included
+    pub fn used_bytes(&self) -> usize {
+        self.state.lock_irqsave().used()
+    }
+
+    /// Bytes still available between each region's `next` and its end
+    pub fn free_bytes(&self) -> usize {
+        self.state.lock_irqsave().regions().iter().map(BumpRegion::free).sum()
+    }
+
+    /// Bytes skipped to satisfy alignment since `init` (or the last
+    /// `reset`); already included in `used_bytes`
+    pub fn padding_bytes(&self) -> usize {
+        self.state.lock_irqsave().padding
+    }
+
+    /// Largest `used_bytes` value ever observed; survives `reset`
+    pub fn high_watermark(&self) -> usize {
+        self.state.lock_irqsave().peak
+    }
+
+    /// `used_bytes`, `free_bytes` and `high_watermark` under one lock
+    pub fn stats(&self) -> HeapStats {
+        let state = self.state.lock_irqsave();
+        HeapStats {
+            used_bytes:     state.used(),
+            free_bytes:     state.regions().iter().map(BumpRegion::free).sum(),
+            high_watermark: state.peak,
+        }
+    }
+
+    /// Reclaim the whole heap by rewinding every region to its start.
+    ///
+    /// SAFETY: this invalidates every outstanding allocation; the caller
+    /// must guarantee nothing allocated from this heap is still live.
+    pub unsafe fn reset(&self) {
+        let mut state = self.state.lock_irqsave();
+        for region in state.regions_mut() {
+            region.next = region.start;
+        }
+        state.padding = 0;
+    }
+
+    /// One allocation attempt without consulting the OOM handler. Regions
+    /// are tried in the order they were added.
+    ///
+    /// Zero-size layouts get a dangling pointer equal to their alignment
+    /// (non-null and aligned, per Rust convention) and consume no heap.
+    /// Layouts larger than every region fail without touching `next`.
+    unsafe fn alloc_once(&self, layout: Layout) -> *mut u8 {
+        self.alloc_once_dirty(layout).0
+    }
+
+    /// `alloc_once`, also returning how many leading bytes of the block
+    /// may be non-zero (the rest is known-zero, see `mark_zeroed`)
+    unsafe fn alloc_once_dirty(&self, layout: Layout) -> (*mut u8, usize) {
+        let layout = min_aligned(layout);
+        if layout.size() == 0 {
+            return (layout.align() as *mut u8, 0);
+        }
+        let mut state = self.state.lock_irqsave();
+        let largest = state.regions().iter().map(|r| r.end - r.start).max().unwrap_or(0);
+        if layout.size() > largest {
+            return (core::ptr::null_mut(), 0);
+        }
+        // update the cursor (and stats) while still holding the lock; the
+        // padding is how far alignment pushed `ptr` past the old `next`
+        let hit = state.regions_mut().iter_mut().find_map(|r| {
+            let (before, clean) = (r.next, r.clean);
+            r.bump(layout).map(|ptr| (ptr, ptr - before, clean.saturating_sub(ptr)))
+        });
+        match hit {
+            Some((ptr, pad, dirty)) => {
+                state.padding += pad;
+                state.note_usage();
+                (ptr as *mut u8, dirty.min(layout.size()))
+            }
+            None => (core::ptr::null_mut(), 0),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        with_oom_retry(layout, || self.alloc_once(layout))
+    }
+
+    /// Clear only the part of the block not known to be zero already
+    /// (all of it unless `mark_zeroed` was called)
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let mut dirty = layout.size();
+        let ptr = with_oom_retry(layout, || {
+            let (ptr, d) = self.alloc_once_dirty(layout);
+            dirty = d;
+            ptr
+        });
+        // `poison` has just filled the whole block
+        if cfg!(all(feature = "poison", debug_assertions)) {
+            dirty = layout.size();
+        }
+        if !ptr.is_null() {
+            core::ptr::write_bytes(ptr, 0, dirty);
+        }
+        ptr
+    }
+
+    /// Reclaim the block only if it is the most recent allocation of its
+    /// region (it ends exactly at `next`), so LIFO frees give the space
+    /// back; anything else stays used until `reset`. Alignment padding in
+    /// front of the block is not recovered.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track_free(ptr);
+        if layout.size() == 0 {
+            return;
+        }
+        let start = ptr as usize;
+        let mut state = self.state.lock_irqsave();
+        let region = state
+            .regions_mut()
+            .iter_mut()
+            .find(|r| r.start <= start && start + layout.size() == r.next);
+        if let Some(region) = region {
+            poison(ptr, layout.size(), POISON_FREE);
+            region.next = start;
+        }
+    }
+
+    /// Resize in place when `ptr` is the most recent allocation of its
+    /// region (it ends exactly at `next`); otherwise fall back to
+    /// alloc + copy + dealloc
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.size() != 0 {
+            let mut state = self.state.lock_irqsave();
+            let start = ptr as usize;
+            let region = state
+                .regions_mut()
+                .iter_mut()
+                .find(|r| r.start <= start && start + layout.size() == r.next);
+            if let Some(region) = region {
+                if let Some(new_next) = start.checked_add(new_size).filter(|&n| n <= region.end) {
+                    region.advance(new_next);
+                    state.note_usage();
+                    #[cfg(feature = "track-allocs")]
+                    {
+                        alloc_track::record_free(ptr);
+                        alloc_track::record_alloc(ptr, new_size, alloc_track::caller_address(0));
+                    }
+                    return ptr;
+                }
+            }
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// Header written into the first bytes of every free block
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Every free-list block start and size is a multiple of this, so
+/// splitting a block can never leave a sliver too small for a header
+const BLOCK_GRANULE: usize = core::mem::size_of::<FreeBlock>();
+
+/// Address-ordered singly linked list of free blocks
+struct FreeListState {
+    head:  *mut FreeBlock,
+    start: usize,
+    end:   usize,
+    free:  usize, // bytes in the list
+    peak:  usize, // most bytes ever outside it
+}
+
+impl FreeListState {
+    /// Bytes of the heap range not in the free list: allocations,
+    /// reserved ranges and the granule rounding at either end
+    fn used(&self) -> usize {
+        self.end - self.start - self.free
+    }
+
+    fn note_usage(&mut self) {
+        self.peak = self.peak.max(self.used());
+    }
+}
+
+// SAFETY: the list only points into heap memory owned by the allocator
+unsafe impl Send for FreeListState {}
+
+/// Under `best-fit` the free list takes the smallest block that fits
+/// instead of the first one
+const BEST_FIT: bool = cfg!(feature = "best-fit");
+
+/// A first-fit (or `best-fit`) free-list allocator that coalesces
+/// neighbours on `dealloc`
+pub struct LinkedListAllocator {
+    state: Spinlock<FreeListState>,
+}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        LinkedListAllocator {
+            state: Spinlock::new(FreeListState {
+                head:  core::ptr::null_mut(),
+                start: 0,
+                end:   0,
+                free:  0,
+                peak:  0,
+            }),
+        }
+    }
+
+    /// (Re)initialize as one free block. Panics if `heap_end <= heap_start`.
+    pub unsafe fn init(&self, heap_start: usize, heap_end: usize) {
+        if let Err(err) = check_heap_range(heap_start, heap_end) {
+            panic!("{}", err);
+        }
+        let start = align_up(heap_start, BLOCK_GRANULE).unwrap_or(heap_end);
+        let end   = heap_end & !(BLOCK_GRANULE - 1);
+        let mut state = self.state.lock_irqsave();
+        state.head  = core::ptr::null_mut();
+        state.start = heap_start;
+        state.end   = heap_end;
+        state.free  = 0;
+        if end > start {
+            Self::insert(&mut state, start, end - start);
+        }
+        state.peak  = state.used();
+    }
+
+    /// Heap range passed to the last `init`, as (start, end)
+    pub fn bounds(&self) -> (usize, usize) {
+        let state = self.state.lock_irqsave();
+        (state.start, state.end)
+    }
+
+    /// `alloc` as a `Result`: `Err` instead of null when the heap is
+    /// exhausted, so the caller can recover (the OOM handler still runs)
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        try_alloc_from(self, layout)
+    }
+
+    /// Usage of the heap range; reserved ranges count as used
+    pub fn stats(&self) -> HeapStats {
+        let state = self.state.lock_irqsave();
+        HeapStats { used_bytes: state.used(), free_bytes: state.free, high_watermark: state.peak }
+    }
+
+    /// Take `[start, end)` (widened to whole granules) out of the free
+    /// list so it is never handed out; blocks that straddle it keep their
+    /// parts outside. Always returns true (the bump version can fail).
+    ///
+    /// SAFETY: the range must not be live allocations of this heap.
+    pub unsafe fn reserve(&self, start: usize, end: usize) -> bool {
+        let lo = start & !(BLOCK_GRANULE - 1);
+        let hi = align_up(end, BLOCK_GRANULE).unwrap_or(usize::MAX & !(BLOCK_GRANULE - 1));
+        if lo >= hi {
+            return true;
+        }
+        let mut state = self.state.lock_irqsave();
+
+        // unlink each overlapping block in turn and give back the parts
+        // outside the range (they never overlap it again)
+        loop {
+            let mut prev: *mut FreeBlock = core::ptr::null_mut();
+            let mut cur = state.head;
+            while !cur.is_null() && cur as usize + (*cur).size <= lo {
+                prev = cur;
+                cur  = (*cur).next;
+            }
+            if cur.is_null() || cur as usize >= hi {
+                return true;
+            }
+            let (b_start, b_end) = (cur as usize, cur as usize + (*cur).size);
+            if prev.is_null() {
+                state.head = (*cur).next;
+            } else {
+                (*prev).next = (*cur).next;
+            }
+            state.free -= b_end - b_start;
+            if lo > b_start {
+                Self::insert(&mut state, b_start, lo - b_start);
+            }
+            if b_end > hi {
+                Self::insert(&mut state, hi, b_end - hi);
+            }
+        }
+    }
+
+    /// Round a layout up to whole granules: returns (size, align)
+    fn block_layout(layout: Layout) -> Option<(usize, usize)> {
+        let align = layout.align().max(BLOCK_GRANULE);
+        let size  = align_up(layout.size().max(1), BLOCK_GRANULE)?;
+        Some((size, align))
+    }
+
+    /// Return `[addr, addr + size)` to the list, merging with any
+    /// free neighbour that touches it
+    unsafe fn insert(state: &mut FreeListState, addr: usize, size: usize) {
+        state.free += size;
+        let mut prev: *mut FreeBlock = core::ptr::null_mut();
+        let mut cur = state.head;
+        while !cur.is_null() && (cur as usize) < addr {
+            prev = cur;
+            cur  = (*cur).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next: cur });
+
+        // merge with the following block
+        if !cur.is_null() && addr + size == cur as usize {
+            (*block).size += (*cur).size;
+            (*block).next  = (*cur).next;
+        }
+
+        // link in, merging with the preceding block if adjacent
+        if prev.is_null() {
+            state.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next  = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Grow the block at `ptr` to `new_size` without moving it, by taking
+    /// what it needs from the free block right behind it; the rest of that
+    /// block stays free. False (nothing changed) if there is no such block
+    /// or it is too small.
+    ///
+    /// SAFETY: `ptr` must be live, allocated here with `layout`.
+    pub unsafe fn grow_in_place(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        let (old, _) = match Self::block_layout(layout) {
+            Some(l) => l,
+            None    => return false,
+        };
+        let new = match align_up(new_size.max(1), BLOCK_GRANULE) {
+            Some(n) if n > old => n,
+            Some(_) => return true,
+            None    => return false,
+        };
+        let end = ptr as usize + old;
+        let mut state = self.state.lock_irqsave();
+
+        let mut prev: *mut FreeBlock = core::ptr::null_mut();
+        let mut cur = state.head;
+        while !cur.is_null() && (cur as usize) < end {
+            prev = cur;
+            cur  = (*cur).next;
+        }
+        if cur as usize != end || old + (*cur).size < new {
+            return false;
+        }
+
+        let next = (*cur).next;
+        let rest = old + (*cur).size - new;
+        if prev.is_null() {
+            state.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        state.free -= (*cur).size;
+        if rest > 0 {
+            Self::insert(&mut state, ptr as usize + new, rest);
+        }
+        state.note_usage();
+        true
+    }
+
+    /// Shrink the block at `ptr` to `new_size` in place, freeing the tail
+    /// (merged with whatever free block follows). Always succeeds.
+    ///
+    /// SAFETY: `ptr` must be live, allocated here with `layout`.
+    pub unsafe fn shrink_in_place(&self, ptr: *mut u8, layout: Layout, new_size: usize) {
+        let (old, _) = match Self::block_layout(layout) {
+            Some(l) => l,
+            None    => return,
+        };
+        // rounding up never exceeds `old` since new_size <= layout.size()
+        let new = align_up(new_size.max(1), BLOCK_GRANULE).unwrap_or(old);
+        if new < old {
+            poison(ptr.add(new), old - new, POISON_FREE);
+            Self::insert(&mut self.state.lock_irqsave(), ptr as usize + new, old - new);
+        }
+    }
+
+    /// One allocation attempt without consulting the OOM handler
+    unsafe fn alloc_once(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = match Self::block_layout(min_aligned(layout)) {
+            Some(l) => l,
+            None    => return core::ptr::null_mut(),
+        };
+        let mut state = self.state.lock_irqsave();
+
+        // (prev, block, ptr, end) of the block chosen so far
+        let mut best: Option<(*mut FreeBlock, *mut FreeBlock, usize, usize)> = None;
+        let mut prev: *mut FreeBlock = core::ptr::null_mut();
+        let mut cur = state.head;
+        while !cur.is_null() {
+            let block_start = cur as usize;
+            let block_end   = block_start + (*cur).size;
+            let fit = align_up(block_start, align)
+                .and_then(|p| Some((p, p.checked_add(size)?)))
+                .filter(|&(_, end)| end <= block_end);
+
+            if let Some((ptr, end)) = fit {
+                if !best.is_some_and(|(_, b, _, _)| (*b).size <= (*cur).size) {
+                    best = Some((prev, cur, ptr, end));
+                }
+                // an exact fit cannot be beaten
+                if !BEST_FIT || block_end - block_start == size {
+                    break;
+                }
+            }
+
+            prev = cur;
+            cur  = (*cur).next;
+        }
+
+        let (prev, block, ptr, end) = match best {
+            Some(b) => b,
+            None    => return core::ptr::null_mut(),
+        };
+        // unlink the block, then hand back the alignment padding in front
+        // and the unused remainder behind the allocation
+        let (block_start, block_end) = (block as usize, block as usize + (*block).size);
+        let next = (*block).next;
+        if prev.is_null() {
+            state.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        state.free -= block_end - block_start;
+        if ptr > block_start {
+            Self::insert(&mut state, block_start, ptr - block_start);
+        }
+        if block_end > end {
+            Self::insert(&mut state, end, block_end - end);
+        }
+        state.note_usage();
+        ptr as *mut u8
+    }
+}
+
+unsafe impl GlobalAlloc for LinkedListAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        with_oom_retry(layout, || self.alloc_once(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // `alloc` succeeded with this layout, so rounding cannot fail here
+        if let Some((size, _)) = Self::block_layout(layout) {
+            track_free(ptr);
+            poison(ptr, layout.size(), POISON_FREE);
+            Self::insert(&mut self.state.lock_irqsave(), ptr as usize, size);
+        }
+    }
+
+    /// Resize in place when possible: shrinking frees the tail, growing
+    /// absorbs a free neighbour behind the block; otherwise fall back to
+    /// alloc + copy + dealloc
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let in_place = if new_size <= layout.size() {
+            self.shrink_in_place(ptr, layout, new_size);
+            true
+        } else {
+            self.grow_in_place(ptr, layout, new_size)
+        };
+        if in_place {
+            #[cfg(feature = "track-allocs")]
+            {
+                alloc_track::record_free(ptr);
+                alloc_track::record_alloc(ptr, new_size, alloc_track::caller_address(0));
+            }
+            return ptr;
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// Object sizes served by the slab allocator; each class is also its alignment
+const SLAB_SIZES: [usize; 5] = [16, 32, 64, 128, 256];
+
+/// Slabs are refilled one page at a time from the bump region
+const SLAB_PAGE: usize = 4096;
+
+/// Link stored in every free slab object
+struct SlabObject {
+    next: *mut SlabObject,
+}
+
+/// One free-list head per size class
+struct SlabState {
+    free: [*mut SlabObject; SLAB_SIZES.len()],
+}
+
+// SAFETY: the lists only point into heap memory owned by the allocator
+unsafe impl Send for SlabState {}
+
+/// Fixed-size slab allocator with O(1) alloc/dealloc for small objects;
+/// anything larger than the biggest class comes from the bump region
+pub struct SlabAllocator {
+    slabs: Spinlock<SlabState>,
+    bump:  BumpAllocator,
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        SlabAllocator {
+            slabs: Spinlock::new(SlabState { free: [core::ptr::null_mut(); SLAB_SIZES.len()] }),
+            bump:  BumpAllocator::new(),
+        }
+    }
+
+    pub unsafe fn init(&self, heap_start: usize, heap_end: usize) {
+        self.slabs.lock_irqsave().free = [core::ptr::null_mut(); SLAB_SIZES.len()];
+        self.bump.init(heap_start, heap_end);
+    }
+
+    /// Heap range passed to the last `init`, as (start, end)
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bump.bounds()
+    }
+
+    /// `alloc` as a `Result`: `Err` instead of null when the heap is
+    /// exhausted, so the caller can recover (the OOM handler still runs)
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        try_alloc_from(self, layout)
+    }
+
+    /// Usage of the bump region behind the slabs: objects sitting free
+    /// in a size class still count as used
+    pub fn stats(&self) -> HeapStats {
+        self.bump.stats()
+    }
+
+    /// Keep `[start, end)` out of future slab pages and large blocks;
+    /// see `BumpAllocator::reserve`. Pages already carved are unaffected.
+    ///
+    /// SAFETY: as for `BumpAllocator::reserve`.
+    pub unsafe fn reserve(&self, start: usize, end: usize) -> bool {
+        self.bump.reserve(start, end)
+    }
+
+    /// Index of the smallest class that satisfies both size and alignment
+    /// (at least `MIN_ALIGN`)
+    fn class_of(layout: Layout) -> Option<usize> {
+        let layout = min_aligned(layout);
+        SLAB_SIZES.iter().position(|&s| s >= layout.size() && s >= layout.align())
+    }
+
+    /// Carve a fresh page into objects of class `class` and push them;
+    /// false if the bump region is exhausted
+    unsafe fn refill(&self, state: &mut SlabState, class: usize) -> bool {
+        let page = self.bump.alloc_once(Layout::from_size_align_unchecked(SLAB_PAGE, SLAB_PAGE));
+        if page.is_null() {
+            return false;
+        }
+        let size = SLAB_SIZES[class];
+        for offset in (0..SLAB_PAGE).step_by(size).rev() {
+            let obj = page.add(offset) as *mut SlabObject;
+            obj.write(SlabObject { next: state.free[class] });
+            state.free[class] = obj;
+        }
+        true
+    }
+
+    /// One allocation attempt without consulting the OOM handler
+    unsafe fn alloc_once(&self, layout: Layout) -> *mut u8 {
+        let class = match Self::class_of(layout) {
+            Some(c) => c,
+            None    => return self.bump.alloc_once(layout),
+        };
+        let mut state = self.slabs.lock_irqsave();
+        if state.free[class].is_null() && !self.refill(&mut state, class) {
+            return core::ptr::null_mut();
+        }
+        let obj = state.free[class];
+        state.free[class] = (*obj).next;
+        obj as *mut u8
+    }
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        with_oom_retry(layout, || self.alloc_once(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match Self::class_of(layout) {
+            Some(class) => {
+                track_free(ptr);
+                poison(ptr, layout.size(), POISON_FREE);
+                let mut state = self.slabs.lock_irqsave();
+                let obj = ptr as *mut SlabObject;
+                obj.write(SlabObject { next: state.free[class] });
+                state.free[class] = obj;
+            }
+            None => self.bump.dealloc(ptr, layout),
+        }
+    }
+}
+
+/// Most CPUs `PerCpuHeap` keeps an arena for
+pub const MAX_CPUS: usize = 64;
+
+/// One CPU's private bump arena; `end == 0` until it is set up. Bumped
+/// with a CAS rather than a lock, which also makes it safe against an
+/// interrupt allocating on the same CPU.
+struct LocalArena {
+    start: AtomicUsize,
+    next:  AtomicUsize,
+    end:   AtomicUsize,
+}
+
+impl LocalArena {
+    const EMPTY: LocalArena = LocalArena {
+        start: AtomicUsize::new(0),
+        next:  AtomicUsize::new(0),
+        end:   AtomicUsize::new(0),
+    };
+
+    fn contains(&self, addr: usize) -> bool {
+        self.start.load(Ordering::Relaxed) <= addr && addr < self.end.load(Ordering::Acquire)
+    }
+
+    fn bump(&self, layout: Layout) -> *mut u8 {
+        let end = self.end.load(Ordering::Acquire);
+        let mut next = self.next.load(Ordering::Relaxed);
+        loop {
+            let fit = align_up(next, layout.align())
+                .and_then(|p| Some((p, p.checked_add(layout.size())?)))
+                .filter(|&(_, new_next)| new_next <= end);
+            let (ptr, new_next) = match fit {
+                Some(f) => f,
+                None    => return core::ptr::null_mut(),
+            };
+            match self.next.compare_exchange_weak(next, new_next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_)    => return ptr as *mut u8,
+                Err(cur) => next = cur,
+            }
+        }
+    }
+}
+
+/// Per-CPU front end for a shared allocator (`percpu-heap`): each CPU
+/// with an arena allocates from it without touching the shared lock, and
+/// only takes the shared allocator's path (lock, OOM policy) when its
+/// arena is missing or exhausted. Arena memory is bump-allocated: a free
+/// hands it back only if it was the arena's last allocation.
+pub struct PerCpuHeap<A> {
+    arenas: [LocalArena; MAX_CPUS],
+    shared: A,
+}
+
+impl<A> PerCpuHeap<A> {
+    pub const fn new(shared: A) -> Self {
+        PerCpuHeap { arenas: [LocalArena::EMPTY; MAX_CPUS], shared }
+    }
+
+    /// The shared allocator, e.g. to carve arenas out of the main heap
+    /// (allocating through `self` would use the caller's own arena)
+    pub fn shared(&self) -> &A {
+        &self.shared
+    }
+
+    /// Set up CPU `cpu_id`'s arena over `[start, end)`, typically during
+    /// its bringup. False if the id is out of range, the range is empty or
+    /// the CPU already has one.
+    ///
+    /// SAFETY: `[start, end)` must be unused memory owned by the caller
+    /// for good (e.g. carved from `shared()`).
+    pub unsafe fn init_local_arena(&self, cpu_id: usize, start: usize, end: usize) -> bool {
+        let arena = match self.arenas.get(cpu_id) {
+            Some(a) if end > start && a.end.load(Ordering::Relaxed) == 0 => a,
+            _ => return false,
+        };
+        arena.start.store(start, Ordering::Relaxed);
+        arena.next.store(start, Ordering::Relaxed);
+        arena.end.store(end, Ordering::Release);
+        true
+    }
+
+    fn local(&self) -> Option<&LocalArena> {
+        self.arenas.get(crate::cpu_id()).filter(|a| a.end.load(Ordering::Acquire) != 0)
+    }
+
+    /// The arena (any CPU's) that `ptr` came from
+    fn owner(&self, ptr: *mut u8) -> Option<&LocalArena> {
+        self.arenas.iter().find(|a| a.contains(ptr as usize))
+    }
+}
+
+impl<A: GlobalAlloc> PerCpuHeap<A> {
+    /// `alloc` as a `Result`, through the caller's arena like `alloc`
+    /// (the shared allocator's `try_alloc`, reached via `Deref`, would
+    /// skip it)
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        try_alloc_from(self, layout)
+    }
+}
+
+impl<A> core::ops::Deref for PerCpuHeap<A> {
+    type Target = A;
+    fn deref(&self) -> &A {
+        &self.shared
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for PerCpuHeap<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() != 0 {
+            if let Some(arena) = self.local() {
+                let ptr = arena.bump(min_aligned(layout));
+                if !ptr.is_null() {
+                    poison(ptr, layout.size(), POISON_ALLOC);
+                    #[cfg(feature = "track-allocs")]
+                    alloc_track::record_alloc(ptr, layout.size(), alloc_track::caller_address(0));
+                    return ptr;
+                }
+            }
+        }
+        // slow path: no arena, arena full, or a zero-size layout
+        self.shared.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match self.owner(ptr) {
+            Some(arena) => {
+                track_free(ptr);
+                let end = ptr as usize + layout.size();
+                // roll back only if nothing was allocated after it
+                if arena
+                    .next
+                    .compare_exchange(end, ptr as usize, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    poison(ptr, layout.size(), POISON_FREE);
+                }
+            }
+            None => self.shared.dealloc(ptr, layout),
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if self.owner(ptr).is_none() {
+            return self.shared.realloc(ptr, layout, new_size);
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// Size of the panic-time scratch arena behind `EMERGENCY_HEAP`
+pub const EMERGENCY_HEAP_SIZE: usize = 4096;
+
+/// Backing store for `EMERGENCY_HEAP`; in .bss, so it exists without any
+/// heap having been set up. Cache-line aligned, so no `MIN_ALIGN` loses
+/// bytes at its start.
+#[repr(align(64))]
+struct EmergencyArena(UnsafeCell<[u8; EMERGENCY_HEAP_SIZE]>);
+
+// SAFETY: only ever touched through `EMERGENCY_HEAP`'s lock
+unsafe impl Sync for EmergencyArena {}
+
+static EMERGENCY_ARENA: EmergencyArena = EmergencyArena(UnsafeCell::new([0; EMERGENCY_HEAP_SIZE]));
+
+/// Scratch memory for panic diagnostics (e.g. formatting a symbol name)
+/// that must not touch `ALLOCATOR`, which may be what failed. A bump arena
+/// over a fixed `EMERGENCY_HEAP_SIZE` bytes:
+///   • Allocates only while the panic handler runs (`panic::begin` has
+///     been called); null otherwise, and null once the arena is used up
+///   • `dealloc` is a no-op; `panic::begin` resets the whole arena, so
+///     each panic starts with all of it free
+///
+/// Use it directly (`EMERGENCY_HEAP.alloc(layout)`), not as a global
+/// allocator.
+pub struct EmergencyHeap {
+    bump: BumpAllocator,
+}
+
+impl EmergencyHeap {
+    const fn new() -> Self {
+        EmergencyHeap { bump: BumpAllocator::new() }
+    }
+
+    /// Make the whole arena free again.
+    ///
+    /// SAFETY: nothing allocated from it may still be in use (true at
+    /// the start of a panic: the previous one never returned).
+    pub unsafe fn reset(&self) {
+        let start = EMERGENCY_ARENA.0.get() as usize;
+        self.bump.init(start, start + EMERGENCY_HEAP_SIZE);
+    }
+
+    /// Bytes handed out since the last reset
+    pub fn used_bytes(&self) -> usize {
+        self.bump.used_bytes()
+    }
+}
+
+unsafe impl GlobalAlloc for EmergencyHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // no OOM handler, no tracking and no poisoning: none of it is
+        // safe to run mid-panic
+        if !panic::in_progress() {
+            return core::ptr::null_mut();
+        }
+        self.bump.alloc_once(layout)
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+/// The panic path's own allocator; see `EmergencyHeap`
+pub static EMERGENCY_HEAP: EmergencyHeap = EmergencyHeap::new();
+
+// The allocator features are mutually exclusive
+#[cfg(any(
+    all(feature = "alloc-bump", feature = "alloc-freelist"),
+    all(feature = "alloc-bump", feature = "alloc-slab"),
+    all(feature = "alloc-freelist", feature = "alloc-slab"),
+))]
+compile_error!("enable at most one of the alloc-bump, alloc-freelist and alloc-slab features");
+
+/// The shared heap, chosen at build time: `BumpAllocator` with
+/// `alloc-bump` (also the default when no allocator feature is set), the
+/// reclaiming `LinkedListAllocator` with `alloc-freelist`, or
+/// `SlabAllocator` with `alloc-slab`. All three are set up by the same
+/// `ALLOCATOR.init(heap_start, heap_end)` call.
+#[cfg(not(any(feature = "alloc-freelist", feature = "alloc-slab")))]
+pub type SharedHeap = BumpAllocator;
+#[cfg(all(feature = "alloc-freelist", not(feature = "alloc-bump")))]
+pub type SharedHeap = LinkedListAllocator;
+#[cfg(all(feature = "alloc-slab", not(any(feature = "alloc-bump", feature = "alloc-freelist"))))]
+pub type SharedHeap = SlabAllocator;
+
+// `no-heap` has no global allocator for the arenas to sit in front of
+#[cfg(all(feature = "no-heap", feature = "percpu-heap"))]
+compile_error!("the no-heap and percpu-heap features are mutually exclusive");
+
+/// The crate's global allocator: the `SharedHeap`, fronted by per-CPU
+/// arenas under `percpu-heap`. Absent under `no-heap`.
+#[cfg(not(any(feature = "percpu-heap", feature = "no-heap")))]
+#[global_allocator]
+pub static ALLOCATOR: SharedHeap = SharedHeap::new();
+
+#[cfg(all(feature = "percpu-heap", not(feature = "no-heap")))]
+#[global_allocator]
+pub static ALLOCATOR: PerCpuHeap<SharedHeap> = PerCpuHeap::new(SharedHeap::new());
+
+/// Give CPU `cpu_id` (as returned by `crate::cpu_id()`) a private arena;
+/// see `PerCpuHeap::init_local_arena`.
+///
+/// SAFETY: as for `PerCpuHeap::init_local_arena`.
+#[cfg(all(feature = "percpu-heap", not(feature = "no-heap")))]
+pub unsafe fn init_local_arena(cpu_id: usize, start: usize, end: usize) -> bool {
+    ALLOCATOR.init_local_arena(cpu_id, start, end)
+}
+
+/// The `alloc_error` message: the failed layout, whether an OOM handler
+/// gave up on it, and the heap's stats at that point
+#[cfg(not(feature = "no-heap"))]
+struct OomReport {
+    layout:  Layout,
+    aborted: bool,
+    stats:   HeapStats,
+}
+
+#[cfg(not(feature = "no-heap"))]
+impl fmt::Display for OomReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "allocation error: {:?}", self.layout)?;
+        if self.aborted {
+            f.write_str(" (OOM handler aborted)")?;
+        }
+        write!(f, "; heap {}", self.stats)
+    }
+}
+
+/// Called on allocation failure (out of memory). Any installed OOM
+/// handler has already declined to `Retry` inside the allocator. The
+/// report includes the heap's stats so it shows how close the heap was;
+/// they are read under the allocator lock (free again by now) and only
+/// formatted, so reporting allocates nothing.
+#[cfg(not(feature = "no-heap"))]
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    let report = OomReport { layout, aborted: oom_handler().is_some(), stats: ALLOCATOR.stats() };
+    panic!("{}", report);
+}
+
+/// `posix_memalign`-style allocation from `ALLOCATOR` without building a
+/// `Layout` by hand. Returns null if `align` is not a power of two, `size`
+/// is zero or overflows when rounded to `align`, or the heap is exhausted.
+#[cfg(not(feature = "no-heap"))]
+pub fn alloc_aligned(size: usize, align: usize) -> *mut u8 {
+    match Layout::from_size_align(size, align) {
+        Ok(layout) if size != 0 => unsafe { ALLOCATOR.alloc(layout) },
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Free a block from `alloc_aligned`; null `ptr` and invalid
+/// `size`/`align` pairs are ignored.
+///
+/// SAFETY: a non-null `ptr` must come from `alloc_aligned(size, align)`
+/// with the same `size` and `align`, and not have been freed yet.
+#[cfg(not(feature = "no-heap"))]
+pub unsafe fn free_aligned(ptr: *mut u8, size: usize, align: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    if let Ok(layout) = Layout::from_size_align(size, align) {
+        ALLOCATOR.dealloc(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_core::FmtBuf;
+
+    const ARENA_SIZE: usize = 4 * SLAB_PAGE;
+
+    /// Scratch RAM the tests build their own heaps over, so `ALLOCATOR`
+    /// is left alone
+    #[repr(align(4096))]
+    struct Arena(UnsafeCell<[u8; ARENA_SIZE]>);
+
+    // SAFETY: tests run one at a time on the boot CPU
+    unsafe impl Sync for Arena {}
+
+    static ARENA: Arena = Arena(UnsafeCell::new([0; ARENA_SIZE]));
+
+    /// The scratch arena as (start, end)
+    fn arena() -> (usize, usize) {
+        let start = ARENA.0.get() as usize;
+        (start, start + ARENA_SIZE)
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    /// A bump allocator over the whole arena
+    fn bump() -> BumpAllocator {
+        let heap = BumpAllocator::new();
+        let (start, end) = arena();
+        unsafe { heap.init(start, end) };
+        heap
+    }
+
+    /// A free-list allocator over the whole arena
+    fn freelist() -> LinkedListAllocator {
+        let heap = LinkedListAllocator::new();
+        let (start, end) = arena();
+        unsafe { heap.init(start, end) };
+        heap
+    }
+
+    /// A slab allocator over the whole arena
+    fn slab() -> SlabAllocator {
+        let heap = SlabAllocator::new();
+        let (start, end) = arena();
+        unsafe { heap.init(start, end) };
+        heap
+    }
+
+    #[test_case]
+    fn bump_allocations_are_disjoint_and_ascending() {
+        let heap = bump();
+        let (start, end) = arena();
+        let mut last_end = start;
+        for size in [8, 24, 64, 200] {
+            let ptr = unsafe { heap.alloc(layout(size, 8)) } as usize;
+            assert!(ptr >= last_end && ptr + size <= end);
+            last_end = ptr + size;
+        }
+    }
+
+    #[test_case]
+    fn bump_lock_is_free_after_each_call() {
+        let heap = bump();
+        let ptr = unsafe { heap.alloc(layout(16, 8)) };
+        assert!(!ptr.is_null());
+        assert!(heap.state.try_lock().is_some());
+        unsafe { heap.dealloc(ptr, layout(16, 8)) };
+        assert!(heap.state.try_lock().is_some());
+    }
+
+    #[test_case]
+    fn align_up_rounds_to_the_next_multiple() {
+        assert_eq!(align_up(0, 8), Some(0));
+        assert_eq!(align_up(1, 8), Some(8));
+        assert_eq!(align_up(8, 8), Some(8));
+        assert_eq!(align_up(0x1001, 0x1000), Some(0x2000));
+        assert_eq!(align_up(5, 1), Some(5));
+    }
+
+    #[test_case]
+    fn align_up_rejects_overflow_and_bad_alignments() {
+        assert_eq!(align_up(usize::MAX, 8), None);
+        assert_eq!(align_up(usize::MAX - 6, 8), None);
+        assert_eq!(align_up(16, 0), None);
+        assert_eq!(align_up(16, 12), None);
+    }
+
+    #[test_case]
+    fn bump_honours_alignment() {
+        let heap = bump();
+        unsafe {
+            heap.alloc(layout(1, 1));
+            for align in [2, 16, 256, 4096] {
+                let ptr = heap.alloc(layout(1, align)) as usize;
+                assert!(ptr != 0 && ptr % align == 0);
+            }
+        }
+    }
+
+    #[test_case]
+    fn bump_alignment_past_the_heap_fails_without_moving() {
+        let heap = bump();
+        unsafe { heap.alloc(layout(8, 8)) };
+        let used = heap.used_bytes();
+        // aligning the cursor to 1 << 62 leaves the heap (or overflows)
+        assert!(unsafe { heap.alloc(layout(8, 1 << 62)) }.is_null());
+        assert_eq!(heap.used_bytes(), used);
+    }
+
+    #[test_case]
+    fn reset_hands_out_the_same_memory_again() {
+        let heap = bump();
+        let first = unsafe { heap.alloc(layout(64, 8)) };
+        unsafe { heap.alloc(layout(128, 8)) };
+        unsafe { heap.reset() };
+        assert_eq!(heap.used_bytes(), 0);
+        assert_eq!(heap.padding_bytes(), 0);
+        assert_eq!(unsafe { heap.alloc(layout(64, 8)) }, first);
+    }
+
+    #[test_case]
+    fn high_watermark_survives_reset() {
+        let heap = bump();
+        unsafe {
+            heap.alloc(layout(512, 8));
+            let peak = heap.high_watermark();
+            assert!(peak >= 512);
+            heap.reset();
+            heap.alloc(layout(16, 8));
+            assert_eq!(heap.high_watermark(), peak);
+        }
+    }
+
+    #[test_case]
+    fn bump_stats_track_used_free_and_peak() {
+        let heap = bump();
+        let empty = HeapStats { used_bytes: 0, free_bytes: ARENA_SIZE, high_watermark: 0 };
+        assert_eq!(heap.stats(), empty);
+        unsafe { heap.alloc(layout(256, 64)) };
+        let stats = heap.stats();
+        assert_eq!(stats.used_bytes, 256);
+        assert_eq!(stats.used_bytes + stats.free_bytes, ARENA_SIZE);
+        assert_eq!(stats.high_watermark, 256);
+        unsafe { heap.reset() };
+        assert_eq!(heap.stats(), HeapStats { high_watermark: 256, ..empty });
+    }
+
+    #[test_case]
+    fn heap_stats_display_names_every_field() {
+        let stats = HeapStats { used_bytes: 1, free_bytes: 2, high_watermark: 3 };
+        let mut w = FmtBuf::<64>::new();
+        fmt::write(&mut w, format_args!("{}", stats)).unwrap();
+        assert_eq!(w.as_str(), "used_bytes=1 free_bytes=2 high_watermark=3");
+    }
+
+    #[test_case]
+    fn freelist_reuses_a_freed_block() {
+        let heap = freelist();
+        unsafe {
+            let a = heap.alloc(layout(64, 8));
+            heap.alloc(layout(64, 8));
+            heap.dealloc(a, layout(64, 8));
+            assert_eq!(heap.alloc(layout(64, 8)), a);
+        }
+    }
+
+    #[test_case]
+    fn freelist_coalesces_neighbours() {
+        let heap = freelist();
+        let l = layout(1024, 8);
+        unsafe {
+            let blocks = [heap.alloc(l), heap.alloc(l), heap.alloc(l)];
+            // the middle block goes last, so it has to merge both ways
+            heap.dealloc(blocks[0], l);
+            heap.dealloc(blocks[2], l);
+            heap.dealloc(blocks[1], l);
+            assert_eq!(heap.stats().free_bytes, ARENA_SIZE);
+            assert_eq!(heap.alloc(layout(ARENA_SIZE, 8)) as usize, arena().0);
+        }
+    }
+
+    #[cfg(not(feature = "best-fit"))]
+    #[test_case]
+    fn freelist_first_fit_takes_the_lowest_block() {
+        let heap = freelist();
+        unsafe {
+            let big   = heap.alloc(layout(256, 64));
+            heap.alloc(layout(64, 64));
+            let small = heap.alloc(layout(64, 64));
+            heap.alloc(layout(64, 64));
+            heap.dealloc(big, layout(256, 64));
+            heap.dealloc(small, layout(64, 64));
+            assert_eq!(heap.alloc(layout(64, 64)), big);
+        }
+    }
+
+    #[cfg(feature = "best-fit")]
+    #[test_case]
+    fn freelist_best_fit_keeps_the_large_block_whole() {
+        let heap = freelist();
+        unsafe {
+            let big   = heap.alloc(layout(256, 64));
+            heap.alloc(layout(64, 64));
+            let small = heap.alloc(layout(64, 64));
+            heap.alloc(layout(64, 64));
+            // use up the rest, so only the two holes are left
+            heap.alloc(layout(heap.stats().free_bytes, 8));
+            heap.dealloc(big, layout(256, 64));
+            heap.dealloc(small, layout(64, 64));
+            // first-fit would split `big` here and fail the 256 below
+            assert_eq!(heap.alloc(layout(64, 64)), small);
+            assert_eq!(heap.alloc(layout(256, 64)), big);
+        }
+    }
+
+    #[test_case]
+    fn freelist_honours_alignment() {
+        let heap = freelist();
+        unsafe {
+            heap.alloc(layout(24, 8));
+            for align in [32, 512, 4096] {
+                let ptr = heap.alloc(layout(40, align)) as usize;
+                assert!(ptr != 0 && ptr % align == 0);
+            }
+            // the padding skipped in front stays allocatable
+            let ptr = heap.alloc(layout(16, 8)) as usize;
+            assert!(ptr < arena().0 + 512);
+        }
+    }
+
+    #[test_case]
+    fn slab_picks_the_smallest_class_that_fits() {
+        let class = |size, align| {
+            SlabAllocator::class_of(layout(size, align)).map(|c| SLAB_SIZES[c])
+        };
+        assert_eq!(class(1, 1),    Some(16.max(MIN_ALIGN)));
+        assert_eq!(class(16, 8),   Some(16.max(MIN_ALIGN)));
+        assert_eq!(class(17, 8),   Some(32.max(MIN_ALIGN)));
+        assert_eq!(class(8, 128),  Some(128));
+        assert_eq!(class(256, 16), Some(256));
+        assert_eq!(class(257, 8),  None);
+        assert_eq!(class(8, 512),  None);
+    }
+
+    #[test_case]
+    fn slab_reuses_the_last_freed_object() {
+        let heap = slab();
+        let l = layout(48, 8);
+        unsafe {
+            let a = heap.alloc(l);
+            let b = heap.alloc(l);
+            assert_eq!(b as usize - a as usize, 64.max(MIN_ALIGN));
+            heap.dealloc(a, l);
+            assert_eq!(heap.alloc(l), a);
+        }
+    }
+
+    #[test_case]
+    fn slab_carves_a_page_per_class_and_sends_large_blocks_to_bump() {
+        let heap = slab();
+        unsafe {
+            heap.alloc(layout(16, 16));
+            heap.alloc(layout(16, 16));
+            assert_eq!(heap.stats().used_bytes, SLAB_PAGE);
+            heap.alloc(layout(128, 128));
+            assert_eq!(heap.stats().used_bytes, 2 * SLAB_PAGE);
+            let big = heap.alloc(layout(1000, 8));
+            assert!(!big.is_null());
+            assert_eq!(heap.stats().used_bytes, 2 * SLAB_PAGE + 1000);
+        }
+    }
+
+    #[test_case]
+    fn bounds_report_the_init_range() {
+        let (start, end) = arena();
+        assert_eq!(bump().bounds(), (start, end));
+        assert_eq!(freelist().bounds(), (start, end));
+        assert_eq!(slab().bounds(), (start, end));
+
+        // the bump allocator trims a ragged range to whole words
+        let heap = BumpAllocator::new();
+        unsafe { heap.init(start + 3, end - 5) };
+        assert_eq!(heap.bounds(), (start + HEAP_ALIGN, end - HEAP_ALIGN));
+        assert_eq!(heap.alignment_loss(), 2 * HEAP_ALIGN - 8);
+    }
+
+    #[test_case]
+    fn reinit_moves_to_the_new_range() {
+        let (start, end) = arena();
+        let heap = bump();
+        unsafe {
+            heap.alloc(layout(64, 8));
+            heap.init(start + SLAB_PAGE, end);
+        }
+        assert_eq!(heap.bounds(), (start + SLAB_PAGE, end));
+        assert_eq!(heap.used_bytes(), 0);
+        assert_eq!(unsafe { heap.alloc(layout(8, 8)) } as usize, start + SLAB_PAGE);
+    }
+
+    #[test_case]
+    fn zero_size_layouts_get_a_dangling_aligned_pointer() {
+        let heap = bump();
+        let ptr = unsafe { heap.alloc_once(layout(0, 64)) };
+        assert_eq!(ptr as usize, 64.max(MIN_ALIGN));
+        assert_eq!(heap.used_bytes(), 0);
+    }
+
+    #[test_case]
+    fn oversized_layouts_fail_without_moving_the_cursor() {
+        let heap = bump();
+        unsafe { heap.alloc(layout(64, 64)) };
+        let used = heap.used_bytes();
+        assert!(unsafe { heap.alloc(layout(ARENA_SIZE + 1, 8)) }.is_null());
+        assert_eq!(heap.used_bytes(), used);
+        // the rest of the heap is still there
+        assert!(!unsafe { heap.alloc(layout(ARENA_SIZE - used, 1)) }.is_null());
+    }
+
+    #[test_case]
+    fn bump_realloc_grows_the_last_block_in_place() {
+        let heap = bump();
+        unsafe {
+            let ptr = heap.alloc(layout(64, 8));
+            ptr.write_bytes(0x5a, 64);
+            assert_eq!(heap.realloc(ptr, layout(64, 8), 256), ptr);
+            assert_eq!(heap.used_bytes(), 256);
+            assert_eq!(*ptr.add(63), 0x5a);
+        }
+    }
+
+    #[test_case]
+    fn bump_realloc_of_an_older_block_copies_it() {
+        let heap = bump();
+        unsafe {
+            let old = heap.alloc(layout(64, 8));
+            old.write_bytes(0x5a, 64);
+            heap.alloc(layout(64, 8));
+            let new = heap.realloc(old, layout(64, 8), 128);
+            assert!(!new.is_null() && new != old);
+            assert_eq!(*new, 0x5a);
+            assert_eq!(*new.add(63), 0x5a);
+        }
+    }
+
+    #[test_case]
+    fn bump_realloc_past_the_end_fails_and_keeps_the_block() {
+        let heap = bump();
+        unsafe {
+            let ptr = heap.alloc(layout(64, 8));
+            assert!(heap.realloc(ptr, layout(64, 8), ARENA_SIZE + 1).is_null());
+            // the failed grow left the block alone
+            assert_eq!(heap.used_bytes(), 64);
+        }
+    }
+
+    #[test_case]
+    fn poison_writes_only_in_poison_debug_builds() {
+        let mut buf = [0u8; 8];
+        unsafe { poison(buf.as_mut_ptr(), buf.len(), POISON_FREE) };
+        let expected = if cfg!(all(feature = "poison", debug_assertions)) { POISON_FREE } else { 0 };
+        assert_eq!(buf, [expected; 8]);
+    }
+
+    #[cfg(all(feature = "poison", debug_assertions))]
+    #[test_case]
+    fn fresh_and_freed_blocks_are_poisoned() {
+        let heap = freelist();
+        let l = layout(64, 8);
+        unsafe {
+            let ptr = heap.alloc(l);
+            assert!((0..64).all(|i| *ptr.add(i) == POISON_ALLOC));
+            heap.alloc(l); // keeps the freed block from merging away
+            heap.dealloc(ptr, l);
+            // past the free-list header written over its start
+            let header = core::mem::size_of::<FreeBlock>();
+            assert!((header..64).all(|i| *ptr.add(i) == POISON_FREE));
+        }
+    }
+
+    #[test_case]
+    fn an_exhausted_heap_still_gets_a_panic_report_out() {
+        let heap = bump();
+        while !unsafe { heap.alloc(layout(64, 8)) }.is_null() {}
+        assert_eq!(heap.free_bytes(), 0);
+        panic::begin();
+        let mut w = FmtBuf::<64>::new();
+        panic::report(&mut w, "out of memory");
+        panic::end();
+        assert_eq!(w.as_str(), "PANIC: out of memory\n");
+    }
+
+    #[test_case]
+    fn allocating_after_begin_trips_the_debug_check() {
+        assert_eq!(check_not_in_panic(), Ok(()));
+        panic::begin();
+        let err = check_not_in_panic();
+        panic::end();
+        assert_eq!(err, Err(AllocInPanic));
+        let mut w = FmtBuf::<64>::new();
+        fmt::write(&mut w, format_args!("{}", AllocInPanic)).unwrap();
+        assert_eq!(w.as_str(), "heap allocation in the panic handler");
+    }
+
+    #[test_case]
+    fn bump_counts_alignment_padding() {
+        let heap = bump();
+        unsafe {
+            heap.alloc(layout(1, 1));
+            heap.alloc(layout(8, 64));
+        }
+        // the arena is page-aligned, so the second block skipped 63 bytes
+        assert_eq!(heap.padding_bytes(), 63);
+        assert_eq!(heap.used_bytes(), 1 + 63 + 8);
+        unsafe { heap.reset() };
+        assert_eq!(heap.padding_bytes(), 0);
+    }
+
+    #[test_case]
+    fn bump_dealloc_reclaims_only_the_latest_block() {
+        let heap = bump();
+        let (a, b) = unsafe { (heap.alloc(layout(64, 8)), heap.alloc(layout(32, 8))) };
+        let used = heap.used_bytes();
+        // not the latest: stays used
+        unsafe { heap.dealloc(a, layout(64, 8)) };
+        assert_eq!(heap.used_bytes(), used);
+        unsafe { heap.dealloc(b, layout(32, 8)) };
+        assert_eq!(heap.used_bytes(), used - 32);
+        assert_eq!(unsafe { heap.alloc(layout(32, 8)) }, b);
+        // freeing in LIFO order empties the heap
+        unsafe {
+            heap.dealloc(b, layout(32, 8));
+            heap.dealloc(a, layout(64, 8));
+        }
+        assert_eq!(heap.used_bytes(), 0);
+    }
+
+    #[test_case]
+    #[cfg(not(feature = "no-heap"))]
+    fn alloc_aligned_rejects_bad_requests() {
+        assert!(alloc_aligned(16, 3).is_null());
+        assert!(alloc_aligned(16, 0).is_null());
+        assert!(alloc_aligned(0, 8).is_null());
+        assert!(alloc_aligned(usize::MAX - 2, 8).is_null());
+        // freeing null is a no-op
+        unsafe { free_aligned(core::ptr::null_mut(), 16, 8) };
+    }
+
+    #[test_case]
+    #[cfg(not(feature = "no-heap"))]
+    fn alloc_aligned_honours_the_alignment() {
+        let ptr = alloc_aligned(100, 256);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % 256, 0);
+        unsafe { free_aligned(ptr, 100, 256) };
+    }
+
+    #[test_case]
+    fn freelist_grows_into_a_free_neighbour() {
+        let heap = freelist();
+        unsafe {
+            let a = heap.alloc(layout(64, 64));
+            let b = heap.alloc(layout(64, 64));
+            // `b` sits right behind `a`
+            assert!(!heap.grow_in_place(a, layout(64, 64), 128));
+            heap.dealloc(b, layout(64, 64));
+            assert!(heap.grow_in_place(a, layout(64, 64), 128));
+            // what the grow left over is still free, right behind `a`
+            assert_eq!(heap.alloc(layout(64, 64)) as usize, a as usize + 128);
+            assert_eq!(heap.stats().free_bytes, ARENA_SIZE - 192);
+        }
+    }
+
+    #[test_case]
+    fn freelist_shrink_frees_the_tail() {
+        let heap = freelist();
+        unsafe {
+            let a = heap.alloc(layout(256, 64));
+            heap.shrink_in_place(a, layout(256, 64), 64);
+            assert_eq!(heap.stats().free_bytes, ARENA_SIZE - 64);
+            assert_eq!(heap.alloc(layout(64, 64)) as usize, a as usize + 64);
+        }
+    }
+
+    #[test_case]
+    fn freelist_realloc_stays_in_place_when_it_can() {
+        let heap = freelist();
+        unsafe {
+            let a = heap.alloc(layout(128, 64));
+            a.write_bytes(0x5a, 128);
+            assert_eq!(heap.realloc(a, layout(128, 64), 64), a);
+            assert_eq!(heap.realloc(a, layout(64, 64), 512), a);
+            assert_eq!(*a.add(63), 0x5a);
+            // blocked behind: moves, keeping the contents
+            heap.alloc(layout(64, 64));
+            let moved = heap.realloc(a, layout(512, 64), 1024);
+            assert!(!moved.is_null() && moved != a);
+            assert_eq!(*moved.add(63), 0x5a);
+        }
+    }
+
+    /// Allocate 256-byte blocks until `heap` runs out; true if none of
+    /// them touches `[lo, hi)`
+    unsafe fn never_hands_out(heap: &dyn GlobalAlloc, lo: usize, hi: usize) -> bool {
+        let mut clear = true;
+        loop {
+            let ptr = heap.alloc(layout(256, 8)) as usize;
+            if ptr == 0 {
+                return clear;
+            }
+            clear &= ptr + 256 <= lo || ptr >= hi;
+        }
+    }
+
+    #[test_case]
+    fn bump_reserve_splits_the_region() {
+        let heap = bump();
+        let (start, _) = arena();
+        let (lo, hi) = (start + 0x1000, start + 0x2000);
+        unsafe {
+            assert!(heap.reserve(lo, hi));
+            assert_eq!(heap.alloc(layout(0x1000, 0x1000)) as usize, start);
+            assert_eq!(heap.alloc(layout(0x1000, 0x1000)) as usize, hi);
+            assert!(never_hands_out(&heap, lo, hi));
+        }
+    }
+
+    #[test_case]
+    fn bump_reserve_fails_without_a_free_region_slot() {
+        let heap = bump();
+        let (start, _) = arena();
+        unsafe {
+            // each hole splits off one more region
+            for i in 1..MAX_HEAP_REGIONS {
+                assert!(heap.reserve(start + i * 256, start + i * 256 + 16));
+            }
+            let (lo, hi) = (start + 0x2000, start + 0x2010);
+            assert!(!heap.reserve(lo, hi));
+            // the part above was dropped, so the range still stays out
+            assert!(never_hands_out(&heap, lo, hi));
+        }
+    }
+
+    #[test_case]
+    fn freelist_reserve_takes_whole_granules() {
+        let heap = freelist();
+        let (start, _) = arena();
+        let (lo, hi) = (start + 0x1000, start + 0x2000);
+        unsafe {
+            assert!(heap.reserve(lo + 3, hi - 3));
+            assert_eq!(heap.stats().free_bytes, ARENA_SIZE - 0x1000);
+            assert!(never_hands_out(&heap, lo, hi));
+        }
+    }
+
+    #[test_case]
+    fn empty_or_inverted_heaps_are_refused() {
+        let err = EmptyHeap { start: 0x2000, end: 0x1000 };
+        assert_eq!(check_heap_range(0x2000, 0x1000), Err(err));
+        assert_eq!(check_heap_range(0x1000, 0x1000), Err(EmptyHeap { start: 0x1000, end: 0x1000 }));
+        assert_eq!(check_heap_range(0x1000, 0x1001), Ok(()));
+        // the panic names both addresses
+        let mut w = FmtBuf::<64>::new();
+        fmt::write(&mut w, format_args!("{}", err)).unwrap();
+        assert_eq!(w.as_str(), "heap: empty or inverted range [0x2000, 0x1000)");
+    }
+
+    #[test_case]
+    fn emergency_heap_is_closed_outside_a_panic() {
+        let used = EMERGENCY_HEAP.used_bytes();
+        assert!(unsafe { EMERGENCY_HEAP.alloc(layout(16, 8)) }.is_null());
+        assert_eq!(EMERGENCY_HEAP.used_bytes(), used);
+    }
+
+    #[test_case]
+    fn emergency_arena_is_fixed_and_reset_whole() {
+        // a second instance over the same arena, which nothing uses
+        // outside a panic; its `alloc_once` skips the panic check
+        let heap = EmergencyHeap::new();
+        let quarter = layout(EMERGENCY_HEAP_SIZE / 4, 16);
+        unsafe {
+            heap.reset();
+            for _ in 0..4 {
+                assert!(!heap.bump.alloc_once(quarter).is_null());
+            }
+            assert!(heap.bump.alloc_once(layout(1, 1)).is_null());
+            assert_eq!(heap.used_bytes(), EMERGENCY_HEAP_SIZE);
+            heap.reset();
+        }
+        assert_eq!(heap.used_bytes(), 0);
+    }
+
+    #[test_case]
+    fn bump_alloc_zeroed_clears_dirty_ram() {
+        unsafe { (arena().0 as *mut u8).write_bytes(0xaa, ARENA_SIZE) };
+        let heap = bump();
+        unsafe {
+            let ptr = heap.alloc_zeroed(layout(64, 8));
+            assert!(core::slice::from_raw_parts(ptr, 64).iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test_case]
+    fn mark_zeroed_skips_only_untouched_ram() {
+        unsafe { (arena().0 as *mut u8).write_bytes(0, ARENA_SIZE) };
+        let heap = bump();
+        unsafe {
+            // unknown until marked
+            assert_eq!(heap.alloc_once_dirty(layout(64, 64)).1, 64);
+            heap.reset();
+            heap.mark_zeroed();
+            let (ptr, dirty) = heap.alloc_once_dirty(layout(64, 64));
+            assert_eq!(dirty, 0);
+            // written, then reclaimed: no longer known to be zero
+            ptr.write_bytes(0xff, 64);
+            heap.dealloc(ptr, layout(64, 64));
+            let again = heap.alloc_zeroed(layout(64, 64));
+            assert_eq!(again, ptr);
+            assert!(core::slice::from_raw_parts(again, 64).iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test_case]
+    #[cfg(not(feature = "no-heap"))]
+    fn oom_report_carries_the_heap_stats() {
+        let stats = HeapStats { used_bytes: 4000, free_bytes: 96, high_watermark: 4090 };
+        let mut report = OomReport { layout: layout(128, 8), aborted: false, stats };
+        let mut w = FmtBuf::<160>::new();
+        fmt::write(&mut w, format_args!("{}", report)).unwrap();
+        assert!(w.as_str().starts_with("allocation error: Layout {"));
+        assert!(w.as_str().ends_with("; heap used_bytes=4000 free_bytes=96 high_watermark=4090"));
+
+        report.aborted = true;
+        let mut w = FmtBuf::<160>::new();
+        fmt::write(&mut w, format_args!("{}", report)).unwrap();
+        assert!(w.as_str().contains(" (OOM handler aborted); heap used_bytes=4000 "));
+    }
+
+    #[test_case]
+    fn min_aligned_only_raises_the_alignment() {
+        let raised = min_aligned(layout(24, 8));
+        assert_eq!((raised.size(), raised.align()), (24, MIN_ALIGN.max(8)));
+        let big = min_aligned(layout(24, 4096));
+        assert_eq!(big.align(), 4096);
+    }
+
+    #[cfg(feature = "min-align-64")]
+    #[test_case]
+    fn every_heap_hands_out_cache_lines() {
+        let bump = bump();
+        let ptrs = unsafe { [bump.alloc(layout(8, 8)), bump.alloc(layout(8, 8))] };
+        let freelist = freelist();
+        let more = unsafe { [freelist.alloc(layout(8, 8)), freelist.alloc(layout(1, 1))] };
+        for ptr in ptrs.into_iter().chain(more) {
+            assert!(!ptr.is_null() && ptr as usize % 64 == 0);
+        }
+    }
+
+    #[test_case]
+    fn word_alignment_can_leave_nothing() {
+        let (start, _) = arena();
+        let heap = bump();
+        assert_eq!(heap.alignment_loss(), 0);
+        // no whole word between start + 1 and start + 7
+        unsafe { heap.init(start + 1, start + 7) };
+        assert_eq!(heap.alignment_loss(), 6);
+        assert_eq!(heap.free_bytes(), 0);
+        assert!(unsafe { heap.alloc(layout(1, 1)) }.is_null());
+    }
+
+    #[test_case]
+    fn try_alloc_reports_exhaustion_as_err() {
+        // one heap at a time: both span the whole arena, and under `poison`
+        // the bump fill would scribble over the free list's block headers
+        let chunk = layout(ARENA_SIZE / 4, 8);
+        let bump = bump();
+        for _ in 0..4 {
+            assert!(bump.try_alloc(chunk).is_ok());
+        }
+        assert_eq!(bump.try_alloc(layout(8, 8)), Err(AllocError));
+        // zero-size requests never fail, even on a full heap
+        let empty = bump.try_alloc(layout(0, 16)).unwrap();
+        assert_eq!(empty.as_ptr() as usize, 16);
+
+        let freelist = freelist();
+        for _ in 0..4 {
+            assert!(freelist.try_alloc(chunk).is_ok());
+        }
+        assert_eq!(freelist.try_alloc(layout(8, 8)), Err(AllocError));
+    }
+
+    /// Heap the OOM handler below allocates from while the allocation that
+    /// failed on it is still in progress
+    static REENTRANT: BumpAllocator = BumpAllocator::new();
+    /// Block the handler got, or 0
+    static NESTED: AtomicUsize = AtomicUsize::new(0);
+
+    fn allocate_again(_: Layout) -> OomAction {
+        // the failed call must have released the state lock, or this
+        // nested allocation would spin on it forever
+        assert!(REENTRANT.state.try_lock().is_some());
+        let ptr = unsafe { REENTRANT.alloc(layout(64, 8)) };
+        NESTED.store(ptr as usize, Ordering::Relaxed);
+        OomAction::Abort
+    }
+
+    #[test_case]
+    fn allocating_from_the_oom_handler_does_not_deadlock() {
+        let (start, end) = arena();
+        unsafe { REENTRANT.init(start, end) };
+        let handler = allocate_again as fn(Layout) -> OomAction;
+        let saved = OOM_HANDLER.swap(handler as usize, Ordering::AcqRel);
+        let failed = unsafe { REENTRANT.alloc(layout(ARENA_SIZE + 1, 8)) };
+        OOM_HANDLER.store(saved, Ordering::Release);
+        assert!(failed.is_null());
+        let nested = NESTED.load(Ordering::Relaxed);
+        assert!(nested >= start && nested + 64 <= end);
+        // the next block starts past the nested one: nothing overlaps
+        let next = unsafe { REENTRANT.alloc(layout(64, 8)) } as usize;
+        assert!(next >= nested + 64 && next + 64 <= end);
+        assert!(REENTRANT.state.try_lock().is_some());
+    }
+}
@@ -0,0 +1,71 @@
+// rooster-os/common/runtime/runtime_core/hexdump.rs
+//! `hexdump -C`-style dump of a byte slice to any `fmt::Write`.
+//!   • 16 bytes per line: address, hex in two groups of 8, `|ASCII|`
+//!   • Addresses start at `base` (pass the slice's own address, or 0 for
+//!     offsets); non-printable bytes show as `.`
+//!   • No allocation, so usable from the panic path
+//!
+//! ```text
+//! 00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|
+//! 00001010  02 03 fe ff                                       |....|
+//! ```
+
+use core::fmt;
+
+/// Bytes shown per line
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Write `bytes` as a hex dump whose first line is labelled `base`
+pub fn hexdump(writer: &mut dyn fmt::Write, base: usize, bytes: &[u8]) -> fmt::Result {
+    for (i, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write!(writer, "{:08x} ", base.wrapping_add(i * BYTES_PER_LINE))?;
+        for col in 0..BYTES_PER_LINE {
+            // extra space between the two groups of 8
+            if col % 8 == 0 {
+                writer.write_char(' ')?;
+            }
+            match line.get(col) {
+                Some(b) => write!(writer, "{:02x} ", b)?,
+                None    => writer.write_str("   ")?,
+            }
+        }
+        writer.write_str(" |")?;
+        for &b in line {
+            let c = if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' };
+            writer.write_char(c)?;
+        }
+        writer.write_str("|\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_core::FmtBuf;
+
+    #[test_case]
+    fn dumps_a_partial_last_line() {
+        let bytes = *b"Hello, world!\n\x00\x01\x02\x03\xfe\xff";
+        let mut out = FmtBuf::<256>::new();
+        hexdump(&mut out, 0x1000, &bytes).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+             00001010  02 03 fe ff                                       |....|\n"
+        );
+    }
+
+    #[test_case]
+    fn empty_input_writes_nothing() {
+        let mut out = FmtBuf::<16>::new();
+        hexdump(&mut out, 0, &[]).unwrap();
+        assert_eq!(out.as_str(), "");
+    }
+
+    #[test_case]
+    fn a_full_writer_reports_the_error() {
+        let mut out = FmtBuf::<16>::new();
+        assert!(hexdump(&mut out, 0, &[0; 4]).is_err());
+    }
+}
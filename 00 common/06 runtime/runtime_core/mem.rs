@@ -0,0 +1,207 @@
+// rooster-os/common/runtime/runtime_core/mem.rs
+//! Segment setup helpers shared by every arch's `rust_start`: the arch
+//! file supplies the linker symbols, these do the copying and zeroing.
+
+use core::intrinsics::copy_nonoverlapping;
+
+/// How `.data` has to be brought from its load address to its run address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DataCopy {
+    /// Loaded where it runs (or empty): nothing to do
+    Skip,
+    /// Disjoint ranges: `copy_nonoverlapping` is valid
+    Disjoint,
+    /// Ranges overlap with the run address below the load address: copy
+    /// front to back
+    OverlapForward,
+    /// Ranges overlap with the run address above the load address: copy
+    /// back to front
+    OverlapBackward,
+}
+
+fn classify_data_copy(src: usize, dst: usize, len: usize) -> DataCopy {
+    if src == dst || len == 0 {
+        DataCopy::Skip
+    } else if !(src < dst + len && dst < src + len) {
+        DataCopy::Disjoint
+    } else if dst < src {
+        DataCopy::OverlapForward
+    } else {
+        DataCopy::OverlapBackward
+    }
+}
+
+const WORD: usize = core::mem::size_of::<usize>();
+
+/// `memmove` for `dst < src`: front to back, so each byte is read before
+/// the copy reaches it. Word-sized when both ends share an alignment.
+/// Volatile accesses keep this from becoming a `memmove` call.
+unsafe fn copy_forward(src: *const u8, dst: *mut u8, len: usize) {
+    let mut i = 0;
+    if (src as usize ^ dst as usize) % WORD == 0 {
+        while i < len && (dst as usize + i) % WORD != 0 {
+            dst.add(i).write_volatile(src.add(i).read_volatile());
+            i += 1;
+        }
+        while len - i >= WORD {
+            let word = (src.add(i) as *const usize).read_volatile();
+            (dst.add(i) as *mut usize).write_volatile(word);
+            i += WORD;
+        }
+    }
+    while i < len {
+        dst.add(i).write_volatile(src.add(i).read_volatile());
+        i += 1;
+    }
+}
+
+/// `memmove` for `dst > src`: back to front, mirroring `copy_forward`
+unsafe fn copy_backward(src: *const u8, dst: *mut u8, len: usize) {
+    let mut i = len;
+    if (src as usize ^ dst as usize) % WORD == 0 {
+        while i > 0 && (dst as usize + i) % WORD != 0 {
+            i -= 1;
+            dst.add(i).write_volatile(src.add(i).read_volatile());
+        }
+        while i >= WORD {
+            i -= WORD;
+            let word = (src.add(i) as *const usize).read_volatile();
+            (dst.add(i) as *mut usize).write_volatile(word);
+        }
+    }
+    while i > 0 {
+        i -= 1;
+        dst.add(i).write_volatile(src.add(i).read_volatile());
+    }
+}
+
+/// Copy `.data` from its load address, skipping the self-copy case and
+/// never handing overlapping ranges to `copy_nonoverlapping`: those get
+/// a `memmove`-style copy in the direction that reads each byte first
+pub unsafe fn copy_data(src: *const u8, dst: *mut u8, len: usize) {
+    match classify_data_copy(src as usize, dst as usize, len) {
+        DataCopy::Skip            => {}
+        DataCopy::Disjoint        => copy_nonoverlapping(src, dst, len),
+        DataCopy::OverlapForward  => copy_forward(src, dst, len),
+        DataCopy::OverlapBackward => copy_backward(src, dst, len),
+    }
+}
+
+/// Whether half-open ranges `[a.0, a.1)` and `[b.0, b.1)` share a byte;
+/// empty ranges overlap nothing, and merely adjacent ranges do not overlap
+pub fn ranges_overlap(a: (usize, usize), b: (usize, usize)) -> bool {
+    a.0 < a.1 && b.0 < b.1 && a.0 < b.1 && b.0 < a.1
+}
+
+/// Zero `[start, start + len)` a `usize` at a time, with byte writes only
+/// for the unaligned head and tail. Volatile stores keep the compiler
+/// from turning the loop back into a (possibly byte-wise) `memset` call.
+pub unsafe fn zero_region(start: *mut u8, len: usize) {
+    let mut p = start;
+    let end   = start.add(len);
+
+    // head: bytes up to the first word boundary
+    while p < end && (p as usize) % WORD != 0 {
+        p.write_volatile(0);
+        p = p.add(1);
+    }
+    // body: whole words
+    while (end as usize) - (p as usize) >= WORD {
+        (p as *mut usize).write_volatile(0);
+        p = p.add(WORD);
+    }
+    // tail: remaining bytes
+    while p < end {
+        p.write_volatile(0);
+        p = p.add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn zero_region_clears_exactly_the_range() {
+        // odd start and length: byte head, word body and byte tail
+        for (start, len) in [(3, 29), (8, 16), (1, 5), (0, 0)] {
+            let mut buf = [0xffu8; 48];
+            unsafe { zero_region(buf.as_mut_ptr().add(start), len) };
+            for (i, &b) in buf.iter().enumerate() {
+                let inside = (start..start + len).contains(&i);
+                assert_eq!(b, if inside { 0 } else { 0xff });
+            }
+        }
+    }
+
+    #[test_case]
+    fn data_copy_is_skipped_in_place_or_when_empty() {
+        assert_eq!(classify_data_copy(0x1000, 0x1000, 0x200), DataCopy::Skip);
+        assert_eq!(classify_data_copy(0x1000, 0x8000, 0), DataCopy::Skip);
+        assert_eq!(classify_data_copy(0x1000, 0x8000, 0x200), DataCopy::Disjoint);
+        // touching but not overlapping
+        assert_eq!(classify_data_copy(0x1000, 0x1200, 0x200), DataCopy::Disjoint);
+    }
+
+    #[test_case]
+    fn copy_data_copies_disjoint_ranges() {
+        let src = *b"rooster .data segment";
+        let mut dst = [0u8; 21];
+        unsafe { copy_data(src.as_ptr(), dst.as_mut_ptr(), src.len()) };
+        assert_eq!(dst, src);
+        // a self-copy leaves the bytes as they are
+        unsafe { copy_data(dst.as_ptr(), dst.as_mut_ptr(), dst.len()) };
+        assert_eq!(dst, src);
+    }
+
+    #[test_case]
+    fn ranges_overlap_only_when_they_share_a_byte() {
+        assert!(ranges_overlap((0x1000, 0x2000), (0x1fff, 0x3000)));
+        assert!(ranges_overlap((0x1000, 0x4000), (0x2000, 0x3000)));
+        // touching ends do not overlap
+        assert!(!ranges_overlap((0x1000, 0x2000), (0x2000, 0x3000)));
+        assert!(!ranges_overlap((0x2000, 0x3000), (0x1000, 0x2000)));
+        // nor does an empty range, even inside the other
+        assert!(!ranges_overlap((0x1800, 0x1800), (0x1000, 0x2000)));
+    }
+
+    #[test_case]
+    fn overlapping_copies_pick_the_safe_direction() {
+        assert_eq!(classify_data_copy(0x1100, 0x1000, 0x200), DataCopy::OverlapForward);
+        assert_eq!(classify_data_copy(0x1000, 0x1100, 0x200), DataCopy::OverlapBackward);
+    }
+
+    #[test_case]
+    fn overlapping_copy_data_matches_memmove() {
+        // word-aligned and odd shifts, in both directions
+        for (src, dst) in [(16, 0), (3, 0), (0, 16), (0, 5), (9, 1), (1, 9)] {
+            let mut buf = [0u8; 96];
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            let mut expected = buf;
+            expected.copy_within(src..src + 64, dst);
+            let base = buf.as_mut_ptr();
+            unsafe { copy_data(base.add(src), base.add(dst), 64) };
+            assert_eq!(buf, expected, "{} -> {}", src, dst);
+        }
+    }
+
+    #[test_case]
+    fn zero_region_handles_sizes_around_a_word() {
+        // 7, 8 and 9 bytes straddle one word; 4095 leaves a 7-byte tail
+        #[repr(align(8))]
+        struct Buf([u8; 4112]);
+        let mut buf = Buf([0xff; 4112]);
+        for len in [7, 8, 9, 4095] {
+            for start in [0, 1, 8] {
+                buf.0.fill(0xff);
+                unsafe { zero_region(buf.0.as_mut_ptr().add(start), len) };
+                for (i, &b) in buf.0.iter().enumerate() {
+                    let inside = (start..start + len).contains(&i);
+                    assert_eq!(b, if inside { 0 } else { 0xff }, "len {} start {}", len, start);
+                }
+            }
+        }
+    }
+}
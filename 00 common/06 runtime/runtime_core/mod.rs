@@ -0,0 +1,54 @@
+// rooster-os/common/runtime/runtime_core/mod.rs
+//! Architecture-independent part of the Rust runtime, included by each
+//! arch's `rt.rs` with `#[path]` so it shares the crate, its features and
+//! (for `track-allocs`) the arch's `alloc_track` module. The arch files
+//! keep only what differs per CPU: the entry stub and `rust_start`, the
+//! segment copy over their linker symbols, consoles and how to halt
+//! (`crate::early_write` / `crate::halt`, which the core calls) and
+//! interrupt masking (`crate::interrupts::InterruptGuard`).
+//!   • `sync`    — `Spinlock`, `Once`
+//!   • `assert`  — `kassert!`, reporting through the arch's early console
+//!   • `buddy`   — power-of-two page-block allocator (frames, page tables)
+//!   • `console` — the pluggable sink behind `print!`/`println!`
+//!   • `heap`    — the allocators, `ALLOCATOR` and the alloc-error handler
+//!   • `hexdump` — hex + ASCII dumps to any `fmt::Write`
+//!   • `mem`     — .data copy / .bss zeroing helpers
+//!   • `panic`   — panic marker and report formatting
+
+pub mod assert;
+pub mod buddy;
+pub mod console;
+pub mod heap;
+pub mod hexdump;
+pub mod mem;
+pub mod panic;
+pub mod sync;
+
+/// `fmt::Write` into a fixed buffer, for tests that check formatted output
+#[cfg(test)]
+pub(crate) struct FmtBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+#[cfg(test)]
+impl<const N: usize> FmtBuf<N> {
+    pub(crate) const fn new() -> Self {
+        FmtBuf { buf: [0; N], len: 0 }
+    }
+
+    /// Everything written so far
+    pub(crate) fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> core::fmt::Write for FmtBuf<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.buf.get_mut(self.len..end).ok_or(core::fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+// rooster-os/common/runtime/runtime_core/panic.rs
+//! Arch-neutral half of the panic handler: the "in panic" marker the
+//! allocators check, the recursion guard, the kernel's pre-panic hook, and
+//! the message format every console prints.
+
+use core::fmt;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Set once the panic handler starts printing. Every path it takes writes
+/// straight to the hardware (e.g. a UART or text console), so an
+/// allocation from here on is a bug: the heap may be what failed.
+static IN_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// How many panic handlers are active: 1 while handling a panic, more if
+/// the handling itself panicked (e.g. a fault inside the console write)
+static PANIC_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Count an entry into the panic handler and return the new depth. Above
+/// 1 the handler must skip all output and halt, or it recurses forever.
+pub fn enter() -> usize {
+    PANIC_DEPTH.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Undo `enter` for a handler that resumes normal execution instead of
+/// halting (the in-kernel test runner)
+pub fn leave() {
+    PANIC_DEPTH.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Installed pre-panic hook as a raw `fn(&PanicInfo)`; 0 = none
+static PANIC_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Install a function the panic handler calls before its own output
+/// (flush a log, beep, ...). It runs once per panic, inside the recursion
+/// guard: if it panics or faults in turn, the handler halts right away.
+/// The heap is still usable from it.
+pub fn set_panic_hook(f: fn(&PanicInfo)) {
+    PANIC_HOOK.store(f as usize, Ordering::Release);
+}
+
+/// Call the installed hook, if any; the arch handlers call this early,
+/// once `enter` has passed
+pub fn run_hook(info: &PanicInfo) {
+    // SAFETY: `set_panic_hook` only stores `fn(&PanicInfo)` pointers
+    unsafe { dispatch(&PANIC_HOOK, info) };
+}
+
+/// Call the hook stored in `slot` (0 = none) with `arg`. Generic over the
+/// argument so tests can dispatch without a real `PanicInfo`.
+///
+/// SAFETY: `slot` holds 0 or a valid `fn(&T)`
+unsafe fn dispatch<T: ?Sized>(slot: &AtomicUsize, arg: &T) {
+    match slot.load(Ordering::Acquire) {
+        0 => {}
+        f => core::mem::transmute::<usize, fn(&T)>(f)(arg),
+    }
+}
+
+/// Mark the panic handler as running; nothing may allocate from the
+/// global heap afterwards (checked in debug builds). Also empties
+/// `heap::EMERGENCY_HEAP` for this panic's scratch allocations.
+pub fn begin() {
+    IN_PANIC.store(true, Ordering::Relaxed);
+    // SAFETY: a previous panic's scratch allocations died with it
+    unsafe { super::heap::EMERGENCY_HEAP.reset() };
+}
+
+/// Whether the panic handler has started
+pub fn in_progress() -> bool {
+    IN_PANIC.load(Ordering::Relaxed)
+}
+
+/// Take the panic handler back out of its panic state, so a test that
+/// called `begin` leaves the heap usable for the next one
+#[cfg(test)]
+pub(crate) fn end() {
+    IN_PANIC.store(false, Ordering::Relaxed);
+}
+
+/// `PANIC: <info>` line, the same on every arch and console. `info` is
+/// the `PanicInfo` on the real path; tests pass a plain message.
+pub struct Report<'a, T: ?Sized = PanicInfo<'a>>(pub &'a T);
+
+impl<T: fmt::Display + ?Sized> fmt::Display for Report<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PANIC: {}", self.0)
+    }
+}
+
+/// Write the panic report to `w`. Formatting goes straight to `w`, so
+/// this never allocates.
+pub fn report<T: fmt::Display + ?Sized>(w: &mut dyn fmt::Write, info: &T) {
+    let _ = write!(w, "{}", Report(info));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn enter_counts_nested_handlers_and_leave_undoes_it() {
+        // no panic is being handled while a test body runs
+        let outer = enter();
+        assert_eq!(outer, 1);
+        assert_eq!(enter(), 2);
+        leave();
+        assert_eq!(enter(), 2);
+        leave();
+        leave();
+        assert_eq!(PANIC_DEPTH.load(Ordering::Relaxed), 0);
+    }
+
+    static HITS: AtomicUsize = AtomicUsize::new(0);
+
+    // different bodies, so they cannot be merged into one address
+    fn first(_: &PanicInfo) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn second(_: &PanicInfo) {
+        HITS.fetch_add(10, Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn the_last_hook_installed_wins() {
+        let saved = PANIC_HOOK.load(Ordering::Acquire);
+        let installed = || PANIC_HOOK.load(Ordering::Acquire);
+        set_panic_hook(first);
+        assert_eq!(installed(), first as fn(&PanicInfo) as usize);
+        set_panic_hook(second);
+        assert_eq!(installed(), second as fn(&PanicInfo) as usize);
+        PANIC_HOOK.store(saved, Ordering::Release);
+        // installing does not call it
+        assert_eq!(HITS.load(Ordering::Relaxed), 0);
+    }
+
+    fn one(_: &str) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn ten(_: &str) {
+        HITS.fetch_add(10, Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn dispatch_calls_only_the_last_hook_once() {
+        // `run_hook`'s dispatch, over a slot of `fn(&str)` hooks
+        static SLOT: AtomicUsize = AtomicUsize::new(0);
+        unsafe { dispatch(&SLOT, "nothing installed") };
+        assert_eq!(HITS.load(Ordering::Relaxed), 0);
+        SLOT.store(ten as fn(&str) as usize, Ordering::Release);
+        SLOT.store(one as fn(&str) as usize, Ordering::Release);
+        unsafe { dispatch(&SLOT, "boom") };
+        assert_eq!(HITS.swap(0, Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,228 @@
+// rooster-os/common/runtime/runtime_core/sync.rs
+//! Spin-based synchronization usable before (and without) an allocator.
+//!   • `Spinlock` — mutual exclusion around a value; `lock_irqsave` also
+//!     masks interrupts (via the arch's `crate::interrupts`) while held
+//!   • `Once`     — a global written once, then read without locking
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// A minimal `AtomicBool`-based spinlock guarding a value of type `T`
+pub struct Spinlock<T> {
+    locked: AtomicBool,
+    data:   UnsafeCell<T>,
+}
+
+// SAFETY: access to `data` is serialized through `locked`
+unsafe impl<T: Send> Sync for Spinlock<T> {}
+
+impl<T> Spinlock<T> {
+    pub const fn new(data: T) -> Self {
+        Spinlock { locked: AtomicBool::new(false), data: UnsafeCell::new(data) }
+    }
+
+    /// Spin until the lock is acquired; released when the guard drops
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Wait on a plain load so we don't hammer the cache line
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        SpinlockGuard { lock: self }
+    }
+
+    /// Mask interrupts, then `lock`. On drop the lock is released first and
+    /// the previous interrupt state restored after, so an interrupt
+    /// handler taking the same lock cannot spin against its own CPU.
+    pub fn lock_irqsave(&self) -> IrqSpinlockGuard<'_, T> {
+        let irq = crate::interrupts::InterruptGuard::new();
+        IrqSpinlockGuard { guard: self.lock(), _irq: irq }
+    }
+
+    /// Take the lock only if it is free right now
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinlockGuard { lock: self })
+    }
+}
+
+/// RAII guard returned by `Spinlock::lock`
+pub struct SpinlockGuard<'a, T> {
+    lock: &'a Spinlock<T>,
+}
+
+impl<T> Deref for SpinlockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// RAII guard returned by `Spinlock::lock_irqsave`. Field order matters:
+/// fields drop in declaration order, so the lock goes before IF/DAIF.
+pub struct IrqSpinlockGuard<'a, T> {
+    guard: SpinlockGuard<'a, T>,
+    _irq:  crate::interrupts::InterruptGuard,
+}
+
+impl<T> Deref for IrqSpinlockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+// `Once` states
+const ONCE_EMPTY:   u8 = 0;
+const ONCE_WRITING: u8 = 1;
+const ONCE_READY:   u8 = 2;
+
+/// A value written at most once and read lock-free afterwards.
+/// States: EMPTY -> WRITING (one winner, by CAS) -> READY. Readers only
+/// look at the value after seeing READY.
+pub struct Once<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: the value is written once, before `state` becomes READY
+// (Release), and only read after observing READY (Acquire)
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+
+impl<T> Once<T> {
+    /// An empty cell; usable in a `static`
+    pub const fn new() -> Self {
+        Once { state: AtomicU8::new(ONCE_EMPTY), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// Store `value` unless one is already stored (or being stored), in
+    /// which case it is handed back
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(ONCE_EMPTY, ONCE_WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        unsafe { (*self.value.get()).write(value) };
+        self.state.store(ONCE_READY, Ordering::Release);
+        Ok(())
+    }
+
+    /// The stored value, running `init` to produce it if nobody has. A
+    /// caller that loses the race spins until the winner's value is in,
+    /// so `init` runs at most once; it must not touch this `Once` again.
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        match self
+            .state
+            .compare_exchange(ONCE_EMPTY, ONCE_WRITING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe { (*self.value.get()).write(init()) };
+                self.state.store(ONCE_READY, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != ONCE_READY {
+                    spin_loop();
+                }
+            }
+        }
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// The stored value, if `set` has completed
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == ONCE_READY {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == ONCE_READY {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test_case]
+    fn once_keeps_the_first_value() {
+        let once = Once::new();
+        assert_eq!(once.get(), None);
+        assert_eq!(once.set(1), Ok(()));
+        assert_eq!(once.set(2), Err(2));
+        assert_eq!(once.get(), Some(&1));
+    }
+
+    #[test_case]
+    fn get_or_init_runs_init_at_most_once() {
+        let once = Once::new();
+        let runs = Cell::new(0);
+        let init = || {
+            runs.set(runs.get() + 1);
+            7
+        };
+        assert_eq!(*once.get_or_init(init), 7);
+        assert_eq!(*once.get_or_init(init), 7);
+        assert_eq!(runs.get(), 1);
+        // nor after a `set` won
+        let set = Once::new();
+        let _ = set.set(3);
+        assert_eq!(*set.get_or_init(init), 3);
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test_case]
+    fn once_drops_only_a_stored_value() {
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        let drops = Cell::new(0);
+        drop(Once::<Counted>::new());
+        assert_eq!(drops.get(), 0);
+        let once = Once::new();
+        let _ = once.set(Counted(&drops));
+        drop(once);
+        assert_eq!(drops.get(), 1);
+    }
+}
@@ -0,0 +1,103 @@
+// rooster-os/arch/x86/runtime/rs_runtime/alloc_track.rs
+//! Allocation call-site tracking for leak hunting (`track-allocs` feature).
+//!   • Fixed ring of `CAPACITY` records: pointer, size, caller address
+//!   • Once full, each new allocation overwrites the oldest record; if
+//!     that record was still live it is counted and reported as lost
+//!   • Caller addresses come from the frame-pointer chain, so build with
+//!     `-C force-frame-pointers=yes` for meaningful output
+
+use core::fmt;
+
+use crate::Spinlock;
+
+/// Number of allocation records kept (each 3 words + flag)
+pub const CAPACITY: usize = 256;
+
+#[derive(Clone, Copy)]
+struct Record {
+    ptr:    usize,
+    size:   usize,
+    caller: usize,
+    live:   bool,
+}
+
+struct Ring {
+    records:   [Record; CAPACITY],
+    head:      usize, // next slot to write
+    len:       usize, // valid records, up to CAPACITY
+    lost_live: usize, // live records overwritten by wraparound
+}
+
+const EMPTY: Record = Record { ptr: 0, size: 0, caller: 0, live: false };
+
+static RING: Spinlock<Ring> = Spinlock::new(Ring {
+    records:   [EMPTY; CAPACITY],
+    head:      0,
+    len:       0,
+    lost_live: 0,
+});
+
+/// Return address `depth` frames above the calling function's frame
+/// (0 = the caller's own return address); 0 if the chain ends early
+#[inline(always)]
+pub fn caller_address(depth: usize) -> usize {
+    let mut rbp: usize;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack)) };
+    for _ in 0..depth {
+        if rbp == 0 || rbp % 8 != 0 {
+            return 0;
+        }
+        rbp = unsafe { *(rbp as *const usize) };
+    }
+    if rbp == 0 || rbp % 8 != 0 {
+        return 0;
+    }
+    unsafe { *((rbp + 8) as *const usize) }
+}
+
+/// Note a successful allocation
+pub fn record_alloc(ptr: *mut u8, size: usize, caller: usize) {
+    let mut ring = RING.lock();
+    let slot = ring.head;
+    if ring.records[slot].live {
+        ring.lost_live += 1;
+    }
+    ring.records[slot] = Record { ptr: ptr as usize, size, caller, live: true };
+    ring.head = (slot + 1) % CAPACITY;
+    ring.len  = (ring.len + 1).min(CAPACITY);
+}
+
+/// Note a deallocation; unknown pointers (already overwritten) are ignored
+pub fn record_free(ptr: *mut u8) {
+    let mut ring = RING.lock();
+    let ptr = ptr as usize;
+    // newest first, so a reused address matches its latest allocation
+    for i in 0..ring.len {
+        let slot = (ring.head + CAPACITY - 1 - i) % CAPACITY;
+        let rec = &mut ring.records[slot];
+        if rec.live && rec.ptr == ptr {
+            rec.live = false;
+            return;
+        }
+    }
+}
+
+/// Print every allocation still live, oldest first
+pub fn dump_live_allocations(w: &mut dyn fmt::Write) {
+    let ring = RING.lock();
+    let _ = writeln!(w, "live allocations (ring of {}):", CAPACITY);
+    let oldest = (ring.head + CAPACITY - ring.len) % CAPACITY;
+    let mut count = 0;
+    for i in 0..ring.len {
+        let rec = &ring.records[(oldest + i) % CAPACITY];
+        if rec.live {
+            count += 1;
+            let _ = writeln!(
+                w,
+                "  {:#018x} {:>8} bytes  from {:#018x}",
+                rec.ptr, rec.size, rec.caller
+            );
+        }
+    }
+    let _ = writeln!(w, "  {} live, {} older live records overwritten", count, ring.lost_live);
+}
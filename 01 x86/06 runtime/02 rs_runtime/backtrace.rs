@@ -0,0 +1,245 @@
+// rooster-os/arch/x86/runtime/rs_runtime/backtrace.rs
+//! Frame-pointer stack unwinding for panic diagnostics.
+//!   • Follows the saved-RBP chain: [rbp] = caller's rbp, [rbp+8] = return address
+//!   • Requires the kernel and runtime to be built with frame pointers
+//!     (`-C force-frame-pointers=yes`), hence the `backtrace` feature
+//!   • Stops after `MAX_FRAMES`, on a null return address, or as soon as
+//!     RBP leaves the boot stack or fails to move towards its top
+//!   • Return addresses print as `name+offset` once `init_symbols` found
+//!     the kernel's `.symtab`/`.strtab`, raw otherwise
+
+use core::fmt;
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+
+use crate::boot_info::BootInfo;
+use crate::multiboot::{c_str_bytes, cmdline_from_bytes, SHT_STRTAB, SHT_SYMTAB};
+use crate::runtime_core::sync::Once;
+use crate::{stack_space, stack_top, sym_addr};
+
+/// Upper bound on frames printed, in case the chain is corrupt
+pub const MAX_FRAMES: usize = 64;
+
+fn stack_bounds() -> (usize, usize) {
+    unsafe {
+        (sym_addr(&stack_space), sym_addr(&stack_top))
+    }
+}
+
+/// Call `f(depth, return_address)` for each frame above the caller
+pub fn walk(mut f: impl FnMut(usize, usize)) {
+    let (lo, hi) = stack_bounds();
+    let mut rbp: usize;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack)) };
+
+    for depth in 0..MAX_FRAMES {
+        if rbp % 8 != 0 || rbp < lo || rbp > hi - 16 {
+            break;
+        }
+        // SAFETY: rbp was just checked to lie inside the boot stack
+        let (saved_rbp, ret) = unsafe {
+            (*(rbp as *const usize), *((rbp + 8) as *const usize))
+        };
+        if ret == 0 {
+            break;
+        }
+        f(depth, ret);
+        // frames must move strictly up the stack; anything else is a cycle
+        if saved_rbp <= rbp {
+            break;
+        }
+        rbp = saved_rbp;
+    }
+}
+
+/// One ELF64 symbol table entry (`Elf64_Sym`)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfSymbol {
+    name:  u32,
+    info:  u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size:  u64,
+}
+
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC:   u8 = 2;
+
+/// The kernel's loaded `.symtab` and the `.strtab` its names point into
+#[derive(Clone, Copy)]
+pub struct SymbolTable {
+    symbols: usize,
+    count:   usize,
+    entsize: usize,
+    strtab:  usize,
+    strsize: usize,
+}
+
+impl SymbolTable {
+    /// Find the symbol table among the kernel's sections. `None` if the
+    /// loader did not load it or its string table, or (when the map is
+    /// known) either lies outside usable RAM.
+    ///
+    /// SAFETY: `info.elf_sections()` must describe this kernel's loaded
+    /// image.
+    pub unsafe fn from_boot_info(info: &BootInfo) -> Option<Self> {
+        let sections = info.elf_sections()?;
+        let symtab = sections.iter().find(|s| s.typ == SHT_SYMTAB && s.addr != 0)?;
+        let strtab = sections.get(symtab.link as usize)
+            .filter(|s| s.typ == SHT_STRTAB && s.addr != 0)?;
+        if (symtab.entsize as usize) < size_of::<ElfSymbol>() {
+            return None;
+        }
+        let map = info.memory_map_or_empty();
+        if !map.is_empty()
+            && !(map.covers_usable(symtab.addr, symtab.size)
+                && map.covers_usable(strtab.addr, strtab.size))
+        {
+            return None;
+        }
+        Some(SymbolTable {
+            symbols: symtab.addr as usize,
+            count:   (symtab.size / symtab.entsize) as usize,
+            entsize: symtab.entsize as usize,
+            strtab:  strtab.addr as usize,
+            strsize: strtab.size as usize,
+        })
+    }
+
+    /// The tables as `[start, end)`, symbols first
+    pub fn ranges(&self) -> [(usize, usize); 2] {
+        [
+            (self.symbols, self.symbols + self.count * self.entsize),
+            (self.strtab, self.strtab + self.strsize),
+        ]
+    }
+
+    fn symbol(&self, i: usize) -> ElfSymbol {
+        // SAFETY: `i < count`, inside the table `from_boot_info` checked
+        unsafe { read_unaligned((self.symbols + i * self.entsize) as *const ElfSymbol) }
+    }
+
+    fn name(&self, sym: &ElfSymbol) -> Option<&'static str> {
+        let start = sym.name as usize;
+        if start == 0 || start >= self.strsize {
+            return None;
+        }
+        // SAFETY: within the string table `from_boot_info` checked
+        let bytes = unsafe { c_str_bytes(self.strtab + start, self.strsize - start) };
+        (bytes.len() < self.strsize - start).then(|| cmdline_from_bytes(bytes))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The nearest named code symbol at or below `addr`, as `(name, addr -
+    /// symbol)`. The table is not sorted, so this is a linear scan; it
+    /// only runs on the panic path.
+    pub fn resolve(&self, addr: usize) -> Option<(&'static str, usize)> {
+        let addr = addr as u64;
+        let mut best: Option<(u64, &'static str)> = None;
+        for i in 0..self.count {
+            let sym = self.symbol(i);
+            let kind = sym.info & 0xf;
+            if sym.shndx == 0 || (kind != STT_FUNC && kind != STT_NOTYPE) || sym.value > addr {
+                continue;
+            }
+            if best.is_some_and(|(value, _)| value >= sym.value) {
+                continue;
+            }
+            if let Some(name) = self.name(&sym) {
+                best = Some((sym.value, name));
+            }
+        }
+        best.map(|(value, name)| (name, (addr - value) as usize))
+    }
+}
+
+/// The kernel's symbols, once `init_symbols` found them
+static SYMBOLS: Once<SymbolTable> = Once::new();
+
+/// Locate `.symtab`/`.strtab` so `print` can name frames; returns false
+/// (and addresses stay raw) if the loader did not provide them.
+///
+/// SAFETY: as for `SymbolTable::from_boot_info`.
+pub unsafe fn init_symbols(info: &BootInfo) -> bool {
+    match SymbolTable::from_boot_info(info) {
+        Some(table) => SYMBOLS.set(table).is_ok(),
+        None        => false,
+    }
+}
+
+/// The table `init_symbols` registered, if any
+pub fn symbols() -> Option<&'static SymbolTable> {
+    SYMBOLS.get()
+}
+
+/// Print return addresses, one per line, with `name+offset` where a
+/// symbol covers them
+pub fn print(w: &mut dyn fmt::Write) {
+    let _ = writeln!(w, "backtrace:");
+    let symbols = symbols();
+    walk(|depth, ret| {
+        let _ = match symbols.and_then(|s| s.resolve(ret)) {
+            Some((name, offset)) => {
+                writeln!(w, "  #{:<2} {:#018x} {}+{:#x}", depth, ret, name, offset)
+            }
+            None => writeln!(w, "  #{:<2} {:#018x}", depth, ret),
+        };
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn sym(name: u32, info: u8, shndx: u16, value: u64) -> ElfSymbol {
+        ElfSymbol { name, info, other: 0, shndx, value, size: 0 }
+    }
+
+    static STRTAB: [u8; 33] = *b"\0kernel_main\0rust_start\0data\0und\0";
+
+    // (name offsets: kernel_main 1, rust_start 13, data 24, und 29)
+    static SYMBOLS: [ElfSymbol; 6] = [
+        sym(0,  0,          0, 0),        // the null symbol
+        sym(13, STT_FUNC,   1, 0x10_0000),
+        sym(1,  STT_FUNC,   1, 0x10_0400),
+        sym(24, 1,          2, 0x10_0600), // STT_OBJECT: not code
+        sym(29, STT_FUNC,   0, 0x10_0700), // undefined
+        sym(0,  STT_NOTYPE, 1, 0x10_0800), // unnamed
+    ];
+
+    fn table() -> SymbolTable {
+        SymbolTable {
+            symbols: SYMBOLS.as_ptr() as usize,
+            count:   SYMBOLS.len(),
+            entsize: size_of::<ElfSymbol>(),
+            strtab:  STRTAB.as_ptr() as usize,
+            strsize: STRTAB.len(),
+        }
+    }
+
+    #[test_case]
+    fn resolve_picks_the_nearest_symbol_below() {
+        let table = table();
+        assert_eq!(table.resolve(0x10_0000), Some(("rust_start", 0)));
+        assert_eq!(table.resolve(0x10_03ff), Some(("rust_start", 0x3ff)));
+        assert_eq!(table.resolve(0x10_0400), Some(("kernel_main", 0)));
+        // data, undefined and unnamed symbols are passed over
+        assert_eq!(table.resolve(0x10_0900), Some(("kernel_main", 0x500)));
+    }
+
+    #[test_case]
+    fn resolve_below_every_symbol_fails() {
+        assert_eq!(table().resolve(0xfffff), None);
+        assert_eq!(table().resolve(0), None);
+    }
+
+    #[test_case]
+    fn names_past_the_string_table_are_ignored() {
+        let table = SymbolTable { strsize: 20, ..table() };
+        // "rust_start" would run past the shortened table
+        assert_eq!(table.resolve(0x10_0100), None);
+        assert_eq!(table.resolve(0x10_0500), Some(("kernel_main", 0x100)));
+    }
+}
@@ -0,0 +1,47 @@
+// rooster-os/arch/x86/runtime/rs_runtime/banner.rs
+//! Boot banner (`banner` feature): what the runtime decided, printed to
+//! COM1 right before `kernel_main`.
+//!   • CPU features, usable RAM, heap range, command line and TSC rate
+//!   • Formats straight to the UART, so it never allocates
+
+use core::fmt::Write;
+
+use crate::boot_info::BootInfo;
+use crate::{cpu, delay, serial};
+
+/// Print the banner for `info`
+pub fn print_banner(info: &BootInfo) {
+    let mut out = serial::com1();
+
+    let map = info.memory_map_or_empty();
+    let usable = map.usable_bytes();
+
+    let _ = writeln!(out, "RoosterOS runtime");
+    let _ = writeln!(out, "  cpu:     {}", cpu::features());
+    let _ = writeln!(
+        out,
+        "  memory:  {} KiB usable in {} regions{}",
+        usable / 1024,
+        map.len(),
+        if map.truncated() { " (map truncated)" } else { "" }
+    );
+    #[cfg(not(feature = "no-heap"))]
+    {
+        let (heap_start, heap_end) = crate::ALLOCATOR.bounds();
+        let _ = writeln!(
+            out,
+            "  heap:    [{:#x}, {:#x}) {} KiB",
+            heap_start,
+            heap_end,
+            (heap_end - heap_start) / 1024
+        );
+    }
+    #[cfg(feature = "no-heap")]
+    let _ = writeln!(out, "  heap:    none (no-heap)");
+    let _ = writeln!(out, "  cmdline: {}", info.cmdline().unwrap_or("(none)"));
+    let _ = writeln!(out, "  runtime: {} cycles", crate::runtime_elapsed_cycles());
+    let _ = match delay::tsc_khz() {
+        Some(khz) => writeln!(out, "  tsc:     {}.{:03} MHz", khz / 1000, khz % 1000),
+        None      => writeln!(out, "  tsc:     not calibrated"),
+    };
+}
@@ -0,0 +1,203 @@
+// rooster-os/arch/x86/runtime/rs_runtime/boot_info.rs
+//! Typed boot information handed to `kernel_main`.
+//!   • Built once in `rust_start` from either Multiboot protocol
+//!   • Keeps the raw magic / MBI address as an escape hatch
+//!   • `flags()` and the `has_*` accessors say which fields are valid
+
+use crate::multiboot::{self, ElfSections, Framebuffer, MemoryMap, Module, ModuleList};
+use crate::multiboot2;
+use crate::MULTIBOOT2_MAGIC;
+
+/// Everything the runtime learned from the bootloader. Each part is read
+/// through an accessor that first checks the matching `flags` bit.
+pub struct BootInfo {
+    /// Raw handoff magic (Multiboot1 or Multiboot2)
+    pub magic:    u32,
+    /// Raw physical address of the MBI
+    pub mbi_addr: usize,
+    /// Multiboot1 `flags` word: which MBI fields are valid. For
+    /// Multiboot2 the same bits are synthesized from the tags found.
+    flags:        u32,
+    memory_map:   MemoryMap,
+    cmdline:      Option<&'static str>,
+    modules:      ModuleList,
+    framebuffer:  Option<Framebuffer>,
+    elf_sections: Option<ElfSections>,
+}
+
+impl BootInfo {
+    pub const fn empty() -> Self {
+        BootInfo {
+            magic:        0,
+            mbi_addr:     0,
+            flags:        0,
+            memory_map:   MemoryMap::new(),
+            cmdline:      None,
+            modules:      ModuleList::new(),
+            framebuffer:  None,
+            elf_sections: None,
+        }
+    }
+
+    /// Parse the handoff for whichever protocol `magic` names.
+    ///
+    /// SAFETY: `magic` must be a supported Multiboot magic and `mbi_addr`
+    /// must point at a valid info structure for that protocol.
+    pub unsafe fn parse(magic: u32, mbi_addr: usize) -> Self {
+        let mut info = BootInfo { magic, mbi_addr, ..BootInfo::empty() };
+        if magic == MULTIBOOT2_MAGIC {
+            let mb2 = multiboot2::parse(mbi_addr);
+            info.memory_map   = mb2.memory_map;
+            info.cmdline      = mb2.cmdline;
+            info.modules      = mb2.modules;
+            info.framebuffer  = mb2.framebuffer;
+            info.elf_sections = mb2.elf_sections;
+            info.flags        = mb2_flags(&info);
+        } else {
+            info.flags        = multiboot::parse_flags(mbi_addr);
+            info.memory_map   = multiboot::parse_memory_map(mbi_addr);
+            info.cmdline      = multiboot::parse_cmdline(mbi_addr);
+            info.modules      = multiboot::parse_modules(mbi_addr, &info.memory_map);
+            info.framebuffer  = multiboot::parse_framebuffer(mbi_addr);
+            info.elf_sections = multiboot::parse_elf_sections(mbi_addr, &info.memory_map);
+        }
+        info
+    }
+
+    /// Which MBI fields the bootloader filled in (`multiboot::FLAG_*`)
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// A full map (bit 6), or the two regions `parse_memory_map` builds
+    /// from `mem_lower`/`mem_upper` (bit 0)
+    pub fn has_memory_map(&self) -> bool {
+        self.flags & (multiboot::FLAG_MMAP | multiboot::FLAG_MEM) != 0
+    }
+
+    pub fn has_cmdline(&self) -> bool {
+        self.flags & multiboot::FLAG_CMDLINE != 0
+    }
+
+    pub fn has_modules(&self) -> bool {
+        self.flags & multiboot::FLAG_MODS != 0
+    }
+
+    pub fn has_framebuffer(&self) -> bool {
+        self.flags & multiboot::FLAG_FRAMEBUFFER != 0
+    }
+
+    pub fn has_elf_sections(&self) -> bool {
+        self.flags & multiboot::FLAG_ELF_SHDR != 0
+    }
+
+    /// The memory map, if the bootloader provided one
+    pub fn memory_map(&self) -> Option<&MemoryMap> {
+        self.has_memory_map().then_some(&self.memory_map)
+    }
+
+    /// `memory_map`, or an empty map if there is none: for checks that
+    /// pass when there is nothing to check against
+    pub(crate) fn memory_map_or_empty(&self) -> &MemoryMap {
+        const NONE: &MemoryMap = &MemoryMap::new();
+        self.memory_map().unwrap_or(NONE)
+    }
+
+    /// The command line, if the bootloader provided one
+    pub fn cmdline(&self) -> Option<&'static str> {
+        self.cmdline.filter(|_| self.has_cmdline())
+    }
+
+    /// Modules (e.g. an initrd) the bootloader loaded; none if it
+    /// provided no module list
+    pub fn modules(&self) -> core::slice::Iter<'_, Module> {
+        let modules = if self.has_modules() { self.modules.iter().as_slice() } else { &[] };
+        modules.iter()
+    }
+
+    /// The linear framebuffer, if the bootloader set one up
+    pub fn framebuffer(&self) -> Option<&Framebuffer> {
+        self.framebuffer.as_ref().filter(|_| self.has_framebuffer())
+    }
+
+    /// The kernel's section header table, if the bootloader provided one
+    /// that passed the bounds checks
+    pub fn elf_sections(&self) -> Option<&ElfSections> {
+        self.elf_sections.as_ref().filter(|_| self.has_elf_sections())
+    }
+}
+
+/// Multiboot1-style `flags` for what a Multiboot2 tag list contained
+fn mb2_flags(info: &BootInfo) -> u32 {
+    let mut flags = 0;
+    if !info.memory_map.is_empty() {
+        flags |= multiboot::FLAG_MMAP;
+    }
+    if info.cmdline.is_some() {
+        flags |= multiboot::FLAG_CMDLINE;
+    }
+    if !info.modules.is_empty() {
+        flags |= multiboot::FLAG_MODS;
+    }
+    if info.framebuffer.is_some() {
+        flags |= multiboot::FLAG_FRAMEBUFFER;
+    }
+    if info.elf_sections.is_some() {
+        flags |= multiboot::FLAG_ELF_SHDR;
+    }
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiboot::{MemoryRegion, RegionKind};
+
+    /// Every part filled in, with `flags` deciding which are visible
+    fn boot_info(flags: u32) -> BootInfo {
+        let mut info = BootInfo { flags, cmdline: Some("quiet"), ..BootInfo::empty() };
+        info.memory_map.push(MemoryRegion { base: 0, length: 0x9fc00, kind: RegionKind::Usable });
+        info.modules.push(Module { start: 0x20_0000, end: 0x28_0000, cmdline: "initrd" });
+        // SAFETY: an empty table is never read
+        info.elf_sections = unsafe { ElfSections::new(0, 64, 0, 0) };
+        info
+    }
+
+    #[test_case]
+    fn has_accessors_decode_the_flag_bits() {
+        // mem_lower/upper, cmdline, modules, ELF sections, mmap
+        let info = boot_info(0x6d);
+        assert_eq!(info.flags(), 0x6d);
+        assert!(info.has_memory_map() && info.has_cmdline() && info.has_modules());
+        assert!(info.has_elf_sections());
+        assert!(!info.has_framebuffer());
+
+        let none = BootInfo::empty();
+        assert!(!none.has_memory_map() && !none.has_cmdline() && !none.has_modules());
+        assert!(!none.has_framebuffer() && !none.has_elf_sections());
+        assert!(BootInfo { flags: 1 << 12, ..BootInfo::empty() }.has_framebuffer());
+    }
+
+    #[test_case]
+    fn a_map_built_from_mem_lower_and_upper_counts() {
+        let info = boot_info(multiboot::FLAG_MEM);
+        assert!(info.has_memory_map());
+        assert_eq!(info.memory_map().map(MemoryMap::len), Some(1));
+    }
+
+    #[test_case]
+    fn accessors_hide_what_the_flags_do_not_vouch_for() {
+        let info = boot_info(0);
+        assert!(info.memory_map().is_none());
+        assert!(info.memory_map_or_empty().is_empty());
+        assert_eq!(info.cmdline(), None);
+        assert_eq!(info.modules().count(), 0);
+        assert!(info.elf_sections().is_none());
+
+        let info = boot_info(multiboot::FLAG_CMDLINE | multiboot::FLAG_MODS);
+        assert_eq!(info.cmdline(), Some("quiet"));
+        assert_eq!(info.modules().count(), 1);
+        assert!(info.memory_map().is_none());
+        assert!(info.elf_sections().is_none());
+    }
+}
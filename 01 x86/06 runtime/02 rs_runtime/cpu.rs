@@ -0,0 +1,396 @@
+// rooster-os/arch/x86/runtime/rs_runtime/cpu.rs
+//! CPU feature detection via `cpuid`.
+//!   • Leaf 1 (ECX/EDX), leaf 7 (EBX) and leaf 0x8000_0001 (EDX)
+//!   • Decoding is separate from the instruction so it works on any input
+//!   • `enable_sse` configures CR0/CR4 so SSE code generated by rustc runs
+//!   • Under `soft-float` the runtime neither needs nor enables SSE; the
+//!     kernel must then be built for a soft-float target too (e.g.
+//!     `x86_64-unknown-none`, or `-C target-feature=-sse,+soft-float`)
+//!   • `rdtsc` reads the time-stamp counter (raw cycles)
+//!   • `lapic_base` decodes IA32_APIC_BASE once for the SMP code, moving
+//!     an xAPIC page the identity map does not reach
+
+use core::arch::asm;
+use core::fmt;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+use crate::msr::{self, MsrBackend};
+use crate::runtime_core::sync::Once;
+
+/* Control-register bits touched by `enable_sse` */
+const CR0_MP:         u64 = 1 << 1;  // monitor coprocessor
+const CR0_EM:         u64 = 1 << 2;  // x87 emulation (must be clear)
+const CR0_TS:         u64 = 1 << 3;  // task switched (lazy FPU trap)
+const CR4_OSFXSR:     u64 = 1 << 9;  // OS supports FXSAVE/FXRSTOR + SSE
+const CR4_OSXMMEXCPT: u64 = 1 << 10; // OS handles #XM exceptions
+
+/// Bitflags-style set of CPU features the runtime cares about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuFeatures(u32);
+
+impl CpuFeatures {
+    pub const FPU:       CpuFeatures = CpuFeatures(1 << 0);
+    pub const TSC:       CpuFeatures = CpuFeatures(1 << 1);
+    pub const MSR:       CpuFeatures = CpuFeatures(1 << 2);
+    pub const APIC:      CpuFeatures = CpuFeatures(1 << 3);
+    pub const FXSR:      CpuFeatures = CpuFeatures(1 << 4);
+    pub const SSE:       CpuFeatures = CpuFeatures(1 << 5);
+    pub const SSE2:      CpuFeatures = CpuFeatures(1 << 6);
+    pub const SSE3:      CpuFeatures = CpuFeatures(1 << 7);
+    pub const SSSE3:     CpuFeatures = CpuFeatures(1 << 8);
+    pub const SSE4_1:    CpuFeatures = CpuFeatures(1 << 9);
+    pub const SSE4_2:    CpuFeatures = CpuFeatures(1 << 10);
+    pub const XSAVE:     CpuFeatures = CpuFeatures(1 << 11);
+    pub const AVX:       CpuFeatures = CpuFeatures(1 << 12);
+    pub const AVX2:      CpuFeatures = CpuFeatures(1 << 13);
+    pub const NX:        CpuFeatures = CpuFeatures(1 << 14);
+    pub const LONG_MODE: CpuFeatures = CpuFeatures(1 << 15);
+
+    /// Features the runtime itself cannot run without
+    #[cfg(not(feature = "soft-float"))]
+    pub const REQUIRED: CpuFeatures = CpuFeatures(
+        Self::FPU.0 | Self::FXSR.0 | Self::SSE.0 | Self::SSE2.0 | Self::LONG_MODE.0,
+    );
+    /// Features the runtime itself cannot run without (`soft-float`: no
+    /// floating-point unit is touched)
+    #[cfg(feature = "soft-float")]
+    pub const REQUIRED: CpuFeatures = Self::LONG_MODE;
+
+    pub const fn empty() -> Self {
+        CpuFeatures(0)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: CpuFeatures) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: CpuFeatures) {
+        self.0 |= other.0;
+    }
+
+    /// Decode raw `cpuid` output registers into a feature set
+    pub fn from_cpuid(leaf1_ecx: u32, leaf1_edx: u32, leaf7_ebx: u32, ext1_edx: u32) -> Self {
+        let mut f = CpuFeatures::empty();
+        let table: [(u32, u32, CpuFeatures); 16] = [
+            (leaf1_edx, 0,  Self::FPU),
+            (leaf1_edx, 4,  Self::TSC),
+            (leaf1_edx, 5,  Self::MSR),
+            (leaf1_edx, 9,  Self::APIC),
+            (leaf1_edx, 24, Self::FXSR),
+            (leaf1_edx, 25, Self::SSE),
+            (leaf1_edx, 26, Self::SSE2),
+            (leaf1_ecx, 0,  Self::SSE3),
+            (leaf1_ecx, 9,  Self::SSSE3),
+            (leaf1_ecx, 19, Self::SSE4_1),
+            (leaf1_ecx, 20, Self::SSE4_2),
+            (leaf1_ecx, 26, Self::XSAVE),
+            (leaf1_ecx, 28, Self::AVX),
+            (leaf7_ebx, 5,  Self::AVX2),
+            (ext1_edx,  20, Self::NX),
+            (ext1_edx,  29, Self::LONG_MODE),
+        ];
+        for &(reg, bit, feature) in table.iter() {
+            if reg & (1 << bit) != 0 {
+                f.insert(feature);
+            }
+        }
+        f
+    }
+}
+
+/// Query the executing CPU
+pub fn features() -> CpuFeatures {
+    unsafe {
+        let max_leaf = __cpuid(0).eax;
+        let leaf1 = __cpuid(1);
+        let leaf7_ebx = if max_leaf >= 7 { __cpuid_count(7, 0).ebx } else { 0 };
+
+        let max_ext = __cpuid(0x8000_0000).eax;
+        let ext1_edx = if max_ext >= 0x8000_0001 { __cpuid(0x8000_0001).edx } else { 0 };
+
+        CpuFeatures::from_cpuid(leaf1.ecx, leaf1.edx, leaf7_ebx, ext1_edx)
+    }
+}
+
+// `soft-float` skips `enable_sse`, so SSE instructions in a hard-float
+// build would fault with #UD (or silently use uninitialised state)
+#[cfg(all(feature = "soft-float", target_feature = "sse"))]
+compile_error!("the soft-float feature needs a soft-float target: build without SSE (e.g. x86_64-unknown-none)");
+
+/// Turn on the x87 FPU and SSE: clear CR0.EM/TS, set CR0.MP, set
+/// CR4.OSFXSR/OSXMMEXCPT, then reset the FPU. Panics if the CPU lacks SSE.
+///
+/// SAFETY: must run before any code that may use SSE registers, i.e.
+/// before the .data copy and .bss zeroing in `rust_start`.
+#[cfg(not(feature = "soft-float"))]
+pub unsafe fn enable_sse() {
+    let f = features();
+    if !f.contains(CpuFeatures::SSE) || !f.contains(CpuFeatures::FXSR) {
+        panic!("enable_sse: CPU does not support SSE/FXSR");
+    }
+
+    let mut cr0: u64;
+    asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    cr0 &= !(CR0_EM | CR0_TS);
+    cr0 |= CR0_MP;
+    asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags));
+
+    let mut cr4: u64;
+    asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    cr4 |= CR4_OSFXSR | CR4_OSXMMEXCPT;
+    asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
+
+    asm!("fninit", options(nomem, nostack));
+}
+
+/// Initial local APIC ID of the executing CPU (CPUID leaf 1, EBX[31:24]).
+/// Unique per logical CPU, but `cpuid` is serializing (and traps under a
+/// hypervisor), so it is not free.
+pub fn apic_id() -> u32 {
+    unsafe { __cpuid(1).ebx >> 24 }
+}
+
+/// IA32_APIC_BASE fields
+const APIC_BASE_BSP:    u64 = 1 << 8;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDR:   u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Decoded IA32_APIC_BASE MSR
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApicBase {
+    /// Physical address of the 4 KiB xAPIC MMIO page
+    pub base:    usize,
+    /// Read on the bootstrap processor
+    pub bsp:     bool,
+    /// x2APIC mode: registers are MSRs and the MMIO page is unused
+    pub x2apic:  bool,
+    /// The local APIC is globally enabled
+    pub enabled: bool,
+}
+
+impl ApicBase {
+    /// Decode a raw IA32_APIC_BASE value
+    pub const fn decode(msr: u64) -> Self {
+        ApicBase {
+            base:    (msr & APIC_BASE_ADDR) as usize,
+            bsp:     msr & APIC_BASE_BSP != 0,
+            x2apic:  msr & APIC_BASE_X2APIC != 0,
+            enabled: msr & APIC_BASE_ENABLE != 0,
+        }
+    }
+
+    /// The IA32_APIC_BASE value `decode` turns back into `self`
+    pub const fn encode(&self) -> u64 {
+        let mut msr = self.base as u64 & APIC_BASE_ADDR;
+        if self.bsp {
+            msr |= APIC_BASE_BSP;
+        }
+        if self.x2apic {
+            msr |= APIC_BASE_X2APIC;
+        }
+        if self.enabled {
+            msr |= APIC_BASE_ENABLE;
+        }
+        msr
+    }
+
+    /// Read IA32_APIC_BASE on the executing CPU, or `None` if CPUID
+    /// reports no local APIC (the MSR would #GP)
+    pub fn read() -> Option<Self> {
+        if !features().contains(CpuFeatures::APIC) {
+            return None;
+        }
+        // SAFETY: the MSR exists on every CPU with a local APIC
+        Some(Self::decode(unsafe { msr::read(msr::IA32_APIC_BASE) }))
+    }
+}
+
+/// Where `lapic_base` moves an xAPIC page it cannot reach (the reset
+/// default)
+pub const LAPIC_DEFAULT_BASE: usize = 0xFEE0_0000;
+
+/// End of the identity map every runtime page-table setup covers
+const LAPIC_REACHABLE: usize = 4 * 1024 * 1024 * 1024;
+
+/// IA32_APIC_BASE through `msrs`, with an xAPIC page at or above 4 GiB
+/// moved to `LAPIC_DEFAULT_BASE` (mode and BSP bit kept). `Err` with the
+/// decoded value, and nothing written, if the local APIC is disabled.
+///
+/// SAFETY: `msrs` must reach IA32_APIC_BASE of a CPU with a local APIC,
+/// and nothing may be using its MMIO page.
+unsafe fn locate_lapic(msrs: &impl MsrBackend) -> Result<ApicBase, ApicBase> {
+    let apic = ApicBase::decode(msrs.read(msr::IA32_APIC_BASE));
+    if !apic.enabled {
+        return Err(apic);
+    }
+    // in x2APIC mode the page is unused, so there is nothing to move
+    if apic.x2apic || apic.base < LAPIC_REACHABLE {
+        return Ok(apic);
+    }
+    let moved = ApicBase { base: LAPIC_DEFAULT_BASE, ..apic };
+    msrs.write(msr::IA32_APIC_BASE, moved.encode());
+    Ok(moved)
+}
+
+/// `lapic_base`, once looked up
+static LAPIC_BASE: Once<usize> = Once::new();
+
+/// Physical base of the local APIC's MMIO page, read from IA32_APIC_BASE
+/// on first use. It must lie in the identity-mapped low 4 GiB (default
+/// `0xFEE0_0000`) to be usable as is; a page above that is first moved
+/// to `LAPIC_DEFAULT_BASE`. Every core is expected to share the page, so
+/// one lookup serves all of them; the move is made on the calling CPU
+/// only (IA32_APIC_BASE is per core). Panics if the CPU has no local
+/// APIC or it is disabled.
+pub fn lapic_base() -> usize {
+    if let Some(&base) = LAPIC_BASE.get() {
+        return base;
+    }
+    // checked outside the `Once`: a panic inside it would leave it
+    // half-written for every later caller
+    if !features().contains(CpuFeatures::APIC) {
+        panic!("lapic_base: CPU has no local APIC");
+    }
+    // SAFETY: the MSR exists on every CPU with a local APIC, and nothing
+    // has used the page before its base is first handed out
+    match unsafe { locate_lapic(&msr::HardwareMsrs) } {
+        Ok(apic)  => *LAPIC_BASE.get_or_init(|| apic.base),
+        Err(apic) => {
+            panic!("lapic_base: local APIC disabled in IA32_APIC_BASE (base {:#x})", apic.base)
+        }
+    }
+}
+
+/// Space-separated list of the features present, e.g. `FPU TSC SSE2`
+impl fmt::Display for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const NAMES: [(CpuFeatures, &str); 16] = [
+            (CpuFeatures::FPU,       "FPU"),
+            (CpuFeatures::TSC,       "TSC"),
+            (CpuFeatures::MSR,       "MSR"),
+            (CpuFeatures::APIC,      "APIC"),
+            (CpuFeatures::FXSR,      "FXSR"),
+            (CpuFeatures::SSE,       "SSE"),
+            (CpuFeatures::SSE2,      "SSE2"),
+            (CpuFeatures::SSE3,      "SSE3"),
+            (CpuFeatures::SSSE3,     "SSSE3"),
+            (CpuFeatures::SSE4_1,    "SSE4.1"),
+            (CpuFeatures::SSE4_2,    "SSE4.2"),
+            (CpuFeatures::XSAVE,     "XSAVE"),
+            (CpuFeatures::AVX,       "AVX"),
+            (CpuFeatures::AVX2,      "AVX2"),
+            (CpuFeatures::NX,        "NX"),
+            (CpuFeatures::LONG_MODE, "LM"),
+        ];
+        let mut first = true;
+        for &(feature, name) in NAMES.iter() {
+            if self.contains(feature) {
+                if !first {
+                    f.write_str(" ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the time-stamp counter. Every x86_64 CPU has one; its rate is not
+/// calibrated here, so values are only good for relative measurements.
+pub fn rdtsc() -> u64 {
+    let (lo, hi): (u32, u32);
+    unsafe {
+        asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    }
+    (hi as u64) << 32 | lo as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn from_cpuid_decodes_each_register() {
+        let leaf1_ecx = 1;                        // SSE3
+        let leaf1_edx = 1 | 1 << 25 | 1 << 26;    // FPU, SSE, SSE2
+        let leaf7_ebx = 1 << 5;                   // AVX2
+        let ext1_edx  = 1 << 20 | 1 << 29;        // NX, LM
+        let f = CpuFeatures::from_cpuid(leaf1_ecx, leaf1_edx, leaf7_ebx, ext1_edx);
+        for feature in [
+            CpuFeatures::FPU, CpuFeatures::SSE, CpuFeatures::SSE2, CpuFeatures::SSE3,
+            CpuFeatures::AVX2, CpuFeatures::NX, CpuFeatures::LONG_MODE,
+        ] {
+            assert!(f.contains(feature));
+        }
+        assert!(!f.contains(CpuFeatures::AVX));
+        assert!(!f.contains(CpuFeatures::TSC));
+    }
+
+    #[test_case]
+    fn from_cpuid_of_nothing_is_empty() {
+        assert_eq!(CpuFeatures::from_cpuid(0, 0, 0, 0), CpuFeatures::empty());
+        // the same bit in another leaf means something else
+        let f = CpuFeatures::from_cpuid(0, 0, 0, 1);
+        assert_eq!(f, CpuFeatures::empty());
+    }
+
+    /// IA32_APIC_BASE as reset leaves it on the BSP
+    const APIC_BASE_RESET: u64 = 0xFEE0_0000 | APIC_BASE_ENABLE | APIC_BASE_BSP;
+
+    #[test_case]
+    fn apic_base_decodes_each_field() {
+        let apic = ApicBase::decode(APIC_BASE_RESET);
+        assert_eq!(apic, ApicBase { base: 0xFEE0_0000, bsp: true, x2apic: false, enabled: true });
+        // reserved bit 9 is ignored
+        let apic = ApicBase::decode(0x12_3456_7000 | 1 << 9 | APIC_BASE_X2APIC);
+        let want = ApicBase { base: 0x12_3456_7000, bsp: false, x2apic: true, enabled: false };
+        assert_eq!(apic, want);
+    }
+
+    #[test_case]
+    fn apic_base_encode_round_trips() {
+        for msr in [
+            APIC_BASE_RESET,
+            0xFEE0_0000 | APIC_BASE_ENABLE,
+            0x8_0000_0000 | APIC_BASE_ENABLE | APIC_BASE_X2APIC,
+            0x000F_FFFF_FFFF_F000,
+            0,
+        ] {
+            assert_eq!(ApicBase::decode(msr).encode(), msr, "{:#x}", msr);
+        }
+    }
+
+    #[test_case]
+    fn lapic_in_the_low_4_gib_stays_put() {
+        let msrs = msr::MockMsrs::new(&[(msr::IA32_APIC_BASE, APIC_BASE_RESET)]);
+        let apic = unsafe { locate_lapic(&msrs) }.unwrap();
+        assert_eq!(apic.base, 0xFEE0_0000);
+        assert_eq!(msrs.writes(), 0);
+    }
+
+    #[test_case]
+    fn lapic_above_4_gib_is_relocated() {
+        let high = 0x1_0000_0000 | APIC_BASE_ENABLE | APIC_BASE_BSP;
+        let msrs = msr::MockMsrs::new(&[(msr::IA32_APIC_BASE, high)]);
+        let apic = unsafe { locate_lapic(&msrs) }.unwrap();
+        assert_eq!(apic.base, LAPIC_DEFAULT_BASE);
+        assert_eq!(msrs.value(msr::IA32_APIC_BASE), APIC_BASE_RESET);
+        assert_eq!(msrs.writes(), 1);
+    }
+
+    #[test_case]
+    fn lapic_in_x2apic_mode_or_disabled_is_not_moved() {
+        let x2apic = 0x1_0000_0000 | APIC_BASE_ENABLE | APIC_BASE_X2APIC;
+        let msrs = msr::MockMsrs::new(&[(msr::IA32_APIC_BASE, x2apic)]);
+        assert_eq!(unsafe { locate_lapic(&msrs) }.unwrap().base, 0x1_0000_0000);
+        let msrs = msr::MockMsrs::new(&[(msr::IA32_APIC_BASE, 0x1_0000_0000)]);
+        assert_eq!(unsafe { locate_lapic(&msrs) }, Err(ApicBase::decode(0x1_0000_0000)));
+        assert_eq!(msrs.writes(), 0);
+    }
+}
@@ -0,0 +1,209 @@
+// rooster-os/arch/x86/runtime/rs_runtime/delay.rs
+//! Calibrated busy-waits on the TSC.
+//!   • `calibrate` times a 10 ms PIT channel 2 one-shot (gated through
+//!     port 0x61, so no IRQ or IDT entry is involved) against `rdtsc`
+//!     and stores the TSC rate; `rust_start` runs it once
+//!   • `delay_ms` then spins on `rdtsc`; `tsc_khz` / `tsc_hz` report the
+//!     measured rate
+//!   • Without a working PIT (some hypervisors leave it out) the count
+//!     never ends: calibration gives up, warns, and `delay_ms` falls back
+//!     to `reboot::io_delay_us`, which is only roughly 1 µs per step
+//!   • `beep` plays a tone on the PC speaker from PIT channel 2 for a
+//!     `delay_ms` wait (`panic-beep` sounds one on panic)
+//!
+//! The TSC is assumed to tick at a constant rate (every CPU the runtime
+//! targets in practice; CPUID 0x8000_0007 EDX.8 is not checked).
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::port::{inb, outb};
+use crate::{cpu, logging, reboot};
+
+/// PIT input clock
+pub const PIT_HZ: u32 = 1_193_182;
+
+/// Length of the calibration window
+const CALIBRATE_MS: u32 = 10;
+/// PIT ticks in the window
+const CALIBRATE_TICKS: u16 = (PIT_HZ / (1000 / CALIBRATE_MS)) as u16;
+/// Port 0x61 polls before deciding the PIT is absent (each `inb` takes
+/// about 1 µs, so this is far more than 10 ms)
+const CALIBRATE_MAX_POLLS: u32 = 1_000_000;
+
+// PIT channel 2 data port and its mode byte (lobyte/hibyte, mode 0:
+// OUT2 goes high when the count reaches zero)
+const PIT_CH2:         u16 = 0x42;
+const PIT_CMD:         u16 = 0x43;
+const PIT_CH2_ONESHOT: u8  = 0xB0;
+// ...and for a tone (lobyte/hibyte, mode 3: square wave)
+const PIT_CH2_SQUARE:  u8  = 0xB6;
+
+// Port 0x61 (NMI status and control): channel 2 gate, speaker, OUT2
+const PORT_B:         u16 = 0x61;
+const PORT_B_GATE2:   u8  = 1 << 0;
+const PORT_B_SPEAKER: u8  = 1 << 1;
+const PORT_B_OUT2:    u8  = 1 << 5;
+
+/// Measured TSC cycles per millisecond (= kHz); 0 until calibrated
+static CYCLES_PER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// TSC rate from `cycles` counted over `pit_ticks` PIT ticks, in cycles
+/// per millisecond. 0 for an empty window.
+pub const fn cycles_per_ms(cycles: u64, pit_ticks: u16) -> u64 {
+    if pit_ticks == 0 {
+        return 0;
+    }
+    // cycles / (pit_ticks / PIT_HZ s) / 1000; u128 so a fast TSC over a
+    // long window cannot overflow
+    (cycles as u128 * PIT_HZ as u128 / (pit_ticks as u128 * 1000)) as u64
+}
+
+/// TSC cycles in `ms` milliseconds at `cycles_per_ms` (saturating)
+pub const fn ms_to_cycles(ms: u32, cycles_per_ms: u64) -> u64 {
+    cycles_per_ms.saturating_mul(ms as u64)
+}
+
+/// Measure the TSC against PIT channel 2 and remember the result. Returns
+/// the rate in cycles per millisecond, or `None` (and logs a warning) if
+/// the PIT never finished counting.
+///
+/// SAFETY: nothing else may be using PIT channel 2 or the speaker.
+pub unsafe fn calibrate() -> Option<u64> {
+    // gate on, speaker off, then load the one-shot count (counting starts
+    // once the high byte is written)
+    let port_b = inb(PORT_B);
+    outb(PORT_B, (port_b & !PORT_B_SPEAKER) | PORT_B_GATE2);
+    outb(PIT_CMD, PIT_CH2_ONESHOT);
+    outb(PIT_CH2, (CALIBRATE_TICKS & 0xFF) as u8);
+    outb(PIT_CH2, (CALIBRATE_TICKS >> 8) as u8);
+
+    let start = cpu::rdtsc();
+    let mut polls = 0;
+    while inb(PORT_B) & PORT_B_OUT2 == 0 {
+        polls += 1;
+        if polls == CALIBRATE_MAX_POLLS {
+            outb(PORT_B, port_b);
+            logging::warn!("delay: PIT not counting, delays are approximate");
+            return None;
+        }
+    }
+    let elapsed = cpu::rdtsc().wrapping_sub(start);
+    outb(PORT_B, port_b);
+
+    let rate = cycles_per_ms(elapsed, CALIBRATE_TICKS);
+    CYCLES_PER_MS.store(rate, Ordering::Relaxed);
+    Some(rate)
+}
+
+/// Measured TSC rate in kHz (cycles per millisecond), if calibrated
+pub fn tsc_khz() -> Option<u64> {
+    match CYCLES_PER_MS.load(Ordering::Relaxed) {
+        0    => None,
+        rate => Some(rate),
+    }
+}
+
+/// Measured TSC rate in Hz, if calibrated
+pub fn tsc_hz() -> Option<u64> {
+    tsc_khz().map(|khz| khz * 1000)
+}
+
+/// PIT reload value for a `freq_hz` square wave, rounded to the nearest
+/// divisor and clamped to what the 16-bit counter can produce (mode 3
+/// needs at least 2). 0 Hz gives 0: no tone.
+pub const fn pit_divisor(freq_hz: u32) -> u16 {
+    if freq_hz == 0 {
+        return 0;
+    }
+    let divisor = (PIT_HZ + freq_hz / 2) / freq_hz;
+    if divisor > u16::MAX as u32 {
+        u16::MAX
+    } else if divisor < 2 {
+        2
+    } else {
+        divisor as u16
+    }
+}
+
+/// Sound `freq_hz` on the PC speaker for `duration_ms` (just wait for
+/// 0 Hz). Takes over PIT channel 2, so not while `calibrate` runs; does
+/// not allocate, so the panic path may use it.
+pub fn beep(freq_hz: u32, duration_ms: u32) {
+    let divisor = pit_divisor(freq_hz);
+    if divisor == 0 {
+        delay_ms(duration_ms);
+        return;
+    }
+    // SAFETY: channel 2 and the speaker belong to the runtime; port B is
+    // restored (speaker off) afterwards
+    unsafe {
+        outb(PIT_CMD, PIT_CH2_SQUARE);
+        outb(PIT_CH2, (divisor & 0xFF) as u8);
+        outb(PIT_CH2, (divisor >> 8) as u8);
+        let port_b = inb(PORT_B);
+        outb(PORT_B, port_b | PORT_B_GATE2 | PORT_B_SPEAKER);
+        delay_ms(duration_ms);
+        outb(PORT_B, port_b & !PORT_B_SPEAKER);
+    }
+}
+
+/// Busy-wait at least `ms` milliseconds (only roughly, if `calibrate`
+/// found no PIT)
+pub fn delay_ms(ms: u32) {
+    match tsc_khz() {
+        Some(rate) => {
+            let start = cpu::rdtsc();
+            let wait = ms_to_cycles(ms, rate);
+            while cpu::rdtsc().wrapping_sub(start) < wait {
+                core::hint::spin_loop();
+            }
+        }
+        None => reboot::io_delay_us(ms as usize * 1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn calibration_window_is_10_ms_of_pit_ticks() {
+        assert_eq!(CALIBRATE_TICKS, 11931);
+    }
+
+    #[test_case]
+    fn tsc_rate_scales_by_the_pit_clock() {
+        // 1000 TSC cycles per PIT tick is PIT_HZ cycles per millisecond
+        assert_eq!(cycles_per_ms(CALIBRATE_TICKS as u64 * 1000, CALIBRATE_TICKS), PIT_HZ as u64);
+        // 3 GHz over the calibration window, less what truncation drops
+        let cycles = 3_000_000_000 * CALIBRATE_TICKS as u64 / PIT_HZ as u64;
+        assert_eq!(cycles_per_ms(cycles, CALIBRATE_TICKS) / 1000, 2999);
+        assert_eq!(cycles_per_ms(12345, 0), 0);
+    }
+
+    #[test_case]
+    fn ms_to_cycles_saturates() {
+        assert_eq!(ms_to_cycles(10, 3_000_000), 30_000_000);
+        assert_eq!(ms_to_cycles(0, 3_000_000), 0);
+        assert_eq!(ms_to_cycles(u32::MAX, u64::MAX / 2), u64::MAX);
+    }
+
+    #[test_case]
+    fn pit_divisor_rounds_to_the_nearest_reload_value() {
+        assert_eq!(pit_divisor(1000), 1193);
+        assert_eq!(pit_divisor(440), 2712);
+        assert_eq!(pit_divisor(19), 62799);
+    }
+
+    #[test_case]
+    fn pit_divisor_clamps_to_the_counter_range() {
+        // no tone
+        assert_eq!(pit_divisor(0), 0);
+        // too low for 16 bits
+        assert_eq!(pit_divisor(1), u16::MAX);
+        assert_eq!(pit_divisor(18), u16::MAX);
+        // mode 3 needs a reload of at least 2
+        assert_eq!(pit_divisor(1_000_000), 2);
+        assert_eq!(pit_divisor(u32::MAX), 2);
+    }
+}
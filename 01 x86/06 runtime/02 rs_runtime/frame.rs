@@ -0,0 +1,222 @@
+// rooster-os/arch/x86/runtime/rs_runtime/frame.rs
+//! Physical 4 KiB frame allocation from the boot memory map.
+//!   • `FrameAllocator` walks the usable regions of a normalized map in
+//!     address order and hands out each whole frame once
+//!   • Frames only partly inside a usable region, or touching a region of
+//!     any other kind, are never returned
+//!   • `mark_used` keeps ranges (kernel image, heap, boot data) out; the
+//!     runtime seeds those in `init_runtime`
+//!   • Frames are not given back: freeing is for a later allocator
+//!     (e.g. `BuddyAllocator`) fed from here
+
+use crate::multiboot::{MemoryMap, RegionKind};
+use crate::paging::PAGE_SIZE;
+use crate::runtime_core::sync::Spinlock;
+
+const FRAME: u64 = PAGE_SIZE as u64;
+
+/// Most ranges `mark_used` can hold
+pub const MAX_USED_RANGES: usize = 32;
+
+/// A 4 KiB-aligned physical frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PhysFrame {
+    start: u64,
+}
+
+impl PhysFrame {
+    /// The frame `addr` lies in
+    pub const fn containing(addr: u64) -> Self {
+        PhysFrame { start: addr & !(FRAME - 1) }
+    }
+
+    pub const fn start_address(&self) -> u64 {
+        self.start
+    }
+
+    pub const fn size(&self) -> u64 {
+        FRAME
+    }
+}
+
+/// Hands out the free frames of a memory map, lowest first
+pub struct FrameAllocator {
+    map:    MemoryMap,
+    used:   [(u64, u64); MAX_USED_RANGES],
+    n_used: usize,
+    /// Index into `map` of the region being handed out
+    region: usize,
+    /// No frame below this is handed out (again)
+    next:   u64,
+}
+
+impl FrameAllocator {
+    /// An allocator with no frames
+    pub const fn empty() -> Self {
+        FrameAllocator {
+            map:    MemoryMap::new(),
+            used:   [(0, 0); MAX_USED_RANGES],
+            n_used: 0,
+            region: 0,
+            next:   0,
+        }
+    }
+
+    /// Serve the usable RAM in `map`
+    pub fn new(map: &MemoryMap) -> Self {
+        FrameAllocator { map: map.normalized(), ..FrameAllocator::empty() }
+    }
+
+    /// Never hand out a frame overlapping `[start, end)`. Frames already
+    /// returned are not recalled. False if `MAX_USED_RANGES` are taken.
+    pub fn mark_used(&mut self, start: u64, end: u64) -> bool {
+        if start >= end {
+            return true;
+        }
+        if self.n_used == MAX_USED_RANGES {
+            return false;
+        }
+        self.used[self.n_used] = (start, end);
+        self.n_used += 1;
+        true
+    }
+
+    /// End of the furthest range (a used range or a region that is not
+    /// usable) overlapping the frame at `addr`, if any
+    fn blocked_until(&self, addr: u64) -> Option<u64> {
+        let end = addr + FRAME;
+        let used = self.used[..self.n_used].iter().copied();
+        let other = self.map
+            .iter()
+            .filter(|r| r.kind != RegionKind::Usable)
+            .map(|r| (r.base, r.base.saturating_add(r.length)));
+        used.chain(other)
+            .filter(|&(s, e)| s < end && addr < e)
+            .map(|(_, e)| e)
+            .max()
+    }
+
+    /// The next free frame, or `None` once the map is used up
+    pub fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        while let Some(&r) = self.map.regions().get(self.region) {
+            if r.kind == RegionKind::Usable {
+                // only whole frames inside the region
+                let first = r.base.checked_add(FRAME - 1).map(|a| a & !(FRAME - 1));
+                let end   = r.base.saturating_add(r.length) & !(FRAME - 1);
+                let mut addr = first.unwrap_or(end).max(self.next);
+                while addr < end {
+                    match self.blocked_until(addr) {
+                        Some(skip) => {
+                            addr = match skip.checked_add(FRAME - 1) {
+                                Some(a) => a & !(FRAME - 1),
+                                None    => end,
+                            };
+                        }
+                        None => {
+                            self.next = addr + FRAME;
+                            return Some(PhysFrame { start: addr });
+                        }
+                    }
+                }
+            }
+            self.region += 1;
+        }
+        None
+    }
+}
+
+/// The runtime's frame allocator, seeded by `init_runtime`
+static FRAMES: Spinlock<FrameAllocator> = Spinlock::new(FrameAllocator::empty());
+
+/// Replace the global allocator's state with `frames`
+pub fn init(frames: FrameAllocator) {
+    *FRAMES.lock_irqsave() = frames;
+}
+
+/// The next free physical frame from the boot memory map
+pub fn allocate_frame() -> Option<PhysFrame> {
+    FRAMES.lock_irqsave().allocate_frame()
+}
+
+/// Keep `[start, end)` out of `allocate_frame`; see
+/// `FrameAllocator::mark_used`
+pub fn mark_used(start: u64, end: u64) -> bool {
+    FRAMES.lock_irqsave().mark_used(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiboot::MemoryRegion;
+
+    fn region(base: u64, length: u64, kind: RegionKind) -> MemoryRegion {
+        MemoryRegion { base, length, kind }
+    }
+
+    /// Every frame `frames` hands out, lowest first (at most 64)
+    fn drain(frames: &mut FrameAllocator) -> ([u64; 64], usize) {
+        let mut out = [0; 64];
+        let mut n = 0;
+        while let Some(frame) = frames.allocate_frame() {
+            out[n] = frame.start_address();
+            n += 1;
+        }
+        (out, n)
+    }
+
+    #[test_case]
+    fn used_ranges_are_never_returned() {
+        let mut map = MemoryMap::new();
+        map.push(region(0x10000, 0x10000, RegionKind::Usable));
+        let mut frames = FrameAllocator::new(&map);
+        // one frame, two frames through a partial overlap at each end,
+        // and a range starting before the region
+        assert!(frames.mark_used(0x12000, 0x13000));
+        assert!(frames.mark_used(0x14800, 0x15001));
+        assert!(frames.mark_used(0x0, 0x10001));
+
+        let (out, n) = drain(&mut frames);
+        assert_eq!(
+            &out[..n],
+            &[0x11000, 0x13000, 0x16000, 0x17000, 0x18000, 0x19000, 0x1a000, 0x1b000,
+              0x1c000, 0x1d000, 0x1e000, 0x1f000]
+        );
+        for &frame in &out[..n] {
+            for &(start, end) in &[(0x12000, 0x13000), (0x14800, 0x15001), (0x0, 0x10001)] {
+                assert!(frame + FRAME <= start || frame >= end);
+            }
+        }
+    }
+
+    #[test_case]
+    fn partial_frames_at_region_edges_are_skipped() {
+        let mut map = MemoryMap::new();
+        map.push(region(0x100, 0x3000, RegionKind::Usable)); // whole: 0x1000, 0x2000
+        map.push(region(0x5000, 0x800, RegionKind::Usable)); // no whole frame
+        let mut frames = FrameAllocator::new(&map);
+        let (out, n) = drain(&mut frames);
+        assert_eq!(&out[..n], &[0x1000, 0x2000]);
+    }
+
+    #[test_case]
+    fn frames_touching_other_kinds_are_skipped() {
+        let mut map = MemoryMap::new();
+        map.push(region(0x10000, 0x4000, RegionKind::Usable));
+        map.push(region(0x11800, 0x100, RegionKind::Reserved));
+        let mut frames = FrameAllocator::new(&map);
+        let (out, n) = drain(&mut frames);
+        assert_eq!(&out[..n], &[0x10000, 0x12000, 0x13000]);
+    }
+
+    #[test_case]
+    fn mark_used_fills_up() {
+        let mut frames = FrameAllocator::empty();
+        for i in 0..MAX_USED_RANGES as u64 {
+            assert!(frames.mark_used(i * FRAME, (i + 1) * FRAME));
+        }
+        assert!(!frames.mark_used(0x100000, 0x101000));
+        // empty ranges take no slot
+        assert!(frames.mark_used(5, 5));
+        assert_eq!(frames.allocate_frame(), None);
+    }
+}
@@ -0,0 +1,408 @@
+// rooster-os/arch/x86/runtime/rs_runtime/framebuffer.rs
+//! Drawing on the linear framebuffer from `BootInfo::framebuffer`.
+//!   • `init` accepts a direct-colour framebuffer of 15/16/24/32 bpp that
+//!     the active page tables identity-map (the runtime's tables cover the
+//!     low 4 GiB, where loaders put it); nothing is remapped
+//!   • `put_pixel` / `fill_rect` take `0xRRGGBB` and convert it to the
+//!     framebuffer's channel layout; coordinates are checked against
+//!     width/height (rectangles are clipped)
+//!   • `set_console(&framebuffer::CONSOLE)` renders `print!` and panic
+//!     output as text in the built-in 8x16 font, scrolling at the bottom
+
+use core::fmt;
+use core::ptr::write_volatile;
+
+use crate::multiboot::{Framebuffer, FramebufferKind};
+use crate::runtime_core::sync::Once;
+use crate::{font, paging, Console, Spinlock};
+
+/// Why `init` rejected the framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FbError {
+    /// Palette-indexed or EGA text mode
+    NotRgb,
+    /// A depth other than 15, 16, 24 or 32 bits per pixel
+    UnsupportedBpp(u8),
+    /// Not identity-mapped at this address
+    Unmapped(u64),
+}
+
+impl fmt::Display for FbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FbError::NotRgb => write!(f, "framebuffer is not direct-colour"),
+            FbError::UnsupportedBpp(bpp) => write!(f, "unsupported framebuffer depth: {} bpp", bpp),
+            FbError::Unmapped(addr) => write!(f, "framebuffer at {:#x} is not identity-mapped", addr),
+        }
+    }
+}
+
+/// A validated framebuffer: geometry plus where each channel goes
+#[derive(Clone, Copy, Debug)]
+pub struct Surface {
+    addr:   usize,
+    pitch:  usize,
+    width:  u32,
+    height: u32,
+    /// Bytes per pixel
+    bytes:  usize,
+    /// (position, size) of red, green and blue
+    red:    (u8, u8),
+    green:  (u8, u8),
+    blue:   (u8, u8),
+}
+
+impl Surface {
+    /// Check `fb` can be drawn on.
+    ///
+    /// SAFETY: `fb` must describe the framebuffer the loader set up, and
+    /// nothing else may own that memory.
+    pub unsafe fn new(fb: &Framebuffer) -> Result<Self, FbError> {
+        let (red, green, blue) = match fb.kind {
+            FramebufferKind::Rgb { red_pos, red_size, green_pos, green_size, blue_pos, blue_size } => {
+                ((red_pos, red_size), (green_pos, green_size), (blue_pos, blue_size))
+            }
+            _ => return Err(FbError::NotRgb),
+        };
+        if !matches!(fb.bpp, 15 | 16 | 24 | 32) {
+            return Err(FbError::UnsupportedBpp(fb.bpp));
+        }
+        let len = fb.pitch as u64 * fb.height as u64;
+        for addr in [fb.addr, fb.addr + len.max(1) - 1] {
+            if paging::translate(addr as usize) != Some(addr) {
+                return Err(FbError::Unmapped(addr));
+            }
+        }
+        Ok(Surface {
+            addr:   fb.addr as usize,
+            pitch:  fb.pitch as usize,
+            width:  fb.width,
+            height: fb.height,
+            bytes:  (fb.bpp as usize + 7) / 8,
+            red,
+            green,
+            blue,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// `0xRRGGBB` in this framebuffer's pixel format
+    fn encode(&self, rgb: u32) -> u32 {
+        let channel = |value: u32, (pos, size): (u8, u8)| {
+            let size = size.min(8) as u32;
+            ((value & 0xff) >> (8 - size)) << pos
+        };
+        channel(rgb >> 16, self.red) | channel(rgb >> 8, self.green) | channel(rgb, self.blue)
+    }
+
+    /// Store an encoded pixel; the caller has checked `x`/`y`
+    fn write(&self, x: u32, y: u32, pixel: u32) {
+        let at = self.addr + y as usize * self.pitch + x as usize * self.bytes;
+        // SAFETY: inside the framebuffer `new` validated
+        unsafe {
+            match self.bytes {
+                4 => write_volatile(at as *mut u32, pixel),
+                3 => {
+                    for (i, byte) in pixel.to_le_bytes()[..3].iter().enumerate() {
+                        write_volatile((at + i) as *mut u8, *byte);
+                    }
+                }
+                _ => write_volatile(at as *mut u16, pixel as u16),
+            }
+        }
+    }
+
+    /// Set pixel (`x`, `y`) to `rgb`; false if it lies off the screen
+    pub fn put_pixel(&self, x: u32, y: u32, rgb: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        self.write(x, y, self.encode(rgb));
+        true
+    }
+
+    /// Fill the `w` x `h` rectangle at (`x`, `y`), clipped to the screen
+    pub fn fill_rect(&self, x: u32, y: u32, w: u32, h: u32, rgb: u32) {
+        let pixel = self.encode(rgb);
+        let x_end = x.saturating_add(w).min(self.width);
+        let y_end = y.saturating_add(h).min(self.height);
+        for row in y..y_end {
+            for col in x..x_end {
+                self.write(col, row, pixel);
+            }
+        }
+    }
+
+    /// Move pixel rows `[from, height)` up to start at row `to`
+    fn scroll_rows(&self, from: u32, to: u32) {
+        let len = (self.height - from) as usize * self.pitch;
+        // SAFETY: both ranges lie inside the framebuffer; `copy` allows
+        // the overlap
+        unsafe {
+            core::ptr::copy(
+                (self.addr + from as usize * self.pitch) as *const u8,
+                (self.addr + to as usize * self.pitch) as *mut u8,
+                len,
+            );
+        }
+    }
+}
+
+static SURFACE: Once<Surface> = Once::new();
+
+/// Validate `fb` and make it the target of `put_pixel`, `fill_rect` and
+/// `CONSOLE`. Only the first successful call takes effect.
+///
+/// SAFETY: as for `Surface::new`.
+pub unsafe fn init(fb: &Framebuffer) -> Result<(), FbError> {
+    let surface = Surface::new(fb)?;
+    let _ = SURFACE.set(surface);
+    Ok(())
+}
+
+/// The framebuffer set up by `init`, if any
+pub fn surface() -> Option<&'static Surface> {
+    SURFACE.get()
+}
+
+/// `Surface::put_pixel` on the framebuffer from `init`; false before
+/// `init` or off the screen
+pub fn put_pixel(x: u32, y: u32, rgb: u32) -> bool {
+    surface().is_some_and(|s| s.put_pixel(x, y, rgb))
+}
+
+/// `Surface::fill_rect` on the framebuffer from `init`; nothing before it
+pub fn fill_rect(x: u32, y: u32, w: u32, h: u32, rgb: u32) {
+    if let Some(s) = surface() {
+        s.fill_rect(x, y, w, h, rgb);
+    }
+}
+
+/// Text cursor over the framebuffer, in character cells
+pub struct TextConsole {
+    col: u32,
+    row: u32,
+    fg:  u32,
+    bg:  u32,
+}
+
+impl TextConsole {
+    pub const fn new(fg: u32, bg: u32) -> Self {
+        TextConsole { col: 0, row: 0, fg, bg }
+    }
+
+    pub fn set_colors(&mut self, fg: u32, bg: u32) {
+        self.fg = fg;
+        self.bg = bg;
+    }
+
+    fn draw_glyph(&self, s: &Surface, byte: u8) {
+        let (fg, bg) = (s.encode(self.fg), s.encode(self.bg));
+        let x0 = self.col * font::WIDTH as u32;
+        let y0 = self.row * font::HEIGHT as u32;
+        for (dy, bits) in font::glyph(byte).iter().enumerate() {
+            for dx in 0..font::WIDTH {
+                let pixel = if bits & (0x80 >> dx) != 0 { fg } else { bg };
+                s.write(x0 + dx as u32, y0 + dy as u32, pixel);
+            }
+        }
+    }
+
+    fn new_line(&mut self, s: &Surface) {
+        let rows = s.height / font::HEIGHT as u32;
+        self.col = 0;
+        if self.row + 1 < rows {
+            self.row += 1;
+            return;
+        }
+        let line = font::HEIGHT as u32;
+        s.scroll_rows(line, 0);
+        s.fill_rect(0, (rows - 1) * line, s.width, s.height - (rows - 1) * line, self.bg);
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        let s = match surface() {
+            Some(s) => s,
+            None    => return,
+        };
+        let cols = s.width / font::WIDTH as u32;
+        if cols == 0 || s.height < font::HEIGHT as u32 {
+            return;
+        }
+        match byte {
+            b'\n' => self.new_line(s),
+            b'\r' => self.col = 0,
+            byte  => {
+                if self.col >= cols {
+                    self.new_line(s);
+                }
+                self.draw_glyph(s, byte);
+                self.col += 1;
+            }
+        }
+    }
+
+    /// Paint the whole screen in the background colour and home the cursor
+    pub fn clear(&mut self) {
+        if let Some(s) = surface() {
+            s.fill_rect(0, 0, s.width, s.height, self.bg);
+        }
+        self.col = 0;
+        self.row = 0;
+    }
+}
+
+impl fmt::Write for TextConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Light grey on black, like the VGA text console
+pub static CONSOLE: Spinlock<TextConsole> = Spinlock::new(TextConsole::new(0xAAAAAA, 0x000000));
+
+/// `set_console(&framebuffer::CONSOLE)` routes `print!`/`println!` here.
+/// While panicking a held lock means we panicked mid-print: skip the
+/// output rather than deadlock.
+impl Console for Spinlock<TextConsole> {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        if crate::runtime_core::panic::in_progress() {
+            match self.try_lock() {
+                Some(mut console) => fmt::Write::write_str(&mut *console, s),
+                None              => Ok(()),
+            }
+        } else {
+            fmt::Write::write_str(&mut *self.lock(), s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XRGB: [(u8, u8); 3] = [(16, 8), (8, 8), (0, 8)];
+    const RGB565: [(u8, u8); 3] = [(11, 5), (5, 6), (0, 5)];
+
+    /// A surface over `buf`, laid out like a loader's framebuffer
+    fn surface(
+        buf: &mut [u32],
+        width: u32,
+        height: u32,
+        pitch: usize,
+        bytes: usize,
+        [red, green, blue]: [(u8, u8); 3],
+    ) -> Surface {
+        assert!(height as usize * pitch <= buf.len() * 4);
+        Surface { addr: buf.as_mut_ptr() as usize, pitch, width, height, bytes, red, green, blue }
+    }
+
+    fn bytes(buf: &[u32]) -> &[u8] {
+        // SAFETY: same memory, viewed as bytes
+        unsafe { core::slice::from_raw_parts(buf.as_ptr() as *const u8, buf.len() * 4) }
+    }
+
+    #[test_case]
+    fn encode_packs_each_channel_at_its_position() {
+        let mut buf = [0u32; 1];
+        let xrgb = surface(&mut buf, 1, 1, 4, 4, XRGB);
+        assert_eq!(xrgb.encode(0x123456), 0x123456);
+        let bgrx = surface(&mut buf, 1, 1, 4, 4, [(8, 8), (16, 8), (24, 8)]);
+        assert_eq!(bgrx.encode(0x123456), 0x5634_1200);
+        // narrower channels keep their top bits
+        let rgb565 = surface(&mut buf, 1, 1, 2, 2, RGB565);
+        assert_eq!(rgb565.encode(0xff0000), 0xf800);
+        assert_eq!(rgb565.encode(0x00ff00), 0x07e0);
+        assert_eq!(rgb565.encode(0x0000ff), 0x001f);
+        assert_eq!(rgb565.encode(0x808080), 0x8410);
+        let rgb555 = surface(&mut buf, 1, 1, 2, 2, [(10, 5), (5, 5), (0, 5)]);
+        assert_eq!(rgb555.encode(0xffffff), 0x7fff);
+    }
+
+    #[test_case]
+    fn pixels_land_at_row_times_pitch_plus_column() {
+        // 3 pixels wide, with a padded 16-byte pitch
+        let mut buf = [0u32; 8];
+        let s = surface(&mut buf, 3, 2, 16, 4, XRGB);
+        assert!(s.put_pixel(2, 1, 0xabcdef));
+        assert!(!s.put_pixel(3, 0, 0xffffff));
+        assert!(!s.put_pixel(0, 2, 0xffffff));
+        assert_eq!(buf, [0, 0, 0, 0, 0, 0, 0xabcdef, 0]);
+    }
+
+    #[test_case]
+    fn three_byte_pixels_leave_their_neighbours_alone() {
+        let mut buf = [u32::MAX; 3];
+        let s = surface(&mut buf, 4, 1, 12, 3, XRGB);
+        s.put_pixel(1, 0, 0x102030);
+        assert_eq!(&bytes(&buf)[..8], &[0xff, 0xff, 0xff, 0x30, 0x20, 0x10, 0xff, 0xff]);
+    }
+
+    #[test_case]
+    fn fill_rect_is_clipped_to_the_screen() {
+        let mut buf = [0u32; 16];
+        let s = surface(&mut buf, 4, 4, 16, 4, XRGB);
+        s.fill_rect(2, 3, 10, u32::MAX, 0x0000ff);
+        for (i, &pixel) in buf.iter().enumerate() {
+            assert_eq!(pixel, if i >= 14 { 0xff } else { 0 }, "pixel {}", i);
+        }
+    }
+
+    #[test_case]
+    fn glyphs_follow_the_font_bits() {
+        let mut buf = [0u32; 2 * font::WIDTH * font::HEIGHT];
+        let s = surface(&mut buf, 16, 16, 64, 4, XRGB);
+        let mut console = TextConsole::new(0xffffff, 0x000080);
+        console.col = 1;
+        console.draw_glyph(&s, b'A');
+        let glyph = font::glyph(b'A');
+        for y in 0..font::HEIGHT {
+            for x in 0..2 * font::WIDTH {
+                let lit = x >= font::WIDTH && glyph[y] & (0x80 >> (x - font::WIDTH)) != 0;
+                let want = match (x >= font::WIDTH, lit) {
+                    (false, _)    => 0, // the first cell is untouched
+                    (true, true)  => 0xffffff,
+                    (true, false) => 0x000080,
+                };
+                assert_eq!(buf[y * 16 + x], want, "({}, {})", x, y);
+            }
+        }
+        // anything outside printable ASCII draws as '?'
+        assert_eq!(font::glyph(0x07), font::glyph(b'?'));
+        assert_eq!(font::glyph(0xe9), font::glyph(b'?'));
+    }
+
+    #[test_case]
+    fn a_new_line_on_the_last_row_scrolls() {
+        // one column, two text rows
+        let mut buf = [0u32; font::WIDTH * 2 * font::HEIGHT];
+        let s = surface(&mut buf, 8, 32, 32, 4, XRGB);
+        let mut console = TextConsole::new(0xffffff, 0x000080);
+        console.draw_glyph(&s, b'#');
+        console.new_line(&s);
+        assert_eq!((console.col, console.row), (0, 1));
+        console.draw_glyph(&s, b'|');
+        console.new_line(&s);
+        assert_eq!((console.col, console.row), (0, 1));
+
+        // '|' moved up a row, and the bottom row is blank
+        let glyph = font::glyph(b'|');
+        for y in 0..font::HEIGHT {
+            for x in 0..font::WIDTH {
+                let lit = glyph[y] & (0x80 >> x) != 0;
+                assert_eq!(buf[y * 8 + x], if lit { 0xffffff } else { 0x000080 });
+                assert_eq!(buf[(y + font::HEIGHT) * 8 + x], 0x000080);
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// rooster-os/arch/x86/runtime/rs_runtime/gdt.rs
+//! Flat 64-bit Global Descriptor Table owned by the runtime.
+//!   • null, kernel code (0x08), kernel data (0x10), TSS (0x18, two slots)
+//!   • `init` loads it with `lgdt`, reloads CS via a far return and the
+//!     data segment registers, then loads the task register
+//!   • The TSS is exposed so IST/privilege stacks can be installed later;
+//!     IST1 is pre-wired to a dedicated double-fault stack
+
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+pub const KERNEL_CODE_SEL: u16 = 0x08;
+pub const KERNEL_DATA_SEL: u16 = 0x10;
+pub const TSS_SEL:         u16 = 0x18;
+
+// Long-mode descriptors: base/limit are ignored for code/data except
+// for the attribute bits (P, DPL 0, S, type, L for code, G/DB for data)
+const KERNEL_CODE: u64 = 0x00AF_9A00_0000_FFFF;
+const KERNEL_DATA: u64 = 0x00CF_9200_0000_FFFF;
+// P=1, DPL 0, type 0x9 = available 64-bit TSS
+const TSS_AVAILABLE: u64 = 0x89;
+
+const GDT_ENTRIES: usize = 5;
+
+/// TSS `interrupt_stack_table` slot used by the #DF gate (IST1)
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
+/// The #DF handler runs the whole panic path on this stack: core::fmt
+/// formatting, the serial/VGA writers and (optionally) the backtrace
+/// walker. That needs a few KiB; 20 KiB leaves ample headroom, and it
+/// must never be smaller than the deepest panic-path call chain, since
+/// overflowing it is an unrecoverable triple fault.
+const DOUBLE_FAULT_STACK_SIZE: usize = 5 * 4096;
+
+#[repr(C, align(16))]
+struct IstStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: IstStack = IstStack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+/// 64-bit Task State Segment; in long mode its only job is to hold the
+/// RSP0-2 privilege stacks and the 7 Interrupt Stack Table pointers
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved0:                 u32,
+    pub privilege_stack_table: [u64; 3],
+    reserved1:                 u64,
+    /// IST1..IST7 (index 0 here is IST1); IDT entries select one by number
+    pub interrupt_stack_table: [u64; 7],
+    reserved2:                 u64,
+    reserved3:                 u16,
+    pub iomap_base:            u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        TaskStateSegment {
+            reserved0:             0,
+            privilege_stack_table: [0; 3],
+            reserved1:             0,
+            interrupt_stack_table: [0; 7],
+            reserved2:             0,
+            reserved3:             0,
+            // no I/O permission bitmap: point past the end of the segment
+            iomap_base:            size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+/// Operand of `lgdt` / `lidt`
+#[repr(C, packed)]
+pub(crate) struct DescriptorTablePointer {
+    pub(crate) limit: u16,
+    pub(crate) base:  u64,
+}
+
+/// The runtime's TSS. Must be edited before `init` (or with interrupts
+/// that use the changed entries disabled); the CPU reads it on demand.
+pub static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+static mut GDT: [u64; GDT_ENTRIES] = [0; GDT_ENTRIES];
+
+/// Encode the 16-byte system descriptor for a TSS at `base`
+fn tss_descriptor(base: u64, limit: u64) -> (u64, u64) {
+    let low = (limit & 0xFFFF)
+        | ((base & 0xFF_FFFF) << 16)
+        | (TSS_AVAILABLE << 40)
+        | (((limit >> 16) & 0xF) << 48)
+        | (((base >> 24) & 0xFF) << 56);
+    let high = base >> 32;
+    (low, high)
+}
+
+/// Build and load the GDT, reload every segment register that matters
+/// in long mode, and load the task register.
+///
+/// FS/GS are deliberately left alone: loading a selector would replace
+/// the FS base entry.S programmed through IA32_FS_BASE for TLS.
+///
+/// SAFETY: call once, with interrupts disabled, after .bss is zeroed
+/// (the table and TSS live there). A second `ltr` on the now-busy TSS
+/// descriptor raises #GP.
+pub unsafe fn init() {
+    // the CPU switches to IST stacks on entry, so point at the top
+    let df_top = addr_of!(DOUBLE_FAULT_STACK) as u64 + DOUBLE_FAULT_STACK_SIZE as u64;
+    TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = df_top;
+
+    let tss_base = addr_of!(TSS) as u64;
+    let (tss_low, tss_high) = tss_descriptor(tss_base, size_of::<TaskStateSegment>() as u64 - 1);
+    GDT = [0, KERNEL_CODE, KERNEL_DATA, tss_low, tss_high];
+
+    let gdtr = DescriptorTablePointer {
+        limit: (size_of::<[u64; GDT_ENTRIES]>() - 1) as u16,
+        base:  addr_of!(GDT) as u64,
+    };
+
+    // CS cannot be written with `mov`: push the new selector and a return
+    // address, then `retfq` pops both, reloading CS with a valid RIP.
+    asm!(
+        "lgdt [{gdtr}]",
+        "push {cs}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        "mov ds, {ds:x}",
+        "mov es, {ds:x}",
+        "mov ss, {ds:x}",
+        gdtr = in(reg) &gdtr,
+        cs   = in(reg) KERNEL_CODE_SEL as u64,
+        ds   = in(reg) KERNEL_DATA_SEL,
+        tmp  = out(reg) _,
+        options(preserves_flags),
+    );
+
+    asm!("ltr {0:x}", in(reg) TSS_SEL, options(nostack, preserves_flags));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn tss_descriptor_splits_base_and_limit() {
+        let (low, high) = tss_descriptor(0x1234_5678_9abc_def0, 0x6_0067);
+        assert_eq!(low, 0x9a06_89bc_def0_0067);
+        assert_eq!(high, 0x1234_5678);
+    }
+
+    #[test_case]
+    fn code_segment_is_64_bit_and_data_is_writable() {
+        let bit = |desc: u64, n: u32| (desc >> n) & 1;
+        // P, S, executable, L set; D clear (required with L)
+        let code = [47, 44, 43, 53, 54].map(|n| bit(KERNEL_CODE, n));
+        assert_eq!(code, [1, 1, 1, 1, 0]);
+        // data: present, writable, not executable
+        let data = [47, 41, 43].map(|n| bit(KERNEL_DATA, n));
+        assert_eq!(data, [1, 1, 0]);
+    }
+
+    #[test_case]
+    fn tss_has_no_io_bitmap() {
+        assert_eq!(size_of::<TaskStateSegment>(), 104);
+        let tss = TaskStateSegment::new();
+        assert_eq!({ tss.iomap_base }, 104);
+    }
+}
@@ -0,0 +1,400 @@
+// rooster-os/arch/x86/runtime/rs_runtime/idt.rs
+//! Default Interrupt Descriptor Table for the runtime.
+//!   • Handlers for #DE, #UD, #DF, #GP and #PF that dump the exception
+//!     frame, every GPR (and error code / CR2) and halt via the panic
+//!     handler. Their asm entry stubs save the registers into an
+//!     `ExceptionContext`; the handlers never return, so there is no
+//!     restore path
+//!   • #DF runs on its own IST stack, so a kernel stack overflow (which
+//!     faults again while pushing the #PF frame) still gets reported
+//!   • Every other vector is left not-present
+//!   • `set_handler` lets the kernel replace entries after boot; the
+//!     table stays loaded, so changes take effect immediately
+
+use core::arch::asm;
+use core::fmt;
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+use crate::gdt::{DescriptorTablePointer, DOUBLE_FAULT_IST_INDEX, KERNEL_CODE_SEL};
+
+pub const DIVIDE_ERROR:       u8 = 0;
+pub const INVALID_OPCODE:     u8 = 6;
+pub const DOUBLE_FAULT:       u8 = 8;
+pub const GENERAL_PROTECTION: u8 = 13;
+pub const PAGE_FAULT:         u8 = 14;
+
+// Gate attributes: P=1, DPL 0, type 0xE = 64-bit interrupt gate (clears IF)
+const GATE_PRESENT:   u16 = 1 << 15;
+const GATE_INTERRUPT: u16 = 0xE << 8;
+// Low 3 bits of the gate options: IST number (1-7), 0 = no stack switch
+const GATE_IST_MASK:  u16 = 0x7;
+
+// Page-fault error-code bits
+pub const PF_ERR_PRESENT:  u64 = 1 << 0;
+pub const PF_ERR_WRITE:    u64 = 1 << 1;
+pub const PF_ERR_USER:     u64 = 1 << 2;
+pub const PF_ERR_RESERVED: u64 = 1 << 3; // a reserved PTE bit was set
+pub const PF_ERR_FETCH:    u64 = 1 << 4; // instruction fetch (needs NX)
+
+/// Page-fault error code; displays as e.g.
+/// `page not present, write, supervisor` (+ `, reserved bit`, `, instruction fetch`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageFaultError(pub u64);
+
+impl fmt::Display for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let e = self.0;
+        write!(
+            f,
+            "{}, {}, {}",
+            if e & PF_ERR_PRESENT != 0 { "protection violation" } else { "page not present" },
+            if e & PF_ERR_FETCH != 0 {
+                "instruction fetch"
+            } else if e & PF_ERR_WRITE != 0 {
+                "write"
+            } else {
+                "read"
+            },
+            if e & PF_ERR_USER != 0 { "user" } else { "supervisor" },
+        )?;
+        if e & PF_ERR_RESERVED != 0 {
+            f.write_str(", reserved bit set")?;
+        }
+        Ok(())
+    }
+}
+
+/// What the CPU pushes on every exception (after any error code)
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InterruptStackFrame {
+    pub rip:    u64,
+    pub cs:     u64,
+    pub rflags: u64,
+    pub rsp:    u64,
+    pub ss:     u64,
+}
+
+impl fmt::Display for InterruptStackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  RIP={:#018x} CS={:#06x}", self.rip, self.cs)?;
+        writeln!(f, "  RSP={:#018x} SS={:#06x}", self.rsp, self.ss)?;
+        write!(f, "  RFLAGS={:#018x}", self.rflags)
+    }
+}
+
+/// General-purpose registers in the order the entry stubs leave them on
+/// the stack (the last push, R15, first). RSP is in the frame.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9:  u64,
+    pub r8:  u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = [
+            [("RAX", self.rax), ("RBX", self.rbx), ("RCX", self.rcx)],
+            [("RDX", self.rdx), ("RSI", self.rsi), ("RDI", self.rdi)],
+            [("RBP", self.rbp), ("R8 ", self.r8),  ("R9 ", self.r9)],
+            [("R10", self.r10), ("R11", self.r11), ("R12", self.r12)],
+            [("R13", self.r13), ("R14", self.r14), ("R15", self.r15)],
+        ];
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, " ")?;
+            for (name, value) in row {
+                write!(f, " {}={:#018x}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Everything an exception entry stub saves, lowest address first: the
+/// GPRs, the error code (0 for vectors without one), then the CPU's frame
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ExceptionContext {
+    pub regs:  Registers,
+    pub error: u64,
+    pub frame: InterruptStackFrame,
+}
+
+impl fmt::Display for ExceptionContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.frame)?;
+        write!(f, "{}", self.regs)
+    }
+}
+
+/// Handler for a vector without an error code
+pub type HandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame);
+/// Handler for a vector the CPU pushes an error code for
+pub type HandlerFuncWithErr = extern "x86-interrupt" fn(InterruptStackFrame, u64);
+/// #DF handler: it has an error code and may never return
+pub type DivergingHandlerFuncWithErr = extern "x86-interrupt" fn(InterruptStackFrame, u64) -> !;
+
+/// One 16-byte IDT gate descriptor
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IdtEntry {
+    offset_low:  u16,
+    selector:    u16,
+    options:     u16,
+    offset_mid:  u16,
+    offset_high: u32,
+    reserved:    u32,
+}
+
+impl IdtEntry {
+    pub const MISSING: IdtEntry = IdtEntry {
+        offset_low:  0,
+        selector:    0,
+        options:     0,
+        offset_mid:  0,
+        offset_high: 0,
+        reserved:    0,
+    };
+
+    /// Interrupt gate to `handler` in the kernel code segment
+    pub const fn new(handler: usize) -> Self {
+        IdtEntry {
+            offset_low:  handler as u16,
+            selector:    KERNEL_CODE_SEL,
+            options:     GATE_PRESENT | GATE_INTERRUPT,
+            offset_mid:  (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved:    0,
+        }
+    }
+
+    /// Switch to TSS `interrupt_stack_table[index]` when this gate fires
+    pub fn set_stack_index(&mut self, index: u16) {
+        debug_assert!(index < 7, "IST index out of range");
+        self.options = (self.options & !GATE_IST_MASK) | (index + 1);
+    }
+}
+
+#[repr(C, align(16))]
+struct Idt {
+    entries: [IdtEntry; 256],
+}
+
+static mut IDT: Idt = Idt { entries: [IdtEntry::MISSING; 256] };
+
+/// Point `vector` at `handler`. The gate runs on the current stack; use
+/// `set_stack_index` afterwards to keep a dedicated IST stack.
+///
+/// SAFETY: `handler` must be the address of a `HandlerFunc`, or of a
+/// `HandlerFuncWithErr` exactly for vectors the CPU pushes an error code
+/// for, and the vector must not fire while the entry is being rewritten.
+pub unsafe fn set_handler(vector: u8, handler: usize) {
+    IDT.entries[vector as usize] = IdtEntry::new(handler);
+}
+
+/// Mark `vector` not-present again; if it fires, the CPU raises #GP.
+///
+/// SAFETY: as for `set_handler`.
+pub unsafe fn clear_handler(vector: u8) {
+    IDT.entries[vector as usize] = IdtEntry::MISSING;
+}
+
+/// Make `vector` switch to TSS IST slot `index` (0-6 for IST1-IST7).
+///
+/// SAFETY: the slot must hold the top of a valid, otherwise unused stack.
+pub unsafe fn set_stack_index(vector: u8, index: u16) {
+    IDT.entries[vector as usize].set_stack_index(index);
+}
+
+/// Install the default exception handlers and load the IDT.
+///
+/// SAFETY: the runtime GDT must be loaded (gates use `KERNEL_CODE_SEL`)
+/// and .bss must already be zeroed.
+pub unsafe fn init() {
+    set_handler(DIVIDE_ERROR,       idt_stub_de as unsafe extern "C" fn() as usize);
+    set_handler(INVALID_OPCODE,     idt_stub_ud as unsafe extern "C" fn() as usize);
+    set_handler(DOUBLE_FAULT,       idt_stub_df as unsafe extern "C" fn() as usize);
+    set_handler(GENERAL_PROTECTION, idt_stub_gp as unsafe extern "C" fn() as usize);
+    set_handler(PAGE_FAULT,         idt_stub_pf as unsafe extern "C" fn() as usize);
+    set_stack_index(DOUBLE_FAULT, DOUBLE_FAULT_IST_INDEX);
+
+    let idtr = DescriptorTablePointer {
+        limit: (size_of::<Idt>() - 1) as u16,
+        base:  addr_of!(IDT) as u64,
+    };
+    asm!("lidt [{}]", in(reg) &idtr, options(readonly, nostack, preserves_flags));
+}
+
+fn read_cr2() -> u64 {
+    let cr2: u64;
+    // SAFETY: reading CR2 has no side effects
+    unsafe { asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+    cr2
+}
+
+// Entry stub for a default handler: push a 0 error code where the CPU
+// pushes none, save every GPR, then call `handler(&ExceptionContext)` on a
+// 16-byte aligned stack (the CPU aligned it before pushing 48 bytes; 120
+// more for the GPRs, plus 8). The handlers diverge, hence no iretq.
+macro_rules! exception_stub {
+    ($stub:literal, $handler:path, $push_error:literal) => {
+        core::arch::global_asm!(
+            concat!(".global ", $stub),
+            concat!($stub, ":"),
+            $push_error,
+            "push rax", "push rbx", "push rcx", "push rdx",
+            "push rsi", "push rdi", "push rbp", "push r8",
+            "push r9",  "push r10", "push r11", "push r12",
+            "push r13", "push r14", "push r15",
+            "mov rdi, rsp",
+            "sub rsp, 8",
+            "call {handler}",
+            "ud2",
+            handler = sym $handler,
+        );
+    };
+}
+
+exception_stub!("idt_stub_de", divide_error,       "push 0");
+exception_stub!("idt_stub_ud", invalid_opcode,     "push 0");
+exception_stub!("idt_stub_df", double_fault,       "");
+exception_stub!("idt_stub_gp", general_protection, "");
+exception_stub!("idt_stub_pf", page_fault,         "");
+
+extern "C" {
+    fn idt_stub_de();
+    fn idt_stub_ud();
+    fn idt_stub_df();
+    fn idt_stub_gp();
+    fn idt_stub_pf();
+}
+
+extern "C" fn divide_error(ctx: &ExceptionContext) -> ! {
+    panic!("EXCEPTION: #DE divide error\n{}", ctx);
+}
+
+extern "C" fn invalid_opcode(ctx: &ExceptionContext) -> ! {
+    panic!("EXCEPTION: #UD invalid opcode\n{}", ctx);
+}
+
+extern "C" fn double_fault(ctx: &ExceptionContext) -> ! {
+    panic!("EXCEPTION: #DF double fault (error {:#x})\n{}", ctx.error, ctx);
+}
+
+extern "C" fn general_protection(ctx: &ExceptionContext) -> ! {
+    panic!("EXCEPTION: #GP general protection fault (error {:#x})\n{}", ctx.error, ctx);
+}
+
+extern "C" fn page_fault(ctx: &ExceptionContext) -> ! {
+    panic!(
+        "EXCEPTION: #PF at {:#x}: {} (error {:#x})\n{}",
+        read_cr2(),
+        PageFaultError(ctx.error),
+        ctx.error,
+        ctx,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use crate::runtime_core::FmtBuf;
+
+    fn describe(error: u64) -> FmtBuf<96> {
+        let mut out = FmtBuf::new();
+        write!(out, "{}", PageFaultError(error)).unwrap();
+        out
+    }
+
+    #[test_case]
+    fn gate_splits_the_handler_address() {
+        let gate = IdtEntry::new(0x1234_5678_9abc_def0);
+        assert_eq!(gate.offset_low, 0xdef0);
+        assert_eq!(gate.offset_mid, 0x9abc);
+        assert_eq!(gate.offset_high, 0x1234_5678);
+        assert_eq!(gate.selector, KERNEL_CODE_SEL);
+        // present 64-bit interrupt gate, no IST
+        assert_eq!(gate.options, 0x8e00);
+        assert_eq!(size_of::<IdtEntry>(), 16);
+    }
+
+    #[test_case]
+    fn stack_index_is_stored_one_based() {
+        let mut gate = IdtEntry::new(0x1000);
+        gate.set_stack_index(DOUBLE_FAULT_IST_INDEX);
+        assert_eq!(gate.options, 0x8e01);
+        // replaces rather than ORs in the previous index
+        gate.set_stack_index(5);
+        assert_eq!(gate.options, 0x8e06);
+    }
+
+    #[test_case]
+    fn page_fault_error_names_every_bit() {
+        assert_eq!(describe(0).as_str(), "page not present, read, supervisor");
+        let e = PF_ERR_PRESENT | PF_ERR_WRITE | PF_ERR_USER;
+        assert_eq!(describe(e).as_str(), "protection violation, write, user");
+        let e = PF_ERR_PRESENT | PF_ERR_RESERVED;
+        let expected = "protection violation, read, supervisor, reserved bit set";
+        assert_eq!(describe(e).as_str(), expected);
+    }
+
+    #[test_case]
+    fn instruction_fetch_wins_over_write() {
+        let e = PF_ERR_PRESENT | PF_ERR_FETCH;
+        assert_eq!(describe(e).as_str(), "protection violation, instruction fetch, supervisor");
+        assert_eq!(describe(e | PF_ERR_WRITE).as_str(), describe(e).as_str());
+    }
+
+    #[test_case]
+    fn exception_context_lists_the_frame_then_every_gpr() {
+        let regs = Registers { rax: 0xa, rbx: 0xb, r8: 0x8, r15: 0xf, ..Registers::default() };
+        let frame = InterruptStackFrame {
+            rip:    0xffff_8000_0010_2345,
+            cs:     0x08,
+            rflags: 0x246,
+            rsp:    0x7ff0,
+            ss:     0x10,
+        };
+        let ctx = ExceptionContext { regs, error: 0, frame };
+        let mut out = FmtBuf::<1024>::new();
+        write!(out, "{}", ctx).unwrap();
+        let expected = [
+            "  RIP=0xffff800000102345 CS=0x0008",
+            "  RSP=0x0000000000007ff0 SS=0x0010",
+            "  RFLAGS=0x0000000000000246",
+            "  RAX=0x000000000000000a RBX=0x000000000000000b RCX=0x0000000000000000",
+            "  RDX=0x0000000000000000 RSI=0x0000000000000000 RDI=0x0000000000000000",
+            "  RBP=0x0000000000000000 R8 =0x0000000000000008 R9 =0x0000000000000000",
+            "  R10=0x0000000000000000 R11=0x0000000000000000 R12=0x0000000000000000",
+            "  R13=0x0000000000000000 R14=0x0000000000000000 R15=0x000000000000000f",
+        ];
+        assert!(out.as_str().lines().eq(expected));
+        assert!(!out.as_str().ends_with('\n'));
+    }
+
+    #[test_case]
+    fn context_layout_matches_the_entry_stub() {
+        // 15 pushes, the error code, then the 5-word CPU frame
+        assert_eq!(size_of::<Registers>(), 15 * 8);
+        assert_eq!(size_of::<ExceptionContext>(), (15 + 1 + 5) * 8);
+    }
+}
@@ -0,0 +1,87 @@
+// rooster-os/arch/x86/runtime/rs_runtime/interrupts.rs
+//! Masking interrupts around critical sections.
+//!   • `InterruptGuard` saves RFLAGS.IF and executes `cli`; dropping it
+//!     runs `sti` only if IF was set before, so guards nest
+//!   • `without_interrupts(f)` runs `f` under a guard; `runtime_core`
+//!     takes the allocator locks inside it, so an IRQ handler that
+//!     allocates cannot spin on a lock its own CPU holds
+//!   • Restore policy is `restore_if`, separate from the instructions so
+//!     it works on any saved value
+
+use core::arch::asm;
+
+/// RFLAGS.IF: maskable interrupts enabled
+pub const RFLAGS_IF: u64 = 1 << 9;
+
+/// Current RFLAGS
+#[inline]
+pub fn rflags() -> u64 {
+    let flags: u64;
+    // SAFETY: pushfq/pop only touch the stack slot they use
+    unsafe { asm!("pushfq", "pop {}", out(reg) flags, options(nomem, preserves_flags)) };
+    flags
+}
+
+/// Whether leaving a critical section entered with RFLAGS = `saved`
+/// should re-enable interrupts
+#[inline]
+pub const fn restore_if(saved: u64) -> bool {
+    saved & RFLAGS_IF != 0
+}
+
+/// Interrupts stay masked while this is alive
+pub struct InterruptGuard {
+    saved: u64,
+}
+
+impl InterruptGuard {
+    /// Save RFLAGS, then `cli`
+    #[inline]
+    pub fn new() -> Self {
+        let saved = rflags();
+        // SAFETY: masking interrupts cannot break memory safety
+        unsafe { asm!("cli", options(nomem, nostack)) };
+        InterruptGuard { saved }
+    }
+}
+
+impl Drop for InterruptGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if restore_if(self.saved) {
+            // SAFETY: interrupts were enabled when the guard was taken
+            unsafe { asm!("sti", options(nomem, nostack)) };
+        }
+    }
+}
+
+/// Run `f` with interrupts masked, then put IF back as it was
+#[inline]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = InterruptGuard::new();
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn only_a_saved_if_is_restored() {
+        // reserved bit 1 is always set in RFLAGS
+        assert!(restore_if(0x202));
+        assert!(!restore_if(0x002));
+        assert!(!restore_if(!RFLAGS_IF));
+    }
+
+    #[test_case]
+    fn nested_guards_restore_the_outer_state() {
+        let before = rflags() & RFLAGS_IF;
+        without_interrupts(|| {
+            without_interrupts(|| assert_eq!(rflags() & RFLAGS_IF, 0));
+            // the inner guard found IF clear, so it left it clear
+            assert_eq!(rflags() & RFLAGS_IF, 0);
+        });
+        assert_eq!(rflags() & RFLAGS_IF, before);
+    }
+}
@@ -0,0 +1,193 @@
+// rooster-os/arch/x86/runtime/rs_runtime/logging.rs
+//! `log` crate backend that writes to COM1.
+//!   • Each line is prefixed with its level: `[INFO ] message`
+//!   • Goes straight to the UART, so it works before the heap exists
+//!     (but after .bss is zeroed: `log` keeps its state there)
+//!   • The runtime-wide ceiling is set by `init_logging`; the build-time
+//!     one by `log`'s features, e.g. `release_max_level_info` compiles
+//!     `debug!`/`trace!` out of release builds entirely
+//!
+//! The kernel logs with the usual macros, re-exported here:
+//! `logging::info!("heap: {} KiB", kib)`.
+//!
+//! Interrupt handlers should not write to a slow UART (or take a console
+//! lock the interrupted code may hold): they queue bytes with
+//! `log_deferred`, and the main loop writes them out with `drain_log`.
+
+use core::fmt;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+pub use log::{debug, error, info, trace, warn};
+
+use crate::runtime_core::sync::Spinlock;
+use crate::serial;
+
+struct SerialLogger;
+
+impl Log for SerialLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            serial::write_fmt(format_args!("[{:<5}] {}\n", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {
+        serial::com1().drain();
+    }
+}
+
+static LOGGER: SerialLogger = SerialLogger;
+
+/// Install the serial logger and set the runtime maximum level. Calling
+/// it again only changes the level.
+pub fn init_logging(level: LevelFilter) {
+    // fails only if a logger is already installed, which is fine
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
+
+/// Bytes `log_deferred` holds before the oldest are dropped
+pub const DEFERRED_LOG_SIZE: usize = 4096;
+
+/// Bytes `drain_log` copies out per lock hold, so interrupts are only
+/// masked for a short copy and never while the UART is written
+const DRAIN_CHUNK: usize = 64;
+
+/// Fixed byte ring behind `log_deferred`; a push into a full ring
+/// overwrites the oldest bytes
+struct LogRing {
+    buf:     [u8; DEFERRED_LOG_SIZE],
+    head:    usize, // oldest byte
+    len:     usize,
+    dropped: usize, // bytes overwritten since the last drain
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        LogRing { buf: [0; DEFERRED_LOG_SIZE], head: 0, len: 0, dropped: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.len == DEFERRED_LOG_SIZE {
+                self.head = (self.head + 1) % DEFERRED_LOG_SIZE;
+                self.len -= 1;
+                self.dropped += 1;
+            }
+            self.buf[(self.head + self.len) % DEFERRED_LOG_SIZE] = b;
+            self.len += 1;
+        }
+    }
+
+    /// Move the oldest bytes into `out`; returns how many
+    fn pop(&mut self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.len);
+        for (i, slot) in out[..n].iter_mut().enumerate() {
+            *slot = self.buf[(self.head + i) % DEFERRED_LOG_SIZE];
+        }
+        self.head = (self.head + n) % DEFERRED_LOG_SIZE;
+        self.len -= n;
+        n
+    }
+}
+
+static DEFERRED: Spinlock<LogRing> = Spinlock::new(LogRing::new());
+
+/// Queue `bytes` for `drain_log`. Safe from interrupt handlers: it only
+/// copies into a ring under its own lock, held with interrupts masked, so
+/// it cannot deadlock against the code it interrupted. When the ring is
+/// full the oldest bytes make room, and `drain_log` reports how many.
+pub fn log_deferred(bytes: &[u8]) {
+    DEFERRED.lock_irqsave().push(bytes);
+}
+
+/// Write out what `log_deferred` queued, oldest first, after a note of
+/// any bytes dropped since the last drain. Only drains what was queued
+/// on entry, so a flood of interrupts cannot keep it looping. Invalid
+/// UTF-8 comes out as U+FFFD. Call it from normal (not interrupt)
+/// context, e.g. `drain_log(&mut serial::com1())` in the main loop.
+pub fn drain_log(w: &mut dyn fmt::Write) -> fmt::Result {
+    drain_ring(&DEFERRED, w)
+}
+
+/// `drain_log` for any ring
+fn drain_ring(ring: &Spinlock<LogRing>, w: &mut dyn fmt::Write) -> fmt::Result {
+    let (mut left, dropped) = {
+        let mut ring = ring.lock_irqsave();
+        (ring.len, core::mem::take(&mut ring.dropped))
+    };
+    if dropped > 0 {
+        writeln!(w, "[deferred log: {} bytes dropped]", dropped)?;
+    }
+
+    let mut chunk = [0u8; DRAIN_CHUNK];
+    while left > 0 {
+        let n = ring.lock_irqsave().pop(&mut chunk[..left.min(DRAIN_CHUNK)]);
+        if n == 0 {
+            break;
+        }
+        left -= n;
+        for part in chunk[..n].utf8_chunks() {
+            w.write_str(part.valid())?;
+            if !part.invalid().is_empty() {
+                w.write_char(char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_core::FmtBuf;
+
+    #[test_case]
+    fn ring_wraps_around_without_dropping() {
+        let mut ring = LogRing::new();
+        let mut out = [0u8; DEFERRED_LOG_SIZE];
+        ring.push(&[b'a'; 3000]);
+        assert_eq!(ring.pop(&mut out[..2000]), 2000);
+        // 1000 left, so 3000 more fit, crossing the end of the buffer
+        ring.push(&[b'b'; 3000]);
+        assert_eq!(ring.dropped, 0);
+        assert_eq!(ring.pop(&mut out), 4000);
+        assert!(out[..1000].iter().all(|&b| b == b'a'));
+        assert!(out[1000..4000].iter().all(|&b| b == b'b'));
+        assert_eq!(ring.pop(&mut out), 0);
+    }
+
+    #[test_case]
+    fn a_full_ring_drops_the_oldest_bytes() {
+        let mut ring = LogRing::new();
+        for i in 0..DEFERRED_LOG_SIZE + 10 {
+            ring.push(&[i as u8]);
+        }
+        assert_eq!(ring.dropped, 10);
+        let mut out = [0u8; 2];
+        ring.pop(&mut out);
+        assert_eq!(out, [10, 11]);
+    }
+
+    #[test_case]
+    fn drain_notes_the_drop_count_first() {
+        static RING: Spinlock<LogRing> = Spinlock::new(LogRing::new());
+        RING.lock().push(&[b'x'; DEFERRED_LOG_SIZE]);
+        RING.lock().push(b"irq 3\n\xff");
+        let mut out = FmtBuf::<{ DEFERRED_LOG_SIZE + 64 }>::new();
+        drain_ring(&RING, &mut out).unwrap();
+        let text = out.as_str();
+        assert!(text.starts_with("[deferred log: 7 bytes dropped]\nxxx"));
+        // invalid UTF-8 becomes U+FFFD
+        assert!(text.ends_with("xirq 3\n\u{fffd}"));
+        // the note is only printed once
+        let mut again = FmtBuf::<8>::new();
+        drain_ring(&RING, &mut again).unwrap();
+        assert_eq!(again.as_str(), "");
+    }
+}
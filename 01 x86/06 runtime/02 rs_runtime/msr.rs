@@ -0,0 +1,168 @@
+// rooster-os/arch/x86/runtime/rs_runtime/msr.rs
+//! Model-specific register access.
+//!   • `read`/`write` wrap `rdmsr`/`wrmsr` on the executing CPU
+//!   • Named constants for the MSRs (and bits) the runtime uses
+//!   • `MsrBackend` abstracts the two, so logic built on MSRs (e.g.
+//!     `set_bits` for EFER.NXE) runs against `HardwareMsrs` on the CPU or
+//!     a mock register file in RAM
+
+use core::arch::asm;
+#[cfg(test)]
+use core::cell::Cell;
+
+/// Local APIC base address and enable bits
+pub const IA32_APIC_BASE: u32 = 0x1B;
+/// Extended feature enables (long mode, syscall, NX)
+pub const IA32_EFER:      u32 = 0xC000_0080;
+/// FS segment base, used for TLS (entry.S)
+pub const IA32_FS_BASE:   u32 = 0xC000_0100;
+
+/// EFER bit: no-execute enable; PF_NX in a page table faults without it
+pub const EFER_NXE: u64 = 1 << 11;
+
+/// Read MSR `msr`.
+///
+/// SAFETY: `msr` must exist on this CPU, or `rdmsr` raises #GP.
+#[inline]
+pub unsafe fn read(msr: u32) -> u64 {
+    let (lo, hi): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") lo, out("edx") hi,
+         options(nomem, nostack, preserves_flags));
+    (hi as u64) << 32 | lo as u64
+}
+
+/// Write `value` to MSR `msr`.
+///
+/// SAFETY: `msr` must exist and accept `value` (reserved bits raise
+/// #GP), and the change must be one the running code survives.
+#[inline]
+pub unsafe fn write(msr: u32, value: u64) {
+    asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32,
+         options(nostack, preserves_flags));
+}
+
+/// Where MSR reads and writes go
+pub trait MsrBackend {
+    /// SAFETY: as for `msr::read`
+    unsafe fn read(&self, msr: u32) -> u64;
+    /// SAFETY: as for `msr::write`
+    unsafe fn write(&self, msr: u32, value: u64);
+}
+
+/// The executing CPU's own MSRs
+#[derive(Clone, Copy)]
+pub struct HardwareMsrs;
+
+impl MsrBackend for HardwareMsrs {
+    unsafe fn read(&self, msr: u32) -> u64 {
+        read(msr)
+    }
+
+    unsafe fn write(&self, msr: u32, value: u64) {
+        write(msr, value)
+    }
+}
+
+/// Set `bits` in `msr` by read-modify-write, skipping the write if they
+/// are all set already; returns the value read.
+///
+/// SAFETY: as for `msr::write` with the new value.
+pub unsafe fn set_bits(backend: &impl MsrBackend, msr: u32, bits: u64) -> u64 {
+    let old = backend.read(msr);
+    if old & bits != bits {
+        backend.write(msr, old | bits);
+    }
+    old
+}
+
+/// Register file in RAM for tests: a fixed set of MSRs, each seeded with
+/// a value. Touching any other MSR panics, as `rdmsr`/`wrmsr` would #GP.
+#[cfg(test)]
+pub(crate) struct MockMsrs {
+    regs:   [Cell<(u32, u64)>; MockMsrs::CAPACITY],
+    len:    usize,
+    writes: Cell<usize>,
+}
+
+#[cfg(test)]
+impl MockMsrs {
+    const CAPACITY: usize = 8;
+
+    /// A register file holding exactly `regs` as (msr, value)
+    pub(crate) fn new(regs: &[(u32, u64)]) -> Self {
+        assert!(regs.len() <= Self::CAPACITY, "too many mock MSRs");
+        let mock = MockMsrs {
+            regs:   Default::default(),
+            len:    regs.len(),
+            writes: Cell::new(0),
+        };
+        for (slot, &reg) in mock.regs.iter().zip(regs) {
+            slot.set(reg);
+        }
+        mock
+    }
+
+    fn slot(&self, msr: u32) -> &Cell<(u32, u64)> {
+        match self.regs[..self.len].iter().find(|r| r.get().0 == msr) {
+            Some(slot) => slot,
+            None       => panic!("#GP: MSR {:#x} is not mocked", msr),
+        }
+    }
+
+    /// Current value of `msr`
+    pub(crate) fn value(&self, msr: u32) -> u64 {
+        self.slot(msr).get().1
+    }
+
+    /// Writes made so far, to any MSR
+    pub(crate) fn writes(&self) -> usize {
+        self.writes.get()
+    }
+}
+
+#[cfg(test)]
+impl MsrBackend for MockMsrs {
+    unsafe fn read(&self, msr: u32) -> u64 {
+        self.value(msr)
+    }
+
+    unsafe fn write(&self, msr: u32, value: u64) {
+        self.slot(msr).set((msr, value));
+        self.writes.set(self.writes.get() + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn mock_reads_back_what_was_written() {
+        let msrs = MockMsrs::new(&[(IA32_EFER, 0x500), (IA32_FS_BASE, 0)]);
+        unsafe {
+            assert_eq!(msrs.read(IA32_EFER), 0x500);
+            msrs.write(IA32_FS_BASE, 0xdead_b000);
+            assert_eq!(msrs.read(IA32_FS_BASE), 0xdead_b000);
+            assert_eq!(msrs.read(IA32_EFER), 0x500);
+        }
+        assert_eq!(msrs.writes(), 1);
+    }
+
+    #[test_case]
+    fn set_bits_ors_into_the_old_value() {
+        let msrs = MockMsrs::new(&[(IA32_EFER, 0x501)]);
+        let old = unsafe { set_bits(&msrs, IA32_EFER, 1 << 3 | 1) };
+        assert_eq!(old, 0x501);
+        assert_eq!(msrs.value(IA32_EFER), 0x509);
+        assert_eq!(msrs.writes(), 1);
+    }
+
+    #[test_case]
+    fn set_bits_skips_the_write_when_already_set() {
+        let msrs = MockMsrs::new(&[(IA32_EFER, 0xd01)]);
+        let old = unsafe { set_bits(&msrs, IA32_EFER, EFER_NXE) };
+        assert_eq!(old, 0xd01);
+        assert_eq!(msrs.value(IA32_EFER), 0xd01);
+        assert_eq!(msrs.writes(), 0);
+    }
+}
@@ -0,0 +1,1025 @@
+// rooster-os/arch/x86/runtime/rs_runtime/multiboot.rs
+//! Multiboot (v1) information-structure parsing for the Rust runtime.
+//!   • `MultibootInfo` mirrors the loader-provided MBI layout
+//!   • The `mmap_*` buffer is walked with strict bounds checks
+//!   • Results land in a fixed-capacity `MemoryMap` (no heap required)
+//!   • The command line is exposed as a borrowed `&'static str`
+//!   • `ElfSections` reads the kernel's ELF section headers (flag bit 5)
+//!   • `Header` checks the image's own Multiboot header
+
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+
+/// MBI `flags` bit: `mem_lower`/`mem_upper` are valid
+pub const FLAG_MEM:         u32 = 1 << 0;
+/// MBI `flags` bit: `cmdline` is valid
+pub const FLAG_CMDLINE:     u32 = 1 << 2;
+/// MBI `flags` bit: `mods_count`/`mods_addr` are valid
+pub const FLAG_MODS:        u32 = 1 << 3;
+/// MBI `flags` bit: `syms` holds the ELF section header table
+pub const FLAG_ELF_SHDR:    u32 = 1 << 5;
+/// MBI `flags` bit: `mmap_length`/`mmap_addr` are valid
+pub const FLAG_MMAP:        u32 = 1 << 6;
+/// MBI `flags` bit: the `framebuffer_*` fields are valid
+pub const FLAG_FRAMEBUFFER: u32 = 1 << 12;
+
+/// `magic` of the Multiboot1 header in the kernel image (the loader
+/// passes `0x2BADB002` in EAX instead)
+pub const HEADER_MAGIC: u32 = 0x1BAD_B002;
+
+/// Maximum number of regions a `MemoryMap` can hold
+pub const MAX_REGIONS: usize = 64;
+
+/// Maximum number of boot modules a `ModuleList` can hold
+pub const MAX_MODULES: usize = 16;
+
+/// Longest C string (without NUL) the parsers will scan for
+pub const MAX_CMDLINE: usize = 4096;
+
+/// The three mandatory fields of the Multiboot1 header embedded in the
+/// kernel image (within its first 8 KiB, 4-byte aligned)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub magic:    u32,
+    pub flags:    u32,
+    pub checksum: u32,
+}
+
+impl Header {
+    /// The magic matches and `magic + flags + checksum` wraps to zero, as
+    /// the loader requires before it boots the image
+    pub const fn is_valid(&self) -> bool {
+        self.magic == HEADER_MAGIC
+            && self.magic.wrapping_add(self.flags).wrapping_add(self.checksum) == 0
+    }
+}
+
+/// Multiboot information structure as laid out by the bootloader.
+/// Packed because the MBI is only guaranteed 4-byte alignment.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct MultibootInfo {
+    pub flags:              u32,
+    pub mem_lower:          u32,
+    pub mem_upper:          u32,
+    pub boot_device:        u32,
+    pub cmdline:            u32,
+    pub mods_count:         u32,
+    pub mods_addr:          u32,
+    pub syms:               [u32; 4],
+    pub mmap_length:        u32,
+    pub mmap_addr:          u32,
+    pub drives_length:      u32,
+    pub drives_addr:        u32,
+    pub config_table:       u32,
+    pub boot_loader_name:   u32,
+    pub apm_table:          u32,
+    pub vbe_control_info:   u32,
+    pub vbe_mode_info:      u32,
+    pub vbe_mode:           u16,
+    pub vbe_interface_seg:  u16,
+    pub vbe_interface_off:  u16,
+    pub vbe_interface_len:  u16,
+    pub framebuffer_addr:   u64,
+    pub framebuffer_pitch:  u32,
+    pub framebuffer_width:  u32,
+    pub framebuffer_height: u32,
+    pub framebuffer_bpp:    u8,
+    pub framebuffer_type:   u8,
+    pub color_info:         [u8; 6],
+}
+
+impl MultibootInfo {
+    /// Copy the MBI out of bootloader memory.
+    ///
+    /// SAFETY: `mbi_addr` must point at a readable Multiboot info structure.
+    pub unsafe fn read(mbi_addr: usize) -> Self {
+        read_unaligned(mbi_addr as *const MultibootInfo)
+    }
+}
+
+/// Type of a physical memory region, as reported by the firmware
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    Usable,
+    Reserved,
+    AcpiReclaimable,
+    AcpiNvs,
+    BadMemory,
+    Unknown(u32),
+}
+
+impl RegionKind {
+    /// Decode the E820-style type field shared by Multiboot 1 and 2
+    pub fn from_raw(kind: u32) -> Self {
+        match kind {
+            1 => RegionKind::Usable,
+            2 => RegionKind::Reserved,
+            3 => RegionKind::AcpiReclaimable,
+            4 => RegionKind::AcpiNvs,
+            5 => RegionKind::BadMemory,
+            n => RegionKind::Unknown(n),
+        }
+    }
+}
+
+/// One physical memory range from the boot memory map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base:   u64,
+    pub length: u64,
+    pub kind:   RegionKind,
+}
+
+/// Fixed-capacity memory map, filled once at boot
+#[derive(Clone, Copy)]
+pub struct MemoryMap {
+    regions:   [MemoryRegion; MAX_REGIONS],
+    len:       usize,
+    truncated: bool,
+}
+
+impl MemoryMap {
+    pub const fn new() -> Self {
+        const EMPTY: MemoryRegion = MemoryRegion { base: 0, length: 0, kind: RegionKind::Reserved };
+        MemoryMap { regions: [EMPTY; MAX_REGIONS], len: 0, truncated: false }
+    }
+
+    /// Append a region; returns false (and marks the map truncated) when full
+    pub fn push(&mut self, region: MemoryRegion) -> bool {
+        if self.len == MAX_REGIONS {
+            self.truncated = true;
+            return false;
+        }
+        self.regions[self.len] = region;
+        self.len += 1;
+        true
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..self.len]
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, MemoryRegion> {
+        self.regions().iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// True if the bootloader reported more than `MAX_REGIONS` regions
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Bytes covered by any region. Overlaps are counted once and
+    /// zero-length regions not at all.
+    pub fn total_bytes(&self) -> u64 {
+        let mut all = [(0, 0); MAX_REGIONS];
+        let n = self.merged(|_| true, &mut all);
+        all[..n].iter().map(|&(start, end)| end - start).sum()
+    }
+
+    /// Bytes of usable RAM. Overlaps are counted once, and wherever a
+    /// usable region overlaps any other kind, the other kind wins.
+    pub fn usable_bytes(&self) -> u64 {
+        let mut usable = [(0, 0); MAX_REGIONS];
+        let mut other  = [(0, 0); MAX_REGIONS];
+        let nu = self.merged(|k| k == RegionKind::Usable, &mut usable);
+        let no = self.merged(|k| k != RegionKind::Usable, &mut other);
+
+        let mut bytes = 0;
+        for &(start, end) in &usable[..nu] {
+            let lost: u64 = other[..no]
+                .iter()
+                .map(|&(s, e)| end.min(e).saturating_sub(start.max(s)))
+                .sum();
+            bytes += end - start - lost;
+        }
+        bytes
+    }
+
+    /// A copy sorted by base, without zero-length regions, and with regions
+    /// of the same kind that touch or overlap merged into one. Regions of
+    /// different kinds are left as they are, overlaps included; the
+    /// `truncated` flag carries over.
+    pub fn normalized(&self) -> MemoryMap {
+        let mut out = *self;
+        out.len = 0;
+        let mut sorted = self.regions;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable_by_key(|r| (r.base, r.length));
+
+        for r in sorted.iter().filter(|r| r.length != 0) {
+            let end = r.base.saturating_add(r.length);
+            // the last region of the same kind, if `r` starts inside or
+            // right after it
+            let prev = out.regions[..out.len].iter_mut().rev().find(|p| p.kind == r.kind);
+            match prev {
+                Some(p) if r.base <= p.base.saturating_add(p.length) => {
+                    p.length = end.max(p.base.saturating_add(p.length)) - p.base;
+                }
+                _ => {
+                    out.regions[out.len] = *r;
+                    out.len += 1;
+                }
+            }
+        }
+        out
+    }
+
+    /// Regions of the kinds `keep` accepts as sorted, disjoint, non-empty
+    /// `[start, end)` ranges (clamped to the 64-bit address space); returns
+    /// how many were written to `out`
+    fn merged(&self, keep: impl Fn(RegionKind) -> bool, out: &mut [(u64, u64); MAX_REGIONS]) -> usize {
+        let mut n = 0;
+        for r in self.iter().filter(|r| keep(r.kind) && r.length != 0) {
+            out[n] = (r.base, r.base.saturating_add(r.length));
+            n += 1;
+        }
+        out[..n].sort_unstable();
+
+        let mut merged = 0;
+        for i in 0..n {
+            let (start, end) = out[i];
+            if merged > 0 && start <= out[merged - 1].1 {
+                out[merged - 1].1 = out[merged - 1].1.max(end);
+            } else {
+                out[merged] = (start, end);
+                merged += 1;
+            }
+        }
+        merged
+    }
+
+    /// True if `addr` lies inside a usable region. Binary-searches, so the
+    /// map must come from `normalized()`; on a raw map the answer is
+    /// unreliable.
+    pub fn contains_usable(&self, addr: usize) -> bool {
+        let addr = addr as u64;
+        let regions = self.regions();
+        // regions starting at or below `addr`; usable ones are disjoint
+        // there, so only the last of them can contain it
+        let below = regions.partition_point(|r| r.base <= addr);
+        regions[..below]
+            .iter()
+            .rev()
+            .find(|r| r.kind == RegionKind::Usable)
+            .map_or(false, |r| addr - r.base < r.length)
+    }
+
+    /// True if `[start, start + len)` lies entirely inside one usable region
+    pub fn covers_usable(&self, start: u64, len: u64) -> bool {
+        let end = match start.checked_add(len) {
+            Some(e) => e,
+            None    => return false,
+        };
+        self.iter().any(|r| {
+            r.kind == RegionKind::Usable
+                && r.base <= start
+                && end <= r.base.saturating_add(r.length)
+        })
+    }
+}
+
+/// ELF `sh_type` of a symbol table
+pub const SHT_SYMTAB: u32 = 2;
+/// ELF `sh_type` of a string table
+pub const SHT_STRTAB: u32 = 3;
+
+/// One ELF64 section header (`Elf64_Shdr`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ElfSection {
+    /// Offset of the name in the section-name string table
+    pub name:      u32,
+    pub typ:       u32,
+    pub flags:     u64,
+    /// Where the section is in memory (0 if the loader did not load it)
+    pub addr:      u64,
+    pub offset:    u64,
+    pub size:      u64,
+    pub link:      u32,
+    pub info:      u32,
+    pub addralign: u64,
+    pub entsize:   u64,
+}
+
+/// The kernel's ELF section header table as the loader passed it:
+/// `num` headers of `size` bytes each at `addr`; `shndx` is the index of
+/// the section-name string table. Only 64-bit headers are accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ElfSections {
+    num:   usize,
+    size:  usize,
+    addr:  usize,
+    shndx: usize,
+}
+
+impl ElfSections {
+    /// Describe a header table; `None` if `size` is too small for an
+    /// `Elf64_Shdr` or `num * size` overflows.
+    ///
+    /// SAFETY: `[addr, addr + num * size)` must stay readable for good,
+    /// and so must every loaded section a caller then reads (e.g. names).
+    pub unsafe fn new(num: usize, size: usize, addr: usize, shndx: usize) -> Option<Self> {
+        if size < size_of::<ElfSection>() {
+            return None;
+        }
+        num.checked_mul(size)?.checked_add(addr)?;
+        Some(ElfSections { num, size, addr, shndx })
+    }
+
+    pub fn len(&self) -> usize {
+        self.num
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num == 0
+    }
+
+    /// The header table as `[start, end)`
+    pub fn table_range(&self) -> (usize, usize) {
+        (self.addr, self.addr + self.num * self.size)
+    }
+
+    /// Header `i`, if there is one
+    pub fn get(&self, i: usize) -> Option<ElfSection> {
+        if i >= self.num {
+            return None;
+        }
+        // SAFETY: entry `i` lies inside the table `new` was promised
+        Some(unsafe { read_unaligned((self.addr + i * self.size) as *const ElfSection) })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ElfSection> + '_ {
+        (0..self.num).filter_map(move |i| self.get(i))
+    }
+
+    /// Name of `section`, from the loaded section-name string table;
+    /// `None` if that table was not loaded or the name runs off its end
+    pub fn name(&self, section: &ElfSection) -> Option<&'static str> {
+        let names = self.get(self.shndx).filter(|s| s.typ == SHT_STRTAB && s.addr != 0)?;
+        if section.name as u64 >= names.size {
+            return None;
+        }
+        let start = (names.addr + section.name as u64) as usize;
+        let max = (names.size - section.name as u64) as usize;
+        // SAFETY: within the loaded string table (see `new`)
+        let bytes = unsafe { c_str_bytes(start, max) };
+        (bytes.len() < max).then(|| cmdline_from_bytes(bytes))
+    }
+
+    /// The first loaded section called `name`, e.g. `.symtab`
+    pub fn find(&self, name: &str) -> Option<ElfSection> {
+        self.iter().find(|s| s.addr != 0 && self.name(s) == Some(name))
+    }
+}
+
+/// A module (e.g. an initrd) the bootloader loaded into memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub start:   usize,
+    pub end:     usize,
+    pub cmdline: &'static str,
+}
+
+/// Fixed-capacity list of boot modules
+#[derive(Clone, Copy)]
+pub struct ModuleList {
+    modules: [Module; MAX_MODULES],
+    len:     usize,
+}
+
+impl ModuleList {
+    pub const fn new() -> Self {
+        const EMPTY: Module = Module { start: 0, end: 0, cmdline: "" };
+        ModuleList { modules: [EMPTY; MAX_MODULES], len: 0 }
+    }
+
+    /// Append a module; returns false once `MAX_MODULES` are stored
+    pub fn push(&mut self, module: Module) -> bool {
+        if self.len == MAX_MODULES {
+            return false;
+        }
+        self.modules[self.len] = module;
+        self.len += 1;
+        true
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, Module> {
+        self.modules[..self.len].iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// How framebuffer pixels encode colour
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramebufferKind {
+    /// Palette-indexed pixels; `palette_addr` points at RGB byte triples
+    Indexed { palette_addr: usize, num_colors: u16 },
+    /// Direct colour: bit position and width of each channel
+    Rgb {
+        red_pos:    u8,
+        red_size:   u8,
+        green_pos:  u8,
+        green_size: u8,
+        blue_pos:   u8,
+        blue_size:  u8,
+    },
+    /// EGA text mode: width/height are in characters, `bpp` is 16
+    EgaText,
+}
+
+/// Linear framebuffer the bootloader set up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub addr:   u64,
+    pub pitch:  u32,
+    pub width:  u32,
+    pub height: u32,
+    pub bpp:    u8,
+    pub kind:   FramebufferKind,
+}
+
+impl FramebufferKind {
+    /// Decode a Multiboot1 `framebuffer_type` and its 6-byte `color_info`
+    pub fn decode(typ: u8, color_info: [u8; 6]) -> Option<Self> {
+        let c = color_info;
+        match typ {
+            0 => Some(FramebufferKind::Indexed {
+                palette_addr: u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize,
+                num_colors:   u16::from_le_bytes([c[4], c[5]]),
+            }),
+            1 => Some(FramebufferKind::Rgb {
+                red_pos:    c[0],
+                red_size:   c[1],
+                green_pos:  c[2],
+                green_size: c[3],
+                blue_pos:   c[4],
+                blue_size:  c[5],
+            }),
+            2 => Some(FramebufferKind::EgaText),
+            _ => None,
+        }
+    }
+}
+
+/// Raw `mods_addr` array entry
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct RawModule {
+    mod_start: u32,
+    mod_end:   u32,
+    string:    u32,
+    reserved:  u32,
+}
+
+/// Raw `mmap_*` entry; `size` counts the bytes *after* the size field
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct RawMmapEntry {
+    size:      u32,
+    base_addr: u64,
+    length:    u64,
+    kind:      u32,
+}
+
+/// Iterator over the raw Multiboot memory-map buffer. It stops at the
+/// first entry that is too short or would extend past `mmap_length`.
+pub struct MmapIter {
+    cur: usize,
+    end: usize,
+}
+
+impl MmapIter {
+    /// SAFETY: `[addr, addr + length)` must be readable memory.
+    pub unsafe fn new(addr: usize, length: usize) -> Self {
+        let end = addr.checked_add(length).unwrap_or(addr);
+        MmapIter { cur: addr, end }
+    }
+}
+
+impl Iterator for MmapIter {
+    type Item = MemoryRegion;
+
+    fn next(&mut self) -> Option<MemoryRegion> {
+        const SIZE_FIELD: usize = size_of::<u32>();
+        let remaining = self.end - self.cur;
+        if remaining < size_of::<RawMmapEntry>() {
+            return None;
+        }
+
+        // SAFETY: a full entry lies inside the buffer handed to `new`
+        let raw = unsafe { read_unaligned(self.cur as *const RawMmapEntry) };
+        let stride = (raw.size as usize).saturating_add(SIZE_FIELD);
+        if stride < size_of::<RawMmapEntry>() || stride > remaining {
+            // malformed entry: refuse to walk any further
+            self.cur = self.end;
+            return None;
+        }
+
+        self.cur += stride;
+        Some(MemoryRegion {
+            base:   raw.base_addr,
+            length: raw.length,
+            kind:   RegionKind::from_raw(raw.kind),
+        })
+    }
+}
+
+/// Build the memory map from the MBI. Falls back to the basic
+/// `mem_lower`/`mem_upper` fields when no full map was provided.
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_memory_map(mbi_addr: usize) -> MemoryMap {
+    let info = MultibootInfo::read(mbi_addr);
+    let mut map = MemoryMap::new();
+
+    if info.flags & FLAG_MMAP != 0 {
+        for region in MmapIter::new(info.mmap_addr as usize, info.mmap_length as usize) {
+            if !map.push(region) {
+                break;
+            }
+        }
+    } else if info.flags & FLAG_MEM != 0 {
+        // lower memory starts at 0, upper memory at 1 MiB; both in KiB
+        map.push(MemoryRegion {
+            base:   0,
+            length: info.mem_lower as u64 * 1024,
+            kind:   RegionKind::Usable,
+        });
+        map.push(MemoryRegion {
+            base:   0x10_0000,
+            length: info.mem_upper as u64 * 1024,
+            kind:   RegionKind::Usable,
+        });
+    }
+
+    map
+}
+
+/// Interpret bytes up to the first NUL as UTF-8. Invalid input is handled
+/// lossily by keeping the longest valid prefix, since there is no heap to
+/// build a string with replacement characters.
+pub fn cmdline_from_bytes(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..len];
+    match core::str::from_utf8(bytes) {
+        Ok(s)  => s,
+        // SAFETY: `valid_up_to` marks the end of a valid UTF-8 prefix
+        Err(e) => unsafe { core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+    }
+}
+
+/// Borrow a NUL-terminated string at `addr`, scanning at most `max` bytes
+///
+/// SAFETY: `[addr, addr + max)` (or up to the NUL) must be readable.
+pub unsafe fn c_str_bytes(addr: usize, max: usize) -> &'static [u8] {
+    let mut len = 0;
+    while len < max && *((addr + len) as *const u8) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(addr as *const u8, len)
+}
+
+/// The kernel command line, if the loader set the `cmdline` flag
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_cmdline(mbi_addr: usize) -> Option<&'static str> {
+    let info = MultibootInfo::read(mbi_addr);
+    if info.flags & FLAG_CMDLINE == 0 || info.cmdline == 0 {
+        return None;
+    }
+    Some(cmdline_from_bytes(c_str_bytes(info.cmdline as usize, MAX_CMDLINE)))
+}
+
+/// Read the MBI `flags` word (which of the other fields are valid).
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_flags(mbi_addr: usize) -> u32 {
+    MultibootInfo::read(mbi_addr).flags
+}
+
+/// Read the boot-module array. The whole `mods_count` array must sit in
+/// usable RAM according to `map` (when the map is known), so a corrupt
+/// count cannot walk into unmapped memory; otherwise no modules are
+/// returned. At most `MAX_MODULES` entries are kept.
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_modules(mbi_addr: usize, map: &MemoryMap) -> ModuleList {
+    let info = MultibootInfo::read(mbi_addr);
+    let mut list = ModuleList::new();
+    if info.flags & FLAG_MODS == 0 || info.mods_count == 0 {
+        return list;
+    }
+
+    let count = info.mods_count as u64;
+    let bytes = count * size_of::<RawModule>() as u64;
+    if !map.is_empty() && !map.covers_usable(info.mods_addr as u64, bytes) {
+        return list;
+    }
+
+    for i in 0..(count as usize).min(MAX_MODULES) {
+        let entry = info.mods_addr as usize + i * size_of::<RawModule>();
+        let raw = read_unaligned(entry as *const RawModule);
+        let cmdline = if raw.string == 0 {
+            ""
+        } else {
+            cmdline_from_bytes(c_str_bytes(raw.string as usize, MAX_CMDLINE))
+        };
+        list.push(Module { start: raw.mod_start as usize, end: raw.mod_end as usize, cmdline });
+    }
+    list
+}
+
+/// The ELF section header table from `syms`, or `None` if flag bit 5 is
+/// clear, the headers are not 64-bit, or (when the map is known) the
+/// `num * size` table does not lie in usable RAM
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_elf_sections(mbi_addr: usize, map: &MemoryMap) -> Option<ElfSections> {
+    let info = MultibootInfo::read(mbi_addr);
+    if info.flags & FLAG_ELF_SHDR == 0 {
+        return None;
+    }
+    // syms = num, size, addr, shndx for the ELF variant
+    let [num, size, addr, shndx] = info.syms;
+    let bytes = num as u64 * size as u64;
+    if !map.is_empty() && !map.covers_usable(addr as u64, bytes) {
+        return None;
+    }
+    ElfSections::new(num as usize, size as usize, addr as usize, shndx as usize)
+}
+
+/// Framebuffer description, or `None` if flag bit 12 is clear or the
+/// framebuffer type is unknown
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot info structure.
+pub unsafe fn parse_framebuffer(mbi_addr: usize) -> Option<Framebuffer> {
+    let info = MultibootInfo::read(mbi_addr);
+    if info.flags & FLAG_FRAMEBUFFER == 0 {
+        return None;
+    }
+    Some(Framebuffer {
+        addr:   info.framebuffer_addr,
+        pitch:  info.framebuffer_pitch,
+        width:  info.framebuffer_width,
+        height: info.framebuffer_height,
+        bpp:    info.framebuffer_bpp,
+        kind:   FramebufferKind::decode(info.framebuffer_type, info.color_info)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An MBI with only `flags` set. Tests pass its address like the
+    /// loader does; what it points at must be in low memory (statics).
+    fn mbi(flags: u32) -> MultibootInfo {
+        // SAFETY: all-zero is a valid MBI (every field is an integer)
+        MultibootInfo { flags, ..unsafe { core::mem::zeroed() } }
+    }
+
+    fn addr_of(info: &MultibootInfo) -> usize {
+        info as *const MultibootInfo as usize
+    }
+
+    #[test_case]
+    fn header_checksum_wraps_to_zero() {
+        let flags = 0x0000_0003;
+        let checksum = 0u32.wrapping_sub(HEADER_MAGIC + flags);
+        let header = Header { magic: HEADER_MAGIC, flags, checksum };
+        assert!(header.is_valid());
+    }
+
+    #[test_case]
+    fn header_with_a_bad_checksum_is_rejected() {
+        let header = Header { magic: HEADER_MAGIC, flags: 3, checksum: 0 };
+        assert!(!header.is_valid());
+    }
+
+    #[test_case]
+    fn header_with_the_loader_magic_is_rejected() {
+        // the value the loader passes in EAX, not the one in the image
+        let magic = 0x2BAD_B002u32;
+        let header = Header { magic, flags: 0, checksum: 0u32.wrapping_sub(magic) };
+        assert!(!header.is_valid());
+    }
+
+    #[test_case]
+    fn cmdline_stops_at_the_nul() {
+        assert_eq!(cmdline_from_bytes(b"console=ttyS0\0garbage"), "console=ttyS0");
+        assert_eq!(cmdline_from_bytes(b"no terminator"), "no terminator");
+        assert_eq!(cmdline_from_bytes(b"\0"), "");
+    }
+
+    #[test_case]
+    fn cmdline_keeps_the_valid_utf8_prefix() {
+        assert_eq!(cmdline_from_bytes(b"root=/dev/sda\xff\xfequiet\0"), "root=/dev/sda");
+        assert_eq!(cmdline_from_bytes("héllo\0".as_bytes()), "héllo");
+    }
+
+    #[test_case]
+    fn c_str_bytes_scans_at_most_max() {
+        let s = b"abcdef\0";
+        assert_eq!(unsafe { c_str_bytes(s.as_ptr() as usize, 16) }, b"abcdef");
+        assert_eq!(unsafe { c_str_bytes(s.as_ptr() as usize, 3) }, b"abc");
+    }
+
+    #[test_case]
+    fn parse_cmdline_honours_the_flag() {
+        static CMDLINE: [u8; 11] = *b"log=debug \0";
+        let mut info = mbi(FLAG_CMDLINE);
+        info.cmdline = CMDLINE.as_ptr() as u32;
+        assert_eq!(unsafe { parse_cmdline(addr_of(&info)) }, Some("log=debug "));
+
+        info.flags = 0;
+        assert_eq!(unsafe { parse_cmdline(addr_of(&info)) }, None);
+        // flag set but no string
+        let info = mbi(FLAG_CMDLINE);
+        assert_eq!(unsafe { parse_cmdline(addr_of(&info)) }, None);
+    }
+
+    /// Loader-owned memory the MBI points at by 32-bit address, filled in
+    /// by a test before it parses
+    struct LoaderMemory<T>(core::cell::UnsafeCell<T>);
+
+    // SAFETY: tests run one at a time on the boot CPU
+    unsafe impl<T> Sync for LoaderMemory<T> {}
+
+    const NO_MODULE: RawModule = RawModule { mod_start: 0, mod_end: 0, string: 0, reserved: 0 };
+
+    static MODULES: LoaderMemory<[RawModule; 2]> =
+        LoaderMemory(core::cell::UnsafeCell::new([NO_MODULE; 2]));
+    static INITRD: [u8; 12] = *b"initrd rw=1\0";
+
+    /// An MBI listing two modules, the first with a command line
+    fn mbi_with_modules() -> MultibootInfo {
+        let table = MODULES.0.get();
+        // SAFETY: nothing else touches `MODULES` while a test runs
+        unsafe {
+            *table = [
+                RawModule { mod_start: 0x20_0000, mod_end: 0x28_0000, ..NO_MODULE },
+                RawModule { mod_start: 0x30_0000, mod_end: 0x30_1000, ..NO_MODULE },
+            ];
+            (*table)[0].string = INITRD.as_ptr() as u32;
+        }
+        let mut info = mbi(FLAG_MODS);
+        info.mods_count = 2;
+        info.mods_addr  = table as u32;
+        info
+    }
+
+    #[test_case]
+    fn parse_modules_reads_every_entry() {
+        let info = mbi_with_modules();
+        let modules = unsafe { parse_modules(addr_of(&info), &MemoryMap::new()) };
+        assert_eq!(modules.len(), 2);
+        let mut it = modules.iter();
+        let initrd = Module { start: 0x20_0000, end: 0x28_0000, cmdline: "initrd rw=1" };
+        assert_eq!(it.next(), Some(&initrd));
+        assert_eq!(it.next(), Some(&Module { start: 0x30_0000, end: 0x30_1000, cmdline: "" }));
+    }
+
+    #[test_case]
+    fn parse_modules_needs_the_flag() {
+        let mut info = mbi_with_modules();
+        info.flags = 0;
+        assert!(unsafe { parse_modules(addr_of(&info), &MemoryMap::new()) }.is_empty());
+    }
+
+    #[test_case]
+    fn parse_modules_rejects_a_table_outside_usable_ram() {
+        let info = mbi_with_modules();
+        let table = info.mods_addr as u64;
+        let mut map = MemoryMap::new();
+        // usable RAM ending halfway through the second entry
+        let base = table & !0xfff;
+        map.push(MemoryRegion { base, length: table - base + 24, kind: RegionKind::Usable });
+        assert!(unsafe { parse_modules(addr_of(&info), &map) }.is_empty());
+        map.push(MemoryRegion { base: table, length: 32, kind: RegionKind::Usable });
+        assert_eq!(unsafe { parse_modules(addr_of(&info), &map) }.len(), 2);
+    }
+
+    #[test_case]
+    fn module_list_holds_at_most_max_modules() {
+        let mut list = ModuleList::new();
+        let module = Module { start: 0, end: 0x1000, cmdline: "" };
+        for _ in 0..MAX_MODULES {
+            assert!(list.push(module));
+        }
+        assert!(!list.push(module));
+        assert_eq!(list.len(), MAX_MODULES);
+    }
+
+    #[test_case]
+    fn framebuffer_kind_decodes_each_type() {
+        let info = [16, 8, 8, 8, 0, 8];
+        let rgb = FramebufferKind::Rgb {
+            red_pos: 16, red_size: 8, green_pos: 8, green_size: 8, blue_pos: 0, blue_size: 8,
+        };
+        assert_eq!(FramebufferKind::decode(1, info), Some(rgb));
+        let palette = FramebufferKind::Indexed { palette_addr: 0x0808_0810, num_colors: 0x0800 };
+        assert_eq!(FramebufferKind::decode(0, info), Some(palette));
+        assert_eq!(FramebufferKind::decode(2, info), Some(FramebufferKind::EgaText));
+        assert_eq!(FramebufferKind::decode(3, info), None);
+    }
+
+    #[test_case]
+    fn parse_framebuffer_reads_the_geometry() {
+        let mut info = mbi(FLAG_FRAMEBUFFER);
+        info.framebuffer_addr   = 0xfd00_0000;
+        info.framebuffer_pitch  = 4096;
+        info.framebuffer_width  = 1024;
+        info.framebuffer_height = 768;
+        info.framebuffer_bpp    = 32;
+        info.framebuffer_type   = 1;
+        info.color_info         = [16, 8, 8, 8, 0, 8];
+        let fb = unsafe { parse_framebuffer(addr_of(&info)) }.unwrap();
+        let geometry = (fb.addr, fb.pitch, fb.width, fb.height, fb.bpp);
+        assert_eq!(geometry, (0xfd00_0000, 4096, 1024, 768, 32));
+        assert!(matches!(fb.kind, FramebufferKind::Rgb { red_pos: 16, .. }));
+
+        info.framebuffer_type = 7;
+        assert_eq!(unsafe { parse_framebuffer(addr_of(&info)) }, None);
+        info.framebuffer_type = 1;
+        info.flags = 0;
+        assert_eq!(unsafe { parse_framebuffer(addr_of(&info)) }, None);
+    }
+
+    fn map(regions: &[(u64, u64, RegionKind)]) -> MemoryMap {
+        let mut map = MemoryMap::new();
+        for &(base, length, kind) in regions {
+            map.push(MemoryRegion { base, length, kind });
+        }
+        map
+    }
+
+    #[test_case]
+    fn byte_totals_count_overlaps_once() {
+        let map = map(&[
+            (0x0,       0x9fc00,  RegionKind::Usable),
+            (0x9fc00,   0x400,    RegionKind::Reserved),
+            (0x100000,  0x100000, RegionKind::Usable),
+            (0x180000,  0x100000, RegionKind::Usable),   // overlaps the one above
+            (0x200000,  0x1000,   RegionKind::Reserved), // a hole inside it
+            (0x300000,  0,        RegionKind::Usable),
+        ]);
+        assert_eq!(map.total_bytes(), 0xa0000 + 0x180000);
+        // the reserved page inside usable RAM is not usable
+        assert_eq!(map.usable_bytes(), 0x9fc00 + 0x180000 - 0x1000);
+    }
+
+    #[test_case]
+    fn byte_totals_stop_at_the_top_of_the_address_space() {
+        let map = map(&[(u64::MAX - 0xfff, 0x2000, RegionKind::Usable)]);
+        assert_eq!(map.total_bytes(), 0xfff);
+        assert_eq!(map.usable_bytes(), 0xfff);
+        assert_eq!(MemoryMap::new().total_bytes(), 0);
+    }
+
+    /// An out-of-order map with touching, nested and empty regions
+    fn messy_map() -> MemoryMap {
+        map(&[
+            (0x200000,  0x100000, RegionKind::Usable),
+            (0x0,       0x9fc00,  RegionKind::Usable),
+            (0x100000,  0x100000, RegionKind::Usable),   // touches the first
+            (0x9fc00,   0x400,    RegionKind::Reserved),
+            (0xf0000,   0x10000,  RegionKind::Reserved),
+            (0x150000,  0x10,     RegionKind::Usable),   // nested
+            (0x500000,  0,        RegionKind::Usable),   // empty
+        ])
+    }
+
+    #[test_case]
+    fn normalized_sorts_and_merges_same_kind_neighbours() {
+        let normal = messy_map().normalized();
+        let expected = [
+            MemoryRegion { base: 0x0,      length: 0x9fc00,  kind: RegionKind::Usable },
+            MemoryRegion { base: 0x9fc00,  length: 0x400,    kind: RegionKind::Reserved },
+            MemoryRegion { base: 0xf0000,  length: 0x10000,  kind: RegionKind::Reserved },
+            MemoryRegion { base: 0x100000, length: 0x200000, kind: RegionKind::Usable },
+        ];
+        // the usable and reserved regions at 0x9fc00 touch but stay apart
+        assert_eq!(normal.regions(), &expected);
+        // a second pass changes nothing
+        assert_eq!(normal.normalized().regions(), &expected);
+    }
+
+    #[test_case]
+    fn normalized_keeps_the_truncated_flag() {
+        let mut full = MemoryMap::new();
+        for i in 0..=MAX_REGIONS as u64 {
+            full.push(MemoryRegion { base: i * 0x1000, length: 0x1000, kind: RegionKind::Usable });
+        }
+        let normal = full.normalized();
+        assert!(normal.truncated());
+        assert_eq!(normal.len(), 1);
+    }
+
+    #[test_case]
+    fn contains_usable_checks_inside_between_and_above() {
+        let normal = messy_map().normalized();
+        for addr in [0x0, 0x500, 0x9fbff, 0x100000, 0x150008, 0x2fffff] {
+            assert!(normal.contains_usable(addr), "{:#x}", addr);
+        }
+        // reserved, in a hole, past the end
+        for addr in [0x9fc00, 0xa0000, 0xf8000, 0x300000, usize::MAX] {
+            assert!(!normal.contains_usable(addr), "{:#x}", addr);
+        }
+        assert!(!MemoryMap::new().contains_usable(0));
+    }
+
+    const NO_SECTION: ElfSection = ElfSection {
+        name: 0, typ: 0, flags: 0, addr: 0, offset: 0, size: 0,
+        link: 0, info: 0, addralign: 0, entsize: 0,
+    };
+
+    static SECTIONS: LoaderMemory<[ElfSection; 4]> =
+        LoaderMemory(core::cell::UnsafeCell::new([NO_SECTION; 4]));
+    static SHSTRTAB: [u8; 26] = *b"\0.shstrtab\0.symtab\0.debug\0";
+
+    /// An MBI whose `syms` describes a null section, the section names,
+    /// a loaded `.symtab` and an unloaded `.debug`
+    fn mbi_with_sections() -> MultibootInfo {
+        let table = SECTIONS.0.get();
+        // SAFETY: nothing else touches `SECTIONS` while a test runs
+        unsafe {
+            *table = [
+                NO_SECTION,
+                ElfSection {
+                    name: 1, typ: SHT_STRTAB, addr: SHSTRTAB.as_ptr() as u64,
+                    size: SHSTRTAB.len() as u64, ..NO_SECTION
+                },
+                ElfSection { name: 11, typ: SHT_SYMTAB, addr: 0x40_0000, size: 0x180, ..NO_SECTION },
+                ElfSection { name: 19, typ: 1, size: 0x800, ..NO_SECTION },
+            ];
+        }
+        let mut info = mbi(FLAG_ELF_SHDR);
+        info.syms = [4, size_of::<ElfSection>() as u32, table as u32, 1];
+        info
+    }
+
+    #[test_case]
+    fn parse_elf_sections_decodes_each_header() {
+        let info = mbi_with_sections();
+        let sections = unsafe { parse_elf_sections(addr_of(&info), &MemoryMap::new()) }.unwrap();
+        assert_eq!(sections.len(), 4);
+        let start = info.syms[2] as usize;
+        assert_eq!(sections.table_range(), (start, start + 4 * 64));
+        let symtab = sections.get(2).unwrap();
+        assert_eq!((symtab.typ, symtab.addr, symtab.size), (SHT_SYMTAB, 0x40_0000, 0x180));
+        assert_eq!(sections.get(4), None);
+        assert_eq!(sections.iter().count(), 4);
+    }
+
+    #[test_case]
+    fn elf_sections_are_found_by_name() {
+        let info = mbi_with_sections();
+        let sections = unsafe { parse_elf_sections(addr_of(&info), &MemoryMap::new()) }.unwrap();
+        assert_eq!(sections.name(&sections.get(1).unwrap()), Some(".shstrtab"));
+        assert_eq!(sections.find(".symtab"), sections.get(2));
+        // named, but not loaded
+        assert_eq!(sections.name(&sections.get(3).unwrap()), Some(".debug"));
+        assert_eq!(sections.find(".debug"), None);
+        // a name offset past the end of the table
+        let stray = ElfSection { name: 26, ..NO_SECTION };
+        assert_eq!(sections.name(&stray), None);
+    }
+
+    #[test_case]
+    fn parse_elf_sections_checks_the_flag_and_the_table() {
+        let mut info = mbi_with_sections();
+        let empty = MemoryMap::new();
+        info.flags = 0;
+        assert!(unsafe { parse_elf_sections(addr_of(&info), &empty) }.is_none());
+        // 32-bit headers
+        info.flags = FLAG_ELF_SHDR;
+        info.syms[1] = 40;
+        assert!(unsafe { parse_elf_sections(addr_of(&info), &empty) }.is_none());
+        // a table running past usable RAM
+        info.syms[1] = 64;
+        let table = info.syms[2] as u64;
+        let mut map = MemoryMap::new();
+        map.push(MemoryRegion { base: table, length: 3 * 64, kind: RegionKind::Usable });
+        assert!(unsafe { parse_elf_sections(addr_of(&info), &map) }.is_none());
+        // num * size overflowing the address space
+        assert!(unsafe { ElfSections::new(usize::MAX / 64 + 1, 64, 0, 0) }.is_none());
+        assert!(unsafe { ElfSections::new(2, 64, usize::MAX - 64, 0) }.is_none());
+    }
+}
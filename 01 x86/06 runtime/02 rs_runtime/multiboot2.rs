@@ -0,0 +1,451 @@
+// rooster-os/arch/x86/runtime/rs_runtime/multiboot2.rs
+//! Multiboot2 boot-information parsing for the Rust runtime.
+//!   • Walks the type/size tag list (tags are 8-byte aligned)
+//!   • Extracts the memory map (tag 6), command line (tag 1),
+//!     boot modules (tag 3), framebuffer (tag 8) and ELF section
+//!     headers (tag 9)
+//!   • Every tag and entry is bounds-checked against `total_size`
+
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+
+use crate::multiboot::{
+    cmdline_from_bytes, ElfSections, Framebuffer, FramebufferKind, MemoryMap, MemoryRegion, Module,
+    ModuleList, RegionKind,
+};
+
+pub const TAG_END:          u32 = 0;
+pub const TAG_CMDLINE:      u32 = 1;
+pub const TAG_MODULE:       u32 = 3;
+pub const TAG_MMAP:         u32 = 6;
+pub const TAG_FRAMEBUFFER:  u32 = 8;
+pub const TAG_ELF_SECTIONS: u32 = 9;
+
+/// Common header at the start of every tag
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TagHeader {
+    typ:  u32,
+    size: u32,
+}
+
+/// A tag found in the boot information; `addr`/`len` describe its
+/// payload, i.e. everything after the 8-byte header
+#[derive(Clone, Copy, Debug)]
+pub struct Tag {
+    pub typ:  u32,
+    pub addr: usize,
+    pub len:  usize,
+}
+
+impl Tag {
+    /// SAFETY: the tag must come from a `TagIter` over valid memory.
+    pub unsafe fn payload(&self) -> &'static [u8] {
+        core::slice::from_raw_parts(self.addr as *const u8, self.len)
+    }
+}
+
+/// Iterator over the tag list; ends at the terminator tag or at the
+/// first tag whose size is too small or runs past `total_size`
+pub struct TagIter {
+    cur: usize,
+    end: usize,
+}
+
+impl TagIter {
+    /// SAFETY: `mbi_addr` must point at a readable Multiboot2 info block.
+    pub unsafe fn new(mbi_addr: usize) -> Self {
+        let total_size = read_unaligned(mbi_addr as *const u32) as usize;
+        let end = mbi_addr.checked_add(total_size).unwrap_or(mbi_addr);
+        // fixed part: total_size + reserved
+        let cur = (mbi_addr + 8).min(end);
+        TagIter { cur, end }
+    }
+}
+
+impl Iterator for TagIter {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        const HEADER: usize = size_of::<TagHeader>();
+        let remaining = self.end - self.cur;
+        if remaining < HEADER {
+            return None;
+        }
+
+        // SAFETY: the header lies inside the block handed to `new`
+        let header = unsafe { read_unaligned(self.cur as *const TagHeader) };
+        let size = header.size as usize;
+        if header.typ == TAG_END || size < HEADER || size > remaining {
+            self.cur = self.end;
+            return None;
+        }
+
+        let tag = Tag { typ: header.typ, addr: self.cur + HEADER, len: size - HEADER };
+        // the next tag starts at the following 8-byte boundary
+        let stride = (size + 7) & !7;
+        self.cur += stride.min(remaining);
+        Some(tag)
+    }
+}
+
+/// Raw memory-map entry following the tag's entry_size/entry_version
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawMmapEntry {
+    base_addr: u64,
+    length:    u64,
+    kind:      u32,
+    reserved:  u32,
+}
+
+/// Everything the runtime extracts from a Multiboot2 handoff
+pub struct Multiboot2Info {
+    pub memory_map:   MemoryMap,
+    pub cmdline:      Option<&'static str>,
+    pub modules:      ModuleList,
+    pub framebuffer:  Option<Framebuffer>,
+    pub elf_sections: Option<ElfSections>,
+}
+
+/// Decode a memory-map tag into `map`, honouring the declared entry size
+unsafe fn parse_mmap(tag: &Tag, map: &mut MemoryMap) {
+    if tag.len < 8 {
+        return;
+    }
+    let entry_size = read_unaligned(tag.addr as *const u32) as usize;
+    if entry_size < size_of::<RawMmapEntry>() {
+        return;
+    }
+
+    let mut cur = tag.addr + 8;
+    let end = tag.addr + tag.len;
+    while end - cur >= entry_size {
+        let raw = read_unaligned(cur as *const RawMmapEntry);
+        let region = MemoryRegion {
+            base:   raw.base_addr,
+            length: raw.length,
+            kind:   RegionKind::from_raw(raw.kind),
+        };
+        if !map.push(region) {
+            break;
+        }
+        cur += entry_size;
+    }
+}
+
+/// Decode a NUL-terminated command-line tag
+unsafe fn parse_cmdline(tag: &Tag) -> Option<&'static str> {
+    Some(cmdline_from_bytes(tag.payload()))
+}
+
+/// Decode a module tag: mod_start, mod_end, then a NUL-terminated string
+unsafe fn parse_module(tag: &Tag) -> Option<Module> {
+    if tag.len < 8 {
+        return None;
+    }
+    let payload = tag.payload();
+    Some(Module {
+        start:   read_unaligned(tag.addr as *const u32) as usize,
+        end:     read_unaligned((tag.addr + 4) as *const u32) as usize,
+        cmdline: cmdline_from_bytes(&payload[8..]),
+    })
+}
+
+/// Decode a framebuffer tag. Unlike Multiboot1, an indexed palette is
+/// stored inline (u16 count, then RGB triples) rather than by address.
+unsafe fn parse_framebuffer(tag: &Tag) -> Option<Framebuffer> {
+    // addr u64, pitch/width/height u32, bpp u8, type u8, reserved u16
+    if tag.len < 24 {
+        return None;
+    }
+    let p = tag.payload();
+    let kind = match p[21] {
+        0 if tag.len >= 26 => FramebufferKind::Indexed {
+            palette_addr: tag.addr + 26,
+            num_colors:   u16::from_le_bytes([p[24], p[25]]),
+        },
+        1 if tag.len >= 30 => {
+            let mut info = [0u8; 6];
+            info.copy_from_slice(&p[24..30]);
+            FramebufferKind::decode(1, info)?
+        }
+        2 => FramebufferKind::EgaText,
+        _ => return None,
+    };
+    Some(Framebuffer {
+        addr:   read_unaligned(tag.addr as *const u64),
+        pitch:  read_unaligned((tag.addr + 8) as *const u32),
+        width:  read_unaligned((tag.addr + 12) as *const u32),
+        height: read_unaligned((tag.addr + 16) as *const u32),
+        bpp:    p[20],
+        kind,
+    })
+}
+
+/// Decode an ELF-sections tag: num, entsize and shndx (u32 each, as GRUB
+/// writes them), then the headers themselves, which must fit in the tag
+unsafe fn parse_elf_sections(tag: &Tag) -> Option<ElfSections> {
+    if tag.len < 12 {
+        return None;
+    }
+    let num   = read_unaligned(tag.addr as *const u32) as usize;
+    let size  = read_unaligned((tag.addr + 4) as *const u32) as usize;
+    let shndx = read_unaligned((tag.addr + 8) as *const u32) as usize;
+    if num.checked_mul(size)? > tag.len - 12 {
+        return None;
+    }
+    ElfSections::new(num, size, tag.addr + 12, shndx)
+}
+
+/// Walk all tags and collect the ones the runtime understands.
+///
+/// SAFETY: `mbi_addr` must point at a valid Multiboot2 info block.
+pub unsafe fn parse(mbi_addr: usize) -> Multiboot2Info {
+    let mut info = Multiboot2Info {
+        memory_map:   MemoryMap::new(),
+        cmdline:      None,
+        modules:      ModuleList::new(),
+        framebuffer:  None,
+        elf_sections: None,
+    };
+    for tag in TagIter::new(mbi_addr) {
+        match tag.typ {
+            TAG_MMAP         => parse_mmap(&tag, &mut info.memory_map),
+            TAG_CMDLINE      => info.cmdline = parse_cmdline(&tag),
+            TAG_FRAMEBUFFER  => info.framebuffer = parse_framebuffer(&tag),
+            TAG_ELF_SECTIONS => info.elf_sections = parse_elf_sections(&tag),
+            TAG_MODULE       => {
+                if let Some(module) = parse_module(&tag) {
+                    info.modules.push(module);
+                }
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Multiboot2 information block assembled the way a loader lays
+    /// it out: total_size, reserved, then 8-byte aligned tags
+    #[repr(C, align(8))]
+    struct Block {
+        bytes: [u8; 512],
+        len:   usize,
+    }
+
+    impl Block {
+        fn new() -> Self {
+            Block { bytes: [0; 512], len: 8 }
+        }
+
+        fn put(&mut self, bytes: &[u8]) -> &mut Self {
+            self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+            self
+        }
+
+        /// Start a tag of type `typ`; returns where it begins for `end`
+        fn begin(&mut self, typ: u32) -> usize {
+            let at = self.len;
+            self.put(&typ.to_le_bytes()).put(&[0; 4]);
+            at
+        }
+
+        /// Fill in the size of the tag begun at `at` and pad to 8 bytes
+        fn end(&mut self, at: usize) {
+            let size = (self.len - at) as u32;
+            self.bytes[at + 4..at + 8].copy_from_slice(&size.to_le_bytes());
+            self.len = (self.len + 7) & !7;
+        }
+
+        fn tag(&mut self, typ: u32, payload: &[u8]) -> &mut Self {
+            let at = self.begin(typ);
+            self.put(payload);
+            self.end(at);
+            self
+        }
+
+        /// Terminate the list, set total_size and return the block's address
+        fn finish(&mut self) -> usize {
+            self.tag(TAG_END, &[]);
+            let total = self.len as u32;
+            self.bytes[..4].copy_from_slice(&total.to_le_bytes());
+            self.bytes.as_ptr() as usize
+        }
+    }
+
+    #[test_case]
+    fn parses_the_memory_map_and_command_line() {
+        let mut block = Block::new();
+        block.tag(TAG_CMDLINE, b"console=ttyS0 quiet\0");
+        let at = block.begin(TAG_MMAP);
+        block.put(&24u32.to_le_bytes()).put(&[0; 4]); // entry_size, version
+        let regions: [(u64, u64, u32); 3] =
+            [(0, 0x9fc00, 1), (0x10_0000, 0x7ee_0000, 1), (0xfffc_0000, 0x4_0000, 2)];
+        for (base, length, kind) in regions {
+            block.put(&base.to_le_bytes()).put(&length.to_le_bytes());
+            block.put(&kind.to_le_bytes()).put(&[0; 4]);
+        }
+        block.end(at);
+        let info = unsafe { parse(block.finish()) };
+
+        assert_eq!(info.cmdline, Some("console=ttyS0 quiet"));
+        let regions = info.memory_map.regions();
+        assert_eq!(regions.len(), 3);
+        let upper = MemoryRegion { base: 0x10_0000, length: 0x7ee_0000, kind: RegionKind::Usable };
+        assert_eq!(regions[1], upper);
+        assert_eq!(regions[2].kind, RegionKind::Reserved);
+    }
+
+    #[test_case]
+    fn unknown_tags_are_skipped() {
+        let mut block = Block::new();
+        block.tag(21, &[0xff; 13]).tag(TAG_CMDLINE, b"root=/dev/sda\0");
+        let info = unsafe { parse(block.finish()) };
+        assert_eq!(info.cmdline, Some("root=/dev/sda"));
+    }
+
+    #[test_case]
+    fn nothing_after_the_end_tag_is_read() {
+        let mut block = Block::new();
+        block.tag(TAG_END, &[]).tag(TAG_CMDLINE, b"hidden\0");
+        let info = unsafe { parse(block.finish()) };
+        assert_eq!(info.cmdline, None);
+    }
+
+    #[test_case]
+    fn a_tag_running_past_total_size_ends_the_walk() {
+        let mut block = Block::new();
+        block.tag(TAG_CMDLINE, b"first\0");
+        let at = block.begin(TAG_CMDLINE);
+        block.put(b"second\0");
+        block.end(at);
+        let addr = block.finish();
+        // claim the second tag is far larger than the block
+        block.bytes[at + 4..at + 8].copy_from_slice(&4096u32.to_le_bytes());
+
+        let mut tags = unsafe { TagIter::new(addr) };
+        assert!(tags.next().is_some_and(|t| t.typ == TAG_CMDLINE && t.len == 6));
+        assert!(tags.next().is_none());
+    }
+
+    #[test_case]
+    fn module_tags_become_modules() {
+        let mut block = Block::new();
+        let modules: [(u32, u32, &[u8]); 2] =
+            [(0x20_0000, 0x28_0000, b"initrd\0"), (0x30_0000, 0x30_1000, b"\0")];
+        for (start, end, cmdline) in modules {
+            let at = block.begin(TAG_MODULE);
+            block.put(&start.to_le_bytes()).put(&end.to_le_bytes()).put(cmdline);
+            block.end(at);
+        }
+        // too short to hold mod_start and mod_end
+        block.tag(TAG_MODULE, &[0; 4]);
+        let info = unsafe { parse(block.finish()) };
+
+        assert_eq!(info.modules.len(), 2);
+        let mut it = info.modules.iter();
+        assert_eq!(it.next(), Some(&Module { start: 0x20_0000, end: 0x28_0000, cmdline: "initrd" }));
+        assert_eq!(it.next(), Some(&Module { start: 0x30_0000, end: 0x30_1000, cmdline: "" }));
+    }
+
+    /// Payload of a framebuffer tag: a 1024x768 framebuffer of type
+    /// `typ`, followed by `extra`
+    fn framebuffer_tag(block: &mut Block, bpp: u8, typ: u8, extra: &[u8]) {
+        let at = block.begin(TAG_FRAMEBUFFER);
+        block.put(&0xfd00_0000u64.to_le_bytes()).put(&4096u32.to_le_bytes());
+        block.put(&1024u32.to_le_bytes()).put(&768u32.to_le_bytes());
+        block.put(&[bpp, typ, 0, 0]).put(extra);
+        block.end(at);
+    }
+
+    #[test_case]
+    fn framebuffer_tag_with_rgb_channels() {
+        let mut block = Block::new();
+        framebuffer_tag(&mut block, 32, 1, &[16, 8, 8, 8, 0, 8]);
+        let fb = unsafe { parse(block.finish()) }.framebuffer.unwrap();
+        let geometry = (fb.addr, fb.pitch, fb.width, fb.height, fb.bpp);
+        assert_eq!(geometry, (0xfd00_0000, 4096, 1024, 768, 32));
+        let rgb = FramebufferKind::Rgb {
+            red_pos: 16, red_size: 8, green_pos: 8, green_size: 8, blue_pos: 0, blue_size: 8,
+        };
+        assert_eq!(fb.kind, rgb);
+    }
+
+    #[test_case]
+    fn framebuffer_tag_palette_is_inline() {
+        let mut block = Block::new();
+        framebuffer_tag(&mut block, 8, 0, &[2, 0, 0xff, 0, 0, 0, 0xff, 0]);
+        let addr = block.finish();
+        let fb = unsafe { parse(addr) }.framebuffer.unwrap();
+        match fb.kind {
+            FramebufferKind::Indexed { palette_addr, num_colors } => {
+                assert_eq!(num_colors, 2);
+                // right after the u16 count, inside the tag itself
+                assert_eq!(unsafe { *(palette_addr as *const [u8; 6]) }, [0xff, 0, 0, 0, 0xff, 0]);
+            }
+            other => panic!("expected a palette, got {:?}", other),
+        }
+    }
+
+    #[test_case]
+    fn framebuffer_tag_of_unknown_type_is_ignored() {
+        let mut block = Block::new();
+        framebuffer_tag(&mut block, 16, 2, &[]);
+        framebuffer_tag(&mut block, 32, 9, &[]);
+        // the later tag wins, and it is not understood
+        assert_eq!(unsafe { parse(block.finish()) }.framebuffer, None);
+
+        let mut block = Block::new();
+        framebuffer_tag(&mut block, 16, 2, &[]);
+        let fb = unsafe { parse(block.finish()) }.framebuffer.unwrap();
+        assert_eq!(fb.kind, FramebufferKind::EgaText);
+    }
+
+    /// An ELF-sections tag claiming `num` headers of `entsize` bytes, with
+    /// `count` 64-byte headers actually present (header `i` has name `i`)
+    fn elf_sections_tag(block: &mut Block, num: u32, entsize: u32, count: u32) -> usize {
+        let at = block.begin(TAG_ELF_SECTIONS);
+        block.put(&num.to_le_bytes()).put(&entsize.to_le_bytes()).put(&1u32.to_le_bytes());
+        let table = block.bytes.as_ptr() as usize + block.len;
+        for i in 0..count {
+            block.put(&i.to_le_bytes()).put(&[0; 60]);
+        }
+        block.end(at);
+        table
+    }
+
+    #[test_case]
+    fn elf_sections_tag_points_at_its_own_table() {
+        let mut block = Block::new();
+        let table = elf_sections_tag(&mut block, 2, 64, 2);
+        let sections = unsafe { parse(block.finish()) }.elf_sections.unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections.table_range(), (table, table + 128));
+        assert_eq!(sections.get(1).map(|s| s.name), Some(1));
+        assert!(sections.get(2).is_none());
+    }
+
+    #[test_case]
+    fn elf_sections_tag_is_bounds_and_entsize_checked() {
+        // three headers claimed, two present: the table would overrun the tag
+        let mut block = Block::new();
+        elf_sections_tag(&mut block, 3, 64, 2);
+        assert!(unsafe { parse(block.finish()) }.elf_sections.is_none());
+
+        // entries too small to be an Elf64_Shdr
+        let mut block = Block::new();
+        elf_sections_tag(&mut block, 4, 32, 2);
+        assert!(unsafe { parse(block.finish()) }.elf_sections.is_none());
+
+        // no room for num, entsize and shndx
+        let mut block = Block::new();
+        block.tag(TAG_ELF_SECTIONS, &[2, 0, 0, 0, 64, 0, 0, 0]);
+        assert!(unsafe { parse(block.finish()) }.elf_sections.is_none());
+    }
+}
@@ -0,0 +1,617 @@
+// rooster-os/arch/x86/runtime/rs_runtime/paging.rs
+//! x86_64 4-level page-table helpers for the runtime.
+//!   • Flag constants and the 512-entry `PageTable` layout
+//!   • Walks the active tables from CR3 down to a 4 KiB PTE
+//!   • Splits 2 MiB pages into 4 KiB tables from a small static pool
+//!   • `enable_nx` sets EFER.NXE so PF_NX may be used at all
+//!   • `enforce_wx` applies W^X permissions to the kernel image
+//!   • `map_higher_half` adds the kernel alias at `KERNEL_OFFSET`
+//!   • `dump_mappings` prints the active tables as coalesced ranges
+//!
+//! The runtime runs on an identity map (the bootloader's, or ours with
+//! `higher-half`), so table physical addresses are dereferenced directly.
+
+use core::arch::asm;
+use core::fmt;
+
+use crate::cpu::CpuFeatures;
+use crate::msr::{self, MsrBackend};
+
+/* Page sizes & counts */
+pub const PAGE_SIZE:       usize = 4096;
+pub const LARGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+pub const ENTRIES:         usize = 512;
+
+/* Page-table entry flags */
+pub const PF_PRESENT:   u64 = 1 << 0;  // must be 1 to be valid
+pub const PF_RW:        u64 = 1 << 1;  // 0 = read-only; 1 = read/write
+pub const PF_USER:      u64 = 1 << 2;  // 0 = kernel-only; 1 = user-mode
+pub const PF_PWT:       u64 = 1 << 3;  // write-through
+pub const PF_PCD:       u64 = 1 << 4;  // cache disable
+pub const PF_ACCESSED:  u64 = 1 << 5;  // CPU sets on read/write
+pub const PF_DIRTY:     u64 = 1 << 6;  // CPU sets on write
+pub const PF_PAGE_SIZE: u64 = 1 << 7;  // PDE/PDPTE: maps a 2 MiB/1 GiB page
+pub const PF_GLOBAL:    u64 = 1 << 8;  // ignored unless CR4.PGE=1
+pub const PF_NX:        u64 = 1 << 63; // no-execute (needs EFER.NXE)
+
+/// Physical-address bits of a 4 KiB-aligned entry
+pub const ADDR_MASK:       u64 = 0x000F_FFFF_FFFF_F000;
+/// Physical-address bits of a 2 MiB page entry
+pub const LARGE_ADDR_MASK: u64 = 0x000F_FFFF_FFE0_0000;
+
+/// PAT bit position inside a large-page entry (bit 7 in a 4 KiB PTE)
+const PF_LARGE_PAT: u64 = 1 << 12;
+
+/// One level of the paging hierarchy
+#[repr(C, align(4096))]
+#[derive(Clone, Copy)]
+pub struct PageTable {
+    pub entries: [u64; ENTRIES],
+}
+
+impl PageTable {
+    pub const fn empty() -> Self {
+        PageTable { entries: [0; ENTRIES] }
+    }
+}
+
+/// Offset convention for the higher half: the image is linked and loaded
+/// at its physical address and additionally aliased at
+/// `virt = phys + KERNEL_OFFSET` (the top 2 GiB, so physical 0-2 GiB).
+/// `kernel_main` is entered through the alias: RIP-relative addresses in
+/// the kernel then resolve high, while absolute addresses fixed at link
+/// time (vtables, pointers in statics) stay low and remain valid because
+/// the identity map is never torn down.
+pub const KERNEL_OFFSET: usize = 0xFFFF_FFFF_8000_0000;
+/// Size of the higher-half alias: PDPT entries 510 and 511
+pub const HIGHER_HALF_SIZE: usize = 2 * 1024 * 1024 * 1024;
+/// Physical memory identity-mapped by `map_higher_half` (2 MiB pages);
+/// covers the low MMIO hole (LAPIC, IOAPIC) as well
+const IDENTITY_MAP_SIZE: usize = 4 * 1024 * 1024 * 1024;
+const HH_PD_COUNT: usize = IDENTITY_MAP_SIZE / (ENTRIES * LARGE_PAGE_SIZE);
+
+/// Higher-half alias of physical address `phys` (< `HIGHER_HALF_SIZE`)
+pub const fn phys_to_virt(phys: usize) -> usize {
+    phys + KERNEL_OFFSET
+}
+
+/// Physical address behind higher-half address `virt`
+pub const fn virt_to_phys(virt: usize) -> usize {
+    virt - KERNEL_OFFSET
+}
+
+/// Tables built by `map_higher_half`; the PDs are shared by the identity
+/// map and the alias, so a split or permission change applies to both
+struct HigherHalfTables {
+    pml4:      PageTable,
+    pdpt_low:  PageTable,
+    pdpt_high: PageTable,
+    pd:        [PageTable; HH_PD_COUNT],
+}
+
+impl HigherHalfTables {
+    const fn empty() -> Self {
+        HigherHalfTables {
+            pml4:      PageTable::empty(),
+            pdpt_low:  PageTable::empty(),
+            pdpt_high: PageTable::empty(),
+            pd:        [PageTable::empty(); HH_PD_COUNT],
+        }
+    }
+
+    /// Fill in the identity map of the low 4 GiB (2 MiB pages) and the
+    /// alias of the low 2 GiB; returns the PML4's physical address
+    fn build(&mut self) -> u64 {
+        let table = |t: &PageTable| {
+            phys_addr_of(t as *const PageTable as usize) | PF_PRESENT | PF_RW
+        };
+
+        for (i, pd) in self.pd.iter_mut().enumerate() {
+            for (j, pde) in pd.entries.iter_mut().enumerate() {
+                let phys = ((i * ENTRIES + j) * LARGE_PAGE_SIZE) as u64;
+                *pde = phys | PF_PRESENT | PF_RW | PF_PAGE_SIZE;
+            }
+        }
+        for (i, pd) in self.pd.iter().enumerate() {
+            self.pdpt_low.entries[i] = table(pd);
+        }
+        // KERNEL_OFFSET = PML4[511], PDPT[510]
+        self.pdpt_high.entries[table_index(KERNEL_OFFSET, 3)]     = table(&self.pd[0]);
+        self.pdpt_high.entries[table_index(KERNEL_OFFSET, 3) + 1] = table(&self.pd[1]);
+
+        self.pml4.entries[0] = table(&self.pdpt_low);
+        self.pml4.entries[table_index(KERNEL_OFFSET, 4)] = table(&self.pdpt_high);
+        phys_addr_of(&self.pml4 as *const PageTable as usize)
+    }
+}
+
+static mut HH_TABLES: HigherHalfTables = HigherHalfTables::empty();
+
+/// Page tables reserved for splitting 2 MiB pages
+const SPLIT_POOL_SIZE: usize = 4;
+static mut SPLIT_POOL: [PageTable; SPLIT_POOL_SIZE] = [PageTable::empty(); SPLIT_POOL_SIZE];
+static mut SPLIT_USED: usize = 0;
+
+/// Physical address of the active PML4
+pub fn read_cr3() -> usize {
+    let cr3: usize;
+    unsafe { asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+    cr3 & ADDR_MASK as usize
+}
+
+/// Load a new PML4 and flush all non-global TLB entries.
+///
+/// SAFETY: `pml4` must map the currently executing code and stack.
+pub unsafe fn write_cr3(pml4: usize) {
+    asm!("mov cr3, {}", in(reg) pml4, options(nostack, preserves_flags));
+}
+
+/// Flush a single page from the TLB
+#[inline]
+pub unsafe fn flush_tlb(virt: usize) {
+    asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags));
+}
+
+/// Index into the table at `level` (4 = PML4 … 1 = PT) for `virt`
+#[inline]
+pub fn table_index(virt: usize, level: u32) -> usize {
+    (virt >> (12 + 9 * (level - 1))) & (ENTRIES - 1)
+}
+
+/// Physical address of a runtime static: its address as seen from the
+/// higher-half alias or the identity map, whichever the code runs in
+fn phys_addr_of(addr: usize) -> u64 {
+    if addr >= KERNEL_OFFSET { (addr - KERNEL_OFFSET) as u64 } else { addr as u64 }
+}
+
+unsafe fn table_at(entry: u64) -> *mut PageTable {
+    (entry & ADDR_MASK) as *mut PageTable
+}
+
+/// Replace a 2 MiB mapping with an equivalent table of 4 KiB pages;
+/// false if the split pool is exhausted
+unsafe fn split_large_page(pde: *mut u64) -> bool {
+    if SPLIT_USED == SPLIT_POOL_SIZE {
+        return false;
+    }
+    let table = core::ptr::addr_of_mut!(SPLIT_POOL[SPLIT_USED]);
+    SPLIT_USED += 1;
+
+    let entry = *pde;
+    let base  = entry & LARGE_ADDR_MASK;
+    // keep permissions and caching; move PAT from bit 12 to bit 7
+    let mut flags = entry & !(LARGE_ADDR_MASK | PF_PAGE_SIZE | PF_LARGE_PAT);
+    if entry & PF_LARGE_PAT != 0 {
+        flags |= PF_PAGE_SIZE;
+    }
+    for (i, pte) in (*table).entries.iter_mut().enumerate() {
+        *pte = (base + (i * PAGE_SIZE) as u64) | flags;
+    }
+
+    // the PDE keeps the permissive bits; the PTEs decide the rest
+    *pde = phys_addr_of(table as usize) | PF_PRESENT | PF_RW | (entry & PF_USER);
+    write_cr3(read_cr3());
+    true
+}
+
+/// Find the 4 KiB PTE that maps `virt` in the active tables, splitting a
+/// 2 MiB page on the way if necessary. `None` if `virt` is unmapped,
+/// covered by a 1 GiB page, or the split pool ran out.
+///
+/// SAFETY: paging must be active and the tables identity-mapped.
+pub unsafe fn get_pte(virt: usize) -> Option<*mut u64> {
+    let mut table = read_cr3() as *mut PageTable;
+    for level in (2..=4).rev() {
+        let entry = &mut (*table).entries[table_index(virt, level)] as *mut u64;
+        if *entry & PF_PRESENT == 0 {
+            return None;
+        }
+        if *entry & PF_PAGE_SIZE != 0 {
+            // 1 GiB pages (level 3) are not split
+            if level != 2 || !split_large_page(entry) {
+                return None;
+            }
+        }
+        table = table_at(*entry);
+    }
+    Some(&mut (*table).entries[table_index(virt, 1)] as *mut u64)
+}
+
+/// Physical address `virt` maps to in the active tables, or `None` if it
+/// is unmapped. Unlike `get_pte` this never splits a large page.
+///
+/// SAFETY: paging must be active and the tables identity-mapped.
+pub unsafe fn translate(virt: usize) -> Option<u64> {
+    translate_in(read_cr3() as *const PageTable, virt)
+}
+
+/// `translate` through the tables rooted at `pml4`
+unsafe fn translate_in(pml4: *const PageTable, virt: usize) -> Option<u64> {
+    let mut table = pml4;
+    for level in (1..=4).rev() {
+        let entry = (*table).entries[table_index(virt, level)];
+        if entry & PF_PRESENT == 0 {
+            return None;
+        }
+        if level == 1 || (level <= 3 && entry & PF_PAGE_SIZE != 0) {
+            let span = (PAGE_SIZE << (9 * (level - 1))) as u64;
+            return Some((entry & ADDR_MASK & !(span - 1)) | (virt as u64 & (span - 1)));
+        }
+        table = table_at(entry);
+    }
+    None
+}
+
+/// One leaf mapping (or a run of them) found by `walk_mappings`
+#[derive(Clone, Copy)]
+struct Mapping {
+    virt:  usize,
+    phys:  u64,
+    len:   usize,
+    /// Page size of the leaf entries: 4 KiB, 2 MiB or 1 GiB
+    page:  usize,
+    /// Effective PF_RW / PF_USER / PF_NX across all levels
+    flags: u64,
+}
+
+impl Mapping {
+    /// `next` directly follows this run in both address spaces, with the
+    /// same page size and permissions
+    fn continued_by(&self, next: &Mapping) -> bool {
+        self.virt.wrapping_add(self.len) == next.virt
+            && self.phys + self.len as u64 == next.phys
+            && self.page == next.page
+            && self.flags == next.flags
+    }
+}
+
+/// Sign-extend bit 47 so `virt` is a canonical address
+const fn canonical(virt: usize) -> usize {
+    (((virt << 16) as isize) >> 16) as usize
+}
+
+/// Call `f` for every present leaf entry below `table` (at `level`,
+/// covering virtual addresses from `base`), in address order. `parent`
+/// holds the permissions granted by the levels above: RW and USER must be
+/// set at every level, NX at any one.
+unsafe fn walk_mappings(
+    table:  *const PageTable,
+    level:  u32,
+    base:   usize,
+    parent: u64,
+    f:      &mut impl FnMut(Mapping),
+) {
+    let span = PAGE_SIZE << (9 * (level - 1));
+    for (i, &entry) in (*table).entries.iter().enumerate() {
+        if entry & PF_PRESENT == 0 {
+            continue;
+        }
+        let virt  = canonical(base + i * span);
+        let flags = (parent & entry & (PF_RW | PF_USER)) | ((parent | entry) & PF_NX);
+        if level == 1 || (level <= 3 && entry & PF_PAGE_SIZE != 0) {
+            let phys = entry & ADDR_MASK & !(span as u64 - 1);
+            f(Mapping { virt, phys, len: span, page: span, flags });
+        } else {
+            walk_mappings(table_at(entry), level - 1, virt, flags, f);
+        }
+    }
+}
+
+/// Print every mapping in the active tables, one line per run of pages
+/// that are contiguous virtually and physically with the same page size
+/// and effective permissions:
+///
+/// ```text
+/// 0x0000000000000000-0x0000000100000000 -> 0x0000000000 2M P W - X
+/// ```
+///
+/// SAFETY: paging must be active and the tables identity-mapped.
+pub unsafe fn dump_mappings(w: &mut dyn fmt::Write) -> fmt::Result {
+    fn line(w: &mut dyn fmt::Write, m: &Mapping) -> fmt::Result {
+        let size = match m.page {
+            PAGE_SIZE       => "4K",
+            LARGE_PAGE_SIZE => "2M",
+            _               => "1G",
+        };
+        writeln!(
+            w,
+            "{:#018x}-{:#018x} -> {:#012x} {} P {} {} {}",
+            m.virt,
+            m.virt.wrapping_add(m.len),
+            m.phys,
+            size,
+            if m.flags & PF_RW   != 0 { "W" } else { "-" },
+            if m.flags & PF_USER != 0 { "U" } else { "-" },
+            if m.flags & PF_NX   != 0 { "NX" } else { "X" },
+        )
+    }
+
+    let mut run: Option<Mapping> = None;
+    let mut result = Ok(());
+    walk_mappings(read_cr3() as *const PageTable, 4, 0, PF_RW | PF_USER, &mut |m| {
+        match run {
+            Some(ref mut r) if r.continued_by(&m) => r.len += m.len,
+            Some(ref r) => {
+                result = result.and_then(|_| line(w, r));
+                run = Some(m);
+            }
+            None => run = Some(m),
+        }
+    });
+    result?;
+    match run {
+        Some(r) => line(w, &r),
+        None    => writeln!(w, "no mappings"),
+    }
+}
+
+/// CR0.WP: make read-only pages binding for ring 0 as well
+const CR0_WP:    u64 = 1 << 16;
+
+/// Page-aligned section boundaries of the loaded kernel, each `[start, end)`
+#[derive(Clone, Copy, Debug)]
+pub struct KernelImage {
+    /// .text: mapped read-only + executable
+    pub text:   (usize, usize),
+    /// .rodata: read-only + no-execute
+    pub rodata: (usize, usize),
+    /// .data and .bss: writable + no-execute
+    pub data:   (usize, usize),
+}
+
+/// Why `enforce_wx` could not (fully) apply W^X
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WxError {
+    /// CPUID reports no NX support; nothing was changed
+    NxUnsupported,
+    /// EFER.NXE is clear, so setting PF_NX would fault; nothing was changed
+    NxDisabled,
+    /// A section boundary is not page aligned; nothing was changed
+    Unaligned(usize),
+    /// No 4 KiB PTE for this page (unmapped, 1 GiB page or split pool
+    /// exhausted); pages before it were already updated
+    Unmapped(usize),
+}
+
+/// Set EFER.NXE through `msrs` if `features` include NX; false (and
+/// nothing written) if they do not.
+///
+/// SAFETY: `msrs` must reach a long-mode CPU's EFER.
+pub unsafe fn enable_nx_on(msrs: &impl MsrBackend, features: CpuFeatures) -> bool {
+    if !features.contains(CpuFeatures::NX) {
+        return false;
+    }
+    msr::set_bits(msrs, msr::IA32_EFER, msr::EFER_NXE);
+    true
+}
+
+/// Set EFER.NXE on the executing CPU, so page tables may use PF_NX
+/// (which faults while it is clear). EFER is per CPU: each AP needs its
+/// own call. False if CPUID reports no NX; `enforce_wx` then declines
+/// with `WxError::NxUnsupported`.
+pub fn enable_nx() -> bool {
+    // SAFETY: EFER exists on every long-mode CPU; NXE is only set when
+    // CPUID says it is supported
+    unsafe { enable_nx_on(&msr::HardwareMsrs, crate::cpu::features()) }
+}
+
+/// Rewrite the PTE `pte_of` finds for every page in `[start, end)`:
+/// clear `clear`, set `set`. The caller flushes the TLB.
+unsafe fn update_range(
+    start: usize,
+    end: usize,
+    clear: u64,
+    set: u64,
+    pte_of: &mut impl FnMut(usize) -> Option<*mut u64>,
+) -> Result<(), WxError> {
+    for page in (start..end).step_by(PAGE_SIZE) {
+        let pte = pte_of(page).ok_or(WxError::Unmapped(page))?;
+        *pte = (*pte & !clear) | set;
+    }
+    Ok(())
+}
+
+/// Can PF_NX be set, given the CPU's `features` and its `efer`?
+fn check_nx(features: CpuFeatures, efer: u64) -> Result<(), WxError> {
+    if !features.contains(CpuFeatures::NX) {
+        return Err(WxError::NxUnsupported);
+    }
+    if efer & msr::EFER_NXE == 0 {
+        return Err(WxError::NxDisabled);
+    }
+    Ok(())
+}
+
+/// The page-table half of `enforce_wx`: check the boundaries, then
+/// rewrite each section's PTEs as found by `pte_of`
+unsafe fn apply_wx(
+    info: &KernelImage,
+    mut pte_of: impl FnMut(usize) -> Option<*mut u64>,
+) -> Result<(), WxError> {
+    let bounds = [info.text, info.rodata, info.data];
+    if let Some(addr) = bounds.iter().flat_map(|r| [r.0, r.1]).find(|a| a % PAGE_SIZE != 0) {
+        return Err(WxError::Unaligned(addr));
+    }
+
+    update_range(info.text.0,   info.text.1,   PF_RW | PF_NX, 0,     &mut pte_of)?;
+    update_range(info.rodata.0, info.rodata.1, PF_RW,         PF_NX, &mut pte_of)?;
+    update_range(info.data.0,   info.data.1,   0,             PF_NX, &mut pte_of)
+}
+
+/// Enforce W^X over the kernel image: code pages lose PF_RW, everything
+/// else gains PF_NX, and CR0.WP makes the read-only bits apply to the
+/// kernel itself. The linker script must page-align every boundary.
+///
+/// SAFETY: must run after paging is active (always true in long mode)
+/// on identity-mapped tables that map `info`, and after the runtime has
+/// finished writing .text/.rodata (i.e. once `rust_start` is done).
+pub unsafe fn enforce_wx(info: &KernelImage) -> Result<(), WxError> {
+    // SAFETY: EFER exists on every long-mode CPU
+    check_nx(crate::cpu::features(), msr::read(msr::IA32_EFER))?;
+    let result = apply_wx(info, |page| get_pte(page));
+    // pages before a failure were rewritten too
+    write_cr3(read_cr3());
+    result?;
+
+    let mut cr0: u64;
+    asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    asm!("mov cr0, {}", in(reg) cr0 | CR0_WP, options(nostack, preserves_flags));
+    Ok(())
+}
+
+/// Replace the bootloader's tables with an identity map of the low 4 GiB
+/// plus the higher-half alias of the low 2 GiB, and load them.
+///
+/// SAFETY: the kernel image, stack, MBI and modules must lie in the low
+/// 4 GiB (and the image in the low 2 GiB to be reachable through the
+/// alias); .bss must be zeroed; must run before anything (like the stack
+/// guard) edits page tables, since those edits would be discarded.
+pub unsafe fn map_higher_half() {
+    let pml4 = (*core::ptr::addr_of_mut!(HH_TABLES)).build();
+    write_cr3(pml4 as usize);
+}
+
+/// Continue at the higher-half alias of `entry`, passing `arg` in RDI.
+/// The current (identity-mapped) stack is kept.
+///
+/// SAFETY: `map_higher_half` must be active and `entry` must lie in the
+/// low 2 GiB.
+pub unsafe fn enter_higher_half(entry: extern "C" fn(usize) -> !, arg: usize) -> ! {
+    let target = phys_to_virt(entry as usize);
+    // realign the stack so `entry` sees the ABI's post-`call` alignment
+    asm!(
+        "and rsp, -16",
+        "call {}",
+        in(reg) target,
+        in("rdi") arg,
+        options(noreturn),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msr::MockMsrs;
+
+    /// EFER with LME, LMA and SCE set, as in long mode
+    const EFER_LONG_MODE: u64 = 1 << 10 | 1 << 8 | 1;
+
+    #[test_case]
+    fn enable_nx_sets_nxe_and_keeps_other_bits() {
+        let msrs = MockMsrs::new(&[(msr::IA32_EFER, EFER_LONG_MODE)]);
+        assert!(unsafe { enable_nx_on(&msrs, CpuFeatures::NX) });
+        assert_eq!(msrs.value(msr::IA32_EFER), EFER_LONG_MODE | msr::EFER_NXE);
+    }
+
+    #[test_case]
+    fn enable_nx_without_nx_writes_nothing() {
+        let msrs = MockMsrs::new(&[(msr::IA32_EFER, EFER_LONG_MODE)]);
+        assert!(!unsafe { enable_nx_on(&msrs, CpuFeatures::LONG_MODE) });
+        assert_eq!(msrs.value(msr::IA32_EFER), EFER_LONG_MODE);
+        assert_eq!(msrs.writes(), 0);
+    }
+
+    #[test_case]
+    fn enable_nx_twice_writes_once() {
+        let msrs = MockMsrs::new(&[(msr::IA32_EFER, EFER_LONG_MODE)]);
+        unsafe {
+            assert!(enable_nx_on(&msrs, CpuFeatures::NX));
+            assert!(enable_nx_on(&msrs, CpuFeatures::NX));
+        }
+        assert_eq!(msrs.writes(), 1);
+    }
+
+    #[test_case]
+    fn wx_needs_nx_support_and_efer_nxe() {
+        assert_eq!(check_nx(CpuFeatures::LONG_MODE, msr::EFER_NXE), Err(WxError::NxUnsupported));
+        assert_eq!(check_nx(CpuFeatures::NX, EFER_LONG_MODE), Err(WxError::NxDisabled));
+        assert_eq!(check_nx(CpuFeatures::NX, EFER_LONG_MODE | msr::EFER_NXE), Ok(()));
+    }
+
+    /// PTEs for the pages at 0, 0x1000, … 0x7000, all present and writable
+    fn ptes() -> [u64; 8] {
+        core::array::from_fn(|i| (i * PAGE_SIZE) as u64 | PF_PRESENT | PF_RW)
+    }
+
+    fn pte_in(ptes: &mut [u64; 8]) -> impl FnMut(usize) -> Option<*mut u64> + '_ {
+        move |page| ptes.get_mut(page / PAGE_SIZE).map(|pte| pte as *mut u64)
+    }
+
+    const IMAGE: KernelImage = KernelImage {
+        text:   (0x1000, 0x3000),
+        rodata: (0x3000, 0x4000),
+        data:   (0x4000, 0x6000),
+    };
+
+    #[test_case]
+    fn wx_makes_code_read_only_and_the_rest_no_execute() {
+        let mut table = ptes();
+        assert_eq!(unsafe { apply_wx(&IMAGE, pte_in(&mut table)) }, Ok(()));
+        let (rx, r, rw) = (PF_PRESENT, PF_PRESENT | PF_NX, PF_PRESENT | PF_RW | PF_NX);
+        // pages outside the image are left alone
+        let outside = PF_PRESENT | PF_RW;
+        let flags = table.map(|pte| pte & !ADDR_MASK);
+        assert_eq!(flags, [outside, rx, rx, r, rw, rw, outside, outside]);
+        for (i, pte) in table.iter().enumerate() {
+            assert_eq!(pte & ADDR_MASK, (i * PAGE_SIZE) as u64);
+        }
+    }
+
+    #[test_case]
+    fn wx_rejects_unaligned_boundaries_before_writing() {
+        let mut table = ptes();
+        let image = KernelImage { rodata: (0x3000, 0x3800), ..IMAGE };
+        let result = unsafe { apply_wx(&image, pte_in(&mut table)) };
+        assert_eq!(result, Err(WxError::Unaligned(0x3800)));
+        assert_eq!(table, ptes());
+    }
+
+    #[test_case]
+    fn wx_stops_at_the_first_unmapped_page() {
+        let mut table = ptes();
+        let image = KernelImage { data: (0x4000, 0xa000), ..IMAGE };
+        let result = unsafe { apply_wx(&image, pte_in(&mut table)) };
+        assert_eq!(result, Err(WxError::Unmapped(0x8000)));
+        // everything before it was already updated
+        assert_eq!(table[7] & PF_NX, PF_NX);
+    }
+
+    struct Tables(core::cell::UnsafeCell<HigherHalfTables>);
+
+    // SAFETY: tests run one at a time on the boot CPU
+    unsafe impl Sync for Tables {}
+
+    static TABLES: Tables = Tables(core::cell::UnsafeCell::new(HigherHalfTables::empty()));
+
+    #[test_case]
+    fn higher_half_tables_identity_map_the_low_4_gib() {
+        // SAFETY: nothing else touches `TABLES` while a test runs
+        let tables = unsafe { &mut *TABLES.0.get() };
+        let pml4 = tables.build() as *const PageTable;
+        assert_eq!(pml4 as u64, phys_addr_of(&tables.pml4 as *const PageTable as usize));
+        for addr in [0, 0x10_0000, 0x1234_5678, 0xfee0_0000, 0xffff_ffff] {
+            assert_eq!(unsafe { translate_in(pml4, addr) }, Some(addr as u64), "{:#x}", addr);
+        }
+        assert_eq!(unsafe { translate_in(pml4, 0x1_0000_0000) }, None);
+        assert_eq!(tables.pd[3].entries[511], 0xffe0_0000 | PF_PRESENT | PF_RW | PF_PAGE_SIZE);
+    }
+
+    #[test_case]
+    fn higher_half_alias_covers_the_low_2_gib() {
+        // SAFETY: nothing else touches `TABLES` while a test runs
+        let tables = unsafe { &mut *TABLES.0.get() };
+        let pml4 = tables.build() as *const PageTable;
+        for phys in [0, 0x10_0000, 0x7fff_ffff] {
+            let virt = phys_to_virt(phys);
+            assert_eq!(virt_to_phys(virt), phys);
+            assert_eq!(unsafe { translate_in(pml4, virt) }, Some(phys as u64), "{:#x}", virt);
+        }
+        // just below the alias, and the rest of the upper half
+        for virt in [KERNEL_OFFSET - 1, 0xffff_8000_0000_0000] {
+            assert_eq!(unsafe { translate_in(pml4, virt) }, None, "{:#x}", virt);
+        }
+        // the alias and the identity map share the PDs
+        let high = &tables.pdpt_high.entries[table_index(KERNEL_OFFSET, 3)..];
+        assert_eq!(high, &tables.pdpt_low.entries[..2]);
+    }
+}
@@ -0,0 +1,129 @@
+// rooster-os/arch/x86/runtime/rs_runtime/phase.rs
+//! Ordering check for the runtime's boot phases.
+//!   • `rust_start` / `init_runtime` call `advance` as each phase
+//!     completes; a phase reached from anything but its predecessor
+//!     panics naming both, so a reordering refactor fails on first boot
+//!   • The tracker lives in .bss like `ENTERED`: zeroing .bss resets it,
+//!     so that step carries the phase across with `restore`
+
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The phases in the order they must complete
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Nothing done yet (also what a zeroed tracker reads)
+    Reset,
+    /// Magic, MBI pointer, image header and CPU checked
+    Validated,
+    /// .data copied to its run address
+    DataCopied,
+    /// .bss zeroed
+    BssZeroed,
+    /// Heap (unless `no-heap`) and frame allocator set up
+    HeapReady,
+    /// About to call `kernel_main`
+    KernelEntered,
+}
+
+impl Phase {
+    const ALL: [Phase; 6] = [
+        Phase::Reset,
+        Phase::Validated,
+        Phase::DataCopied,
+        Phase::BssZeroed,
+        Phase::HeapReady,
+        Phase::KernelEntered,
+    ];
+
+    /// The phase that must complete right before this one
+    pub const fn predecessor(self) -> Phase {
+        match self {
+            Phase::Reset | Phase::Validated => Phase::Reset,
+            Phase::DataCopied    => Phase::Validated,
+            Phase::BssZeroed     => Phase::DataCopied,
+            Phase::HeapReady     => Phase::BssZeroed,
+            Phase::KernelEntered => Phase::HeapReady,
+        }
+    }
+}
+
+static PHASE: AtomicU8 = AtomicU8::new(Phase::Reset as u8);
+
+/// The last phase completed
+pub fn current() -> Phase {
+    Phase::ALL[PHASE.load(Ordering::Acquire) as usize]
+}
+
+/// A phase reached from anything but its predecessor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderViolation {
+    pub phase: Phase,
+    /// The last phase completed at the time
+    pub after: Phase,
+}
+
+impl fmt::Display for OrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "init order violated: {:?} reached after {:?}, expected after {:?}",
+            self.phase, self.after, self.phase.predecessor()
+        )
+    }
+}
+
+/// May `phase` complete now that `now` has?
+fn check(now: Phase, phase: Phase) -> Result<(), OrderViolation> {
+    if now != phase.predecessor() {
+        return Err(OrderViolation { phase, after: now });
+    }
+    Ok(())
+}
+
+/// Record that `phase` completed. Panics if its predecessor had not.
+pub fn advance(phase: Phase) {
+    if let Err(err) = check(current(), phase) {
+        panic!("{}", err);
+    }
+    PHASE.store(phase as u8, Ordering::Release);
+}
+
+/// Put `phase` back after zeroing .bss cleared the tracker
+pub(crate) fn restore(phase: Phase) {
+    PHASE.store(phase as u8, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use crate::runtime_core::FmtBuf;
+
+    #[test_case]
+    fn each_phase_follows_its_predecessor() {
+        for pair in Phase::ALL.windows(2) {
+            assert_eq!(check(pair[0], pair[1]), Ok(()));
+        }
+    }
+
+    #[test_case]
+    fn skipping_a_phase_is_a_violation() {
+        // zeroing .bss before .data was copied
+        let err = check(Phase::Validated, Phase::BssZeroed).unwrap_err();
+        assert_eq!(err, OrderViolation { phase: Phase::BssZeroed, after: Phase::Validated });
+        let mut text = FmtBuf::<96>::new();
+        write!(text, "{}", err).unwrap();
+        assert_eq!(
+            text.as_str(),
+            "init order violated: BssZeroed reached after Validated, expected after DataCopied"
+        );
+    }
+
+    #[test_case]
+    fn repeating_or_going_back_is_a_violation() {
+        assert!(check(Phase::HeapReady, Phase::HeapReady).is_err());
+        assert!(check(Phase::Validated, Phase::Validated).is_err());
+        assert!(check(Phase::KernelEntered, Phase::DataCopied).is_err());
+    }
+}
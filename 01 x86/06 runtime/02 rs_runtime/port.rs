@@ -0,0 +1,30 @@
+// rooster-os/arch/x86/runtime/rs_runtime/port.rs
+//! x86 port-mapped I/O primitives (`in`/`out`).
+
+use core::arch::asm;
+
+/// Write a byte to an I/O port.
+///
+/// SAFETY: writing to arbitrary ports can reconfigure hardware.
+#[inline]
+pub unsafe fn outb(port: u16, val: u8) {
+    asm!("out dx, al", in("dx") port, in("al") val, options(nomem, nostack, preserves_flags));
+}
+
+/// Read a byte from an I/O port.
+///
+/// SAFETY: some port reads have side effects (e.g. acknowledging IRQs).
+#[inline]
+pub unsafe fn inb(port: u16) -> u8 {
+    let val: u8;
+    asm!("in al, dx", out("al") val, in("dx") port, options(nomem, nostack, preserves_flags));
+    val
+}
+
+/// Write a dword to an I/O port.
+///
+/// SAFETY: same as `outb`.
+#[inline]
+pub unsafe fn outl(port: u16, val: u32) {
+    asm!("out dx, eax", in("dx") port, in("eax") val, options(nomem, nostack, preserves_flags));
+}
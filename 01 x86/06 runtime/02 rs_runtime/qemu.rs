@@ -0,0 +1,30 @@
+// rooster-os/arch/x86/runtime/rs_runtime/qemu.rs
+//! QEMU `isa-debug-exit` support for automated test runs.
+//!
+//! Start QEMU with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+//! A write of `code` makes QEMU exit with status `(code << 1) | 1`, so
+//! `Success` becomes 33 and `Failed` 35 — never 0, which QEMU uses itself.
+
+use crate::port::outl;
+
+/// I/O port the debug-exit device listens on
+pub const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Exit codes understood by the test harness
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed  = 0x11,
+}
+
+/// Ask QEMU to exit with `code`. Returns only if no debug-exit device
+/// is present (e.g. on real hardware).
+pub fn qemu_exit(code: u32) {
+    unsafe { outl(DEBUG_EXIT_PORT, code) };
+}
+
+/// Convenience wrapper taking a typed exit code
+pub fn exit(code: QemuExitCode) {
+    qemu_exit(code as u32);
+}
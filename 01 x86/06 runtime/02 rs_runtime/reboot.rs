@@ -0,0 +1,46 @@
+// rooster-os/arch/x86/runtime/rs_runtime/reboot.rs
+//! Machine reset, used by the panic handler under `panic_reboot`.
+//!   • Tries the chipset reset control register (port 0xCF9) first
+//!   • Falls back to a triple fault: load an empty IDT and trap
+
+use core::arch::asm;
+
+use crate::gdt::DescriptorTablePointer;
+use crate::port::outb;
+
+/// Chipset reset control register (PIIX/ICH and QEMU's q35/i440fx)
+const RESET_CONTROL_PORT: u16 = 0xCF9;
+const RESET_SYS_RST:      u8  = 1 << 1;
+const RESET_CPU_RST:      u8  = 1 << 2;
+
+/// POST diagnostic port; each write takes roughly a microsecond
+const IO_DELAY_PORT: u16 = 0x80;
+
+/// Busy-wait about `us` microseconds using I/O delays. No timer is
+/// needed, which is what the panic path wants, but the duration is
+/// only approximate.
+pub fn io_delay_us(us: usize) {
+    for _ in 0..us {
+        unsafe { outb(IO_DELAY_PORT, 0) };
+    }
+}
+
+/// Reset the machine. Asks the chipset for a hard reset first; if that
+/// port is absent, forces a triple fault, which every x86 CPU turns
+/// into a reset.
+pub fn triple_fault_reboot() -> ! {
+    unsafe {
+        // SYS_RST selects a hard reset; the 0→1 edge of CPU_RST fires it
+        outb(RESET_CONTROL_PORT, RESET_SYS_RST);
+        outb(RESET_CONTROL_PORT, RESET_SYS_RST | RESET_CPU_RST);
+        io_delay_us(1000);
+
+        // With a zero-limit IDT no exception can be delivered: int3
+        // escalates to #GP, then #DF, then a triple fault
+        let idtr = DescriptorTablePointer { limit: 0, base: 0 };
+        asm!("lidt [{}]", "int3", in(reg) &idtr, options(readonly, nostack));
+    }
+    loop {
+        unsafe { asm!("hlt"); }
+    }
+}
@@ -0,0 +1,44 @@
+// rooster-os/arch/x86/runtime/rs_runtime/reloc.rs
+//! Load-bias correction for an image loaded away from its link address
+//! (`reloc` feature).
+//!   • Anchor: `_start` (entry.S). Its real address comes from a
+//!     RIP-relative `lea`; its link address from taking the symbol's
+//!     address the way the rest of the runtime takes linker symbols.
+//!     The difference is the load bias.
+//!   • `relocate` adds the bias to a linker-symbol address
+//!   • Works before .data is copied and .bss zeroed: nothing is cached
+//!
+//! Assumptions:
+//!   • The bootloader moved the whole image as one block, so one bias
+//!     fits every section
+//!   • Code reaches itself and its statics RIP-relative (as entry.S
+//!     does). Only addresses *taken of* linker symbols come out as link
+//!     addresses, and those are what `rust_start` corrects.
+//!   • Nothing else is patched: absolute pointers stored in data
+//!     (vtables, function-pointer tables) still need a real relocation
+//!     pass or the link address
+//!
+//! If the build already yields runtime addresses for linker symbols, the
+//! bias is 0 and `relocate` is a no-op.
+
+extern "C" {
+    fn _start();
+}
+
+/// Actual load address minus link address of the image, in bytes
+#[inline(always)]
+pub fn load_bias() -> isize {
+    let actual: usize;
+    // SAFETY: only computes an address
+    unsafe {
+        core::arch::asm!("lea {}, [rip + _start]", out(reg) actual, options(nomem, nostack, pure));
+    }
+    let linked = _start as usize;
+    actual.wrapping_sub(linked) as isize
+}
+
+/// Runtime address of something at link address `addr`
+#[inline(always)]
+pub fn relocate(addr: usize) -> usize {
+    addr.wrapping_add_signed(load_bias())
+}
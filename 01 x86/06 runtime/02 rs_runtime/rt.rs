@@ -1,144 +1,884 @@
-// rooster-os/arch/x86/runtime/rs_runtime/rt.rs
-#![no_std]
-#![no_main]
-#![feature(alloc_error_handler)]
-#![feature(core_intrinsics)]
-#![feature(lang_items)]
-#![feature(ptr_internals)]
-
-//! Rust “runtime” for RoosterOS on x86_64.
-//!   • Validates Multiboot handoff
-//!   • Copies .data from flash to RAM
-//!   • Zeroes .bss
-//!   • Initializes a bump‐allocator as GlobalAlloc
-//!   • Provides panic and alloc‐error handlers
-//!   • Transfers control to `kernel_main(magic, mbi) -> !`
-
-use core::panic::PanicInfo;
-use core::alloc::{GlobalAlloc, Layout};
-use core::intrinsics::copy_nonoverlapping;
-use core::ptr::write_bytes;
-
-// Multiboot magic constant
-const MULTIBOOT_MAGIC: u32 = 0x2BADB002;
-
-// Symbols provided by the linker script
-extern "C" {
-    // Kernel entry point (written in Rust or C)
-    fn kernel_main(magic: u32, mbi_addr: usize) -> !;
-
-    // Data segment: load‐address, start, end
-    static __data_load: u8;
-    static mut __data_start: u8;
-    static mut __data_end:   u8;
-
-    // BSS segment: start, end
-    static mut __bss_start: u8;
-    static mut __bss_end:   u8;
-
-    // Heap region: start, end
-    static mut __heap_start: u8;
-    static mut __heap_end:   u8;
-}
-
-/// A simple bump‐pointer allocator
-struct BumpAllocator {
-    next: usize,
-    end:  usize,
-}
-
-impl BumpAllocator {
-    const fn new() -> Self {
-        BumpAllocator { next: 0, end: 0 }
-    }
-
-    unsafe fn init(&mut self, heap_start: usize, heap_end: usize) {
-        self.next = heap_start;
-        self.end  = heap_end;
-    }
-
-    #[inline]
-    fn align_up(addr: usize, align: usize) -> usize {
-        (addr + align - 1) & !(align - 1)
-    }
-}
-
-unsafe impl GlobalAlloc for BumpAllocator {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut ptr = Self::align_up(self.next, layout.align());
-        let new_next = ptr.checked_add(layout.size()).unwrap_or(self.end);
-        if new_next > self.end {
-            return core::ptr::null_mut();
-        }
-        // update pointer
-        let alloc_ptr = ptr as *mut u8;
-        // SAFETY: &mut through raw pointer
-        let me = &mut *(self as *const _ as *mut BumpAllocator);
-        me.next = new_next;
-        alloc_ptr
-    }
-
-    unsafe fn dealloc(&self, _: *mut u8, _: Layout) {
-        // no-op for bump allocator
-    }
-}
-
-#[global_allocator]
-static ALLOCATOR: BumpAllocator = BumpAllocator::new();
-
-/// Entry point called from assembly stub (`entry.S`):
-///   EDI = multiboot_magic, ESI = mbi_ptr
-#[no_mangle]
-pub extern "C" fn rust_start(magic: u32, mbi_addr: usize) -> ! {
-    // 1) Validate Multiboot signature
-    if magic != MULTIBOOT_MAGIC {
-        panic!("Bad multiboot magic: {:#x}", magic);
-    }
-
-    unsafe {
-        // 2) Copy .data from flash (load‐address) to RAM
-        let data_size = (&__data_end as *const _ as usize)
-                      - (&__data_start as *const _ as usize);
-        copy_nonoverlapping(
-            &__data_load as *const u8,
-            &mut __data_start as *mut u8,
-            data_size,
-        );
-
-        // 3) Zero .bss
-        let bss_size = (&__bss_end as *const _ as usize)
-                     - (&__bss_start as *const _ as usize);
-        write_bytes(&mut __bss_start as *mut u8, 0, bss_size);
-
-        // 4) Initialize heap allocator
-        ALLOCATOR.init(
-            &__heap_start as *const _ as usize,
-            &__heap_end   as *const _ as usize,
-        );
-    }
-
-    // 5) Call the kernel’s main function (does not return)
-    unsafe { kernel_main(magic, mbi_addr) }
-}
-
-/// Called on allocation failure (out of memory)
-#[alloc_error_handler]
-fn alloc_error(layout: Layout) -> ! {
-    panic!("allocation error: {:?}", layout);
-}
-
-/// Panic handler prints info (if you’ve hooked a console) then halts
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    // You can integrate with your VGA/serial console here, e.g.:
-    // console::write_fmt(format_args!("PANIC: {}\n", info)).ok();
-
-    // Fallback: just spin with HLT
-    loop {
-        unsafe { core::arch::asm!("hlt"); }
-    }
-}
-
-// Minimal lang-items to satisfy `no_std` linking
-#[lang = "eh_personality"] extern fn eh_personality() {}
-#[lang = "oom"] fn oom(_: Layout) -> ! { loop { unsafe { core::arch::asm!("hlt"); } } }
+// rooster-os/arch/x86/runtime/rs_runtime/rt.rs
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+#![feature(core_intrinsics)]
+#![feature(lang_items)]
+#![feature(ptr_internals)]
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(all(test, feature = "testing"), test_runner(crate::testing::test_runner))]
+#![cfg_attr(all(test, feature = "testing"), reexport_test_harness_main = "test_main")]
+
+//! Rust “runtime” for RoosterOS on x86_64.
+//!   • Validates Multiboot (v1 or v2) handoff
+//!   • Copies .data from flash to RAM
+//!   • Zeroes .bss
+//!   • Loads a flat 64-bit GDT with a TSS and a default exception IDT
+//!   • Initializes a bump‐allocator as GlobalAlloc (not under `no-heap`,
+//!     for kernels that never allocate: any `alloc` use then fails to link)
+//!   • Parses the Multiboot memory map and command line
+//!   • Seeds a 4 KiB physical frame allocator (`frame`) from that map
+//!   • Routes `log` macros to COM1
+//!   • Optionally (`reloc`) rebases the linker symbols it uses when
+//!     loaded away from the link address (see `reloc.rs`)
+//!   • Optionally (`soft-float`) skips SSE setup for soft-float kernels
+//!   • Optionally (`stack-probe`) paints the boot stack once .bss is
+//!     zeroed so `stack::stack_high_watermark` can report its peak depth
+//!     (the stack has its own `.stack` section, so that zeroing spares it)
+//!   • Optionally (`higher-half`) enters the kernel through an alias at
+//!     `0xFFFF_FFFF_8000_0000` (see `paging::KERNEL_OFFSET`)
+//!   • Provides panic and alloc‐error handlers (panics print to the
+//!     installed console, or COM1 before there is one)
+//!   • Transfers control to `kernel_main(&BootInfo) -> !`
+//!
+//! Allocators, OOM policy and panic formatting live in the shared
+//! `runtime_core` (also used by the AArch64 runtime).
+
+use core::panic::PanicInfo;
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+#[cfg(feature = "track-allocs")]
+pub mod alloc_track;
+#[cfg(feature = "backtrace")]
+pub mod backtrace;
+#[cfg(feature = "banner")]
+pub mod banner;
+pub mod cpu;
+pub mod delay;
+pub mod font;
+pub mod frame;
+pub mod framebuffer;
+pub mod boot_info;
+pub mod gdt;
+pub mod idt;
+pub mod interrupts;
+pub mod logging;
+pub mod msr;
+pub mod multiboot;
+pub mod multiboot2;
+pub mod paging;
+pub mod phase;
+pub mod port;
+pub mod qemu;
+pub mod reboot;
+#[cfg(feature = "reloc")]
+pub mod reloc;
+pub mod serial;
+#[cfg(feature = "smp")]
+pub mod smp;
+pub mod stack;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod vga;
+#[cfg(feature = "boot-watchdog")]
+pub mod watchdog;
+
+#[path = "../../../00 common/06 runtime/runtime_core/mod.rs"]
+pub mod runtime_core;
+
+pub use runtime_core::heap::{
+    set_oom_handler, AllocError, BumpAllocator, EmergencyHeap, HeapStats, LinkedListAllocator,
+    OomAction, PerCpuHeap, SlabAllocator, EMERGENCY_HEAP, EMERGENCY_HEAP_SIZE, MAX_HEAP_REGIONS,
+    MIN_ALIGN,
+};
+#[cfg(not(feature = "no-heap"))]
+pub use runtime_core::heap::{alloc_aligned, free_aligned, ALLOCATOR};
+#[cfg(feature = "percpu-heap")]
+pub use runtime_core::heap::init_local_arena;
+pub use runtime_core::buddy::BuddyAllocator;
+pub use runtime_core::console::{set_console, Console, TeeConsole};
+pub use runtime_core::hexdump::hexdump;
+pub use runtime_core::panic::set_panic_hook;
+pub use runtime_core::sync::{IrqSpinlockGuard, Once, Spinlock, SpinlockGuard};
+pub use interrupts::without_interrupts;
+use runtime_core::mem::{copy_data, zero_region};
+#[cfg(not(feature = "no-heap"))]
+use runtime_core::mem::ranges_overlap;
+
+use boot_info::BootInfo;
+use phase::Phase;
+use multiboot::MemoryMap;
+
+// The runner, its panic hook and the QEMU exit all live in `testing`;
+// without it there is no harness for the `#[test_case]`s
+#[cfg(all(test, not(feature = "testing")))]
+compile_error!("run the in-kernel tests with `cargo test --features testing`");
+
+// Multiboot magic constants (v1 and v2)
+const MULTIBOOT_MAGIC:  u32 = 0x2BADB002;
+const MULTIBOOT2_MAGIC: u32 = 0x36d76289;
+
+// Symbols provided by the linker script
+extern "C" {
+    // Kernel entry point. `BootInfo` is a Rust type, so a C kernel must
+    // mirror its layout or treat the pointer as opaque.
+    #[allow(improper_ctypes)]
+    fn kernel_main(boot: &BootInfo) -> !;
+
+    // Multiboot1 header in the kernel image, e.g.
+    // `__multiboot_header = .; KEEP(*(.multiboot))` in the linker script
+    static __multiboot_header: multiboot::Header;
+
+    // Kernel image: start (.text), end of .text (.rodata follows)
+    static __kernel_start: u8;
+    static __text_end:     u8;
+
+    // Data segment: load‐address, start, end
+    static __data_load: u8;
+    static mut __data_start: u8;
+    static mut __data_end:   u8;
+
+    // BSS segment: start, end
+    static mut __bss_start: u8;
+    static mut __bss_end:   u8;
+
+    // Heap region: start, end
+    static mut __heap_start: u8;
+    static mut __heap_end:   u8;
+
+    // Boot stack (entry.S): page-aligned bottom, top
+    static stack_space: u8;
+    static stack_top:   u8;
+}
+
+
+/// Where linker symbol `sym` really is: the address taken of it, plus the
+/// load bias under `reloc` (see there for what that assumes)
+#[inline(always)]
+pub(crate) fn sym_addr<T>(sym: *const T) -> usize {
+    #[cfg(feature = "reloc")]
+    return reloc::relocate(sym as usize);
+    #[cfg(not(feature = "reloc"))]
+    return sym as usize;
+}
+
+/// Boot information parsed from the MBI; written once in `rust_start`
+/// (after .bss is zeroed) and read-only from then on
+static mut BOOT_INFO: BootInfo = BootInfo::empty();
+
+/// The parsed boot information, also passed to `kernel_main`
+pub fn boot_info() -> &'static BootInfo {
+    // SAFETY: only mutated in `rust_start` before `kernel_main` runs
+    unsafe { &*core::ptr::addr_of!(BOOT_INFO) }
+}
+
+/// Section boundaries from the linker script, for `paging::enforce_wx`
+pub fn kernel_image() -> paging::KernelImage {
+    // SAFETY: only the addresses of the linker symbols are taken
+    unsafe {
+        let (kernel_start, text_end) = (sym_addr(&__kernel_start), sym_addr(&__text_end));
+        let (data_start, bss_end) = (sym_addr(addr_of!(__data_start)), sym_addr(addr_of!(__bss_end)));
+        paging::KernelImage {
+            text:   (kernel_start, text_end),
+            rodata: (text_end, data_start),
+            data:   (data_start, bss_end),
+        }
+    }
+}
+
+/// What is wrong with the Multiboot info pointer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MbiProblem {
+    Null,
+    /// Not aligned to the protocol's guarantee (the value)
+    Misaligned(usize),
+    OutsideRam,
+}
+
+/// Why the runtime refused to start the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitError {
+    /// Neither the Multiboot1 nor the Multiboot2 handoff magic
+    BadMagic(u32),
+    BadMbi { addr: usize, problem: MbiProblem },
+    /// The CPU lacks part of `CpuFeatures::REQUIRED` (what it has)
+    CpuTooOld(cpu::CpuFeatures),
+    /// The image's Multiboot1 header fails its checksum (a mis-built image)
+    BadHeader(multiboot::Header),
+    /// The linker-provided heap overlaps the kernel image (both `[start, end)`)
+    HeapOverlapsKernel { heap: (usize, usize), kernel: (usize, usize) },
+    /// `mem-selftest`: a write to `addr` showed up at `alias` (A20 masked)
+    MemoryAliased { addr: usize, alias: usize },
+    /// `mem-selftest`: `addr` did not read back what was written to it
+    MemoryMismatch { addr: usize, wrote: u64, read: u64 },
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InitError::BadMagic(magic) => write!(
+                f,
+                "Unsupported boot protocol: magic {:#x}, expected {:#x} (Multiboot1) or {:#x} \
+                 (Multiboot2); check the bootloader configuration",
+                magic, MULTIBOOT_MAGIC, MULTIBOOT2_MAGIC
+            ),
+            InitError::BadMbi { addr, problem } => match problem {
+                MbiProblem::Null => write!(f, "Bad multiboot info pointer: null"),
+                MbiProblem::Misaligned(align) => write!(
+                    f, "Bad multiboot info pointer: {:#x} is not {}-byte aligned", addr, align
+                ),
+                MbiProblem::OutsideRam => write!(
+                    f, "Bad multiboot info pointer: {:#x} is outside usable RAM", addr
+                ),
+            },
+            InitError::BadHeader(h) => write!(
+                f,
+                "Bad multiboot header: magic {:#x} + flags {:#x} + checksum {:#x} != 0",
+                h.magic, h.flags, h.checksum
+            ),
+            InitError::CpuTooOld(have) => write!(
+                f,
+                "CPU too old: need {}, have features {:#x}",
+                cpu::CpuFeatures::REQUIRED, have.bits()
+            ),
+            InitError::HeapOverlapsKernel { heap, kernel } => write!(
+                f,
+                "Heap [{:#x}, {:#x}) overlaps kernel image [{:#x}, {:#x})",
+                heap.0, heap.1, kernel.0, kernel.1
+            ),
+            InitError::MemoryAliased { addr, alias } => write!(
+                f, "Memory aliasing: {:#x} also appears at {:#x} (A20 line masked?)", addr, alias
+            ),
+            InitError::MemoryMismatch { addr, wrote, read } => write!(
+                f, "Memory fault: wrote {:#x} to {:#x}, read back {:#x}", wrote, addr, read
+            ),
+        }
+    }
+}
+
+/// Which handoff protocol the loader used, by the magic it left in EAX
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootProtocol {
+    Multiboot1,
+    Multiboot2,
+    /// Anything else (the magic): a misconfigured loader or a stray jump
+    Unknown(u32),
+}
+
+impl BootProtocol {
+    pub const fn from_magic(magic: u32) -> Self {
+        match magic {
+            MULTIBOOT_MAGIC  => BootProtocol::Multiboot1,
+            MULTIBOOT2_MAGIC => BootProtocol::Multiboot2,
+            other            => BootProtocol::Unknown(other),
+        }
+    }
+}
+
+/// Accept only the two Multiboot handoff magics
+fn check_magic(magic: u32) -> Result<(), InitError> {
+    match BootProtocol::from_magic(magic) {
+        BootProtocol::Unknown(magic) => Err(InitError::BadMagic(magic)),
+        _ => Ok(()),
+    }
+}
+
+/// Not booted by a Multiboot loader: say which magics were expected on
+/// COM1 and stop. Nothing is set up yet, so this skips the panic path.
+fn unsupported_protocol(magic: u32) -> ! {
+    serial::write_fmt(format_args!("{}\n", InitError::BadMagic(magic)));
+    halt()
+}
+
+/// The Multiboot1 header linked into the image
+fn header() -> multiboot::Header {
+    // SAFETY: the symbol is provided by the linker script and lies in .text
+    unsafe { core::ptr::read(sym_addr(&__multiboot_header) as *const multiboot::Header) }
+}
+
+/// Check the Multiboot1 header linked into the image: a loader that
+/// accepted a broken header points at a mis-built or corrupted image
+fn verify_header() -> bool {
+    header().is_valid()
+}
+
+/// Reject MBI pointers that are null or misaligned for the protocol
+/// (Multiboot1 guarantees 4-byte, Multiboot2 8-byte alignment)
+fn check_mbi_pointer(magic: u32, mbi_addr: usize) -> Result<(), InitError> {
+    let align = if magic == MULTIBOOT2_MAGIC { 8 } else { 4 };
+    let problem = if mbi_addr == 0 {
+        MbiProblem::Null
+    } else if mbi_addr % align != 0 {
+        MbiProblem::Misaligned(align)
+    } else {
+        return Ok(());
+    };
+    Err(InitError::BadMbi { addr: mbi_addr, problem })
+}
+
+/// Once the memory map is known, make sure the MBI lives in usable RAM.
+/// An empty map gives us nothing to check against, so it is accepted.
+fn check_mbi_in_ram(mbi_addr: usize, map: &MemoryMap) -> Result<(), InitError> {
+    if !map.is_empty() && !map.normalized().contains_usable(mbi_addr) {
+        return Err(InitError::BadMbi { addr: mbi_addr, problem: MbiProblem::OutsideRam });
+    }
+    Ok(())
+}
+
+/// Make sure the CPU provides what the runtime (and rustc) assume
+fn check_cpu(features: cpu::CpuFeatures) -> Result<(), InitError> {
+    if !features.contains(cpu::CpuFeatures::REQUIRED) {
+        return Err(InitError::CpuTooOld(features));
+    }
+    Ok(())
+}
+
+/// Word `check_memory_sanity` writes; in .bss, so it lies in the image
+/// (above 1 MiB) and nothing else uses it
+#[cfg(feature = "mem-selftest")]
+static mut MEM_PROBE: u64 = 0;
+
+/// Smoke test for memory wiring (`mem-selftest`). Probes two addresses:
+///   • `MEM_PROBE` in .bss, written with two complementary markers and
+///     read back each time
+///   • the same address with bit 20 flipped (1 MiB away), only read:
+///     with the A20 line masked both decode to the same cell, so it
+///     follows the markers
+///
+/// The alias is never written, so probing it cannot corrupt whatever
+/// lives there; a false alarm needs it to hold both markers in turn.
+#[cfg(feature = "mem-selftest")]
+fn check_memory_sanity() -> Result<(), InitError> {
+    const MARKERS: [u64; 2] = [0x5AA5_0FF0_C33C_9669, !0x5AA5_0FF0_C33C_9669];
+    let probe = unsafe { core::ptr::addr_of_mut!(MEM_PROBE) };
+    let alias = (probe as usize ^ (1 << 20)) as *const u64;
+
+    let mut followed = 0;
+    for wrote in MARKERS {
+        // SAFETY: `probe` is ours; `alias` is identity-mapped RAM near
+        // the image, and only read
+        let (read, seen) = unsafe {
+            probe.write_volatile(wrote);
+            (probe.read_volatile(), alias.read_volatile())
+        };
+        if read != wrote {
+            return Err(InitError::MemoryMismatch { addr: probe as usize, wrote, read });
+        }
+        if seen == wrote {
+            followed += 1;
+        }
+    }
+    if followed == MARKERS.len() {
+        return Err(InitError::MemoryAliased { addr: probe as usize, alias: alias as usize });
+    }
+    Ok(())
+}
+
+/// `select_heap` and the frame allocator ignore RAM below 1 MiB (BIOS
+/// data, EBDA, option ROMs)
+const HEAP_MIN_ADDR: u64 = 0x10_0000;
+/// ...and above 4 GiB, the most the runtime relies on being identity-mapped
+const HEAP_MAX_ADDR: u64 = 0x1_0000_0000;
+
+/// Boot data the heap and the frame allocator must not overwrite, as
+/// `[start, end)` (unused slots are empty): the MBI itself, the command
+/// line, the framebuffer, the ELF section header table, the symbol and
+/// string tables `backtrace` reads, the boot stack with its guard page
+/// (it sits outside the kernel image's .bss) and every module
+fn boot_data_ranges(info: &BootInfo) -> [(usize, usize); 7 + multiboot::MAX_MODULES] {
+    let mut ranges = [(0, 0); 7 + multiboot::MAX_MODULES];
+    // SAFETY: `info` was parsed from this MBI, so its header is readable
+    let mbi_len = if info.magic == MULTIBOOT2_MAGIC {
+        unsafe { core::ptr::read_unaligned(info.mbi_addr as *const u32) as usize }
+    } else {
+        core::mem::size_of::<multiboot::MultibootInfo>()
+    };
+    ranges[0] = (info.mbi_addr, info.mbi_addr + mbi_len);
+    if let Some(cmdline) = info.cmdline() {
+        ranges[1] = (cmdline.as_ptr() as usize, cmdline.as_ptr() as usize + cmdline.len() + 1);
+    }
+    if let Some(fb) = info.framebuffer() {
+        let len = fb.pitch as usize * fb.height as usize;
+        ranges[2] = (fb.addr as usize, fb.addr as usize + len);
+    }
+    if let Some(sections) = info.elf_sections() {
+        ranges[3] = sections.table_range();
+    }
+    #[cfg(feature = "backtrace")]
+    if let Some(symbols) = backtrace::symbols() {
+        ranges[4..6].copy_from_slice(&symbols.ranges());
+    }
+    // SAFETY: only the addresses of the linker symbols are taken
+    ranges[6] = unsafe { (sym_addr(&stack_space) - paging::PAGE_SIZE, sym_addr(&stack_top)) };
+    for (slot, module) in ranges[7..].iter_mut().zip(info.modules()) {
+        *slot = (module.start, module.end);
+    }
+    ranges
+}
+
+/// Pick the heap from the memory map: the largest page-aligned stretch of
+/// usable RAM between 1 MiB and 4 GiB that touches neither the kernel
+/// image nor any range in `avoid` (boot data the kernel still needs).
+/// `None` if no such stretch exists.
+pub fn select_heap(
+    map:   &MemoryMap,
+    image: &paging::KernelImage,
+    avoid: &[(usize, usize)],
+) -> Option<(usize, usize)> {
+    const PAGE: usize = paging::PAGE_SIZE;
+    let kernel = (image.text.0, image.data.1);
+    let excluded = || {
+        core::iter::once(kernel).chain(avoid.iter().copied()).filter(|&(start, end)| end > start)
+    };
+
+    let mut best: Option<(usize, usize)> = None;
+    // sorted and merged, so a usable range split into touching entries
+    // counts as one
+    let map = map.normalized();
+    for region in map.iter().filter(|r| r.kind == multiboot::RegionKind::Usable) {
+        let start = region.base.max(HEAP_MIN_ADDR);
+        let end = region.base.saturating_add(region.length).min(HEAP_MAX_ADDR);
+        if end <= start {
+            continue;
+        }
+        let (start, end) = (start as usize, end as usize);
+
+        // a free gap begins at the region start or right after an excluded
+        // range, and runs up to the next excluded range
+        let gap_starts = core::iter::once(start).chain(excluded().map(|(_, e)| e));
+        for gap_start in gap_starts.filter(|&g| g >= start && g < end) {
+            if excluded().any(|(s, e)| s <= gap_start && gap_start < e) {
+                continue;
+            }
+            let gap_end = excluded()
+                .map(|(s, _)| s)
+                .filter(|&s| s > gap_start && s < end)
+                .min()
+                .unwrap_or(end);
+            let lo = match gap_start.checked_add(PAGE - 1) {
+                Some(v) => v & !(PAGE - 1),
+                None    => continue,
+            };
+            let hi = gap_end & !(PAGE - 1);
+            if hi > lo && best.map_or(true, |(b0, b1)| hi - lo > b1 - b0) {
+                best = Some((lo, hi));
+            }
+        }
+    }
+    best
+}
+
+/// Runtime log ceiling installed at boot; the kernel may change it with
+/// `logging::init_logging`
+const LOG_LEVEL: log::LevelFilter =
+    if cfg!(debug_assertions) { log::LevelFilter::Debug } else { log::LevelFilter::Info };
+
+/// Allocations inside the kernel's own .text/.data/.bss would silently
+/// overwrite it, so refuse a heap that overlaps `[kernel_start, bss_end)`
+#[cfg(not(feature = "no-heap"))]
+fn check_heap(heap: (usize, usize), kernel: (usize, usize)) -> Result<(), InitError> {
+    if ranges_overlap(heap, kernel) {
+        return Err(InitError::HeapOverlapsKernel { heap, kernel });
+    }
+    Ok(())
+}
+
+/// Set on the first entry into `rust_start`. A safety net against a buggy
+/// trampoline or a stray AP jumping in again, not a supported path: a
+/// second entry halts instead of re-copying .data and re-zeroing .bss
+/// under live state. Re-entering via `_start` is not caught, since the
+/// stub clears .bss (and with it this flag) itself.
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+/// Validate the handoff, bring up the CPU and memory segments, and parse
+/// the boot information. Policy (what to do on `Err`) is the caller's.
+///
+/// SAFETY: call exactly once, from `rust_start`, before anything relies
+/// on .data, .bss or the heap.
+unsafe fn init_runtime(magic: u32, mbi_addr: usize) -> Result<BootInfo, InitError> {
+    // 1) Validate Multiboot signature and the image's own header
+    check_magic(magic)?;
+    check_mbi_pointer(magic, mbi_addr)?;
+    // Multiboot2 images carry a different header
+    if magic == MULTIBOOT_MAGIC && !verify_header() {
+        return Err(InitError::BadHeader(header()));
+    }
+
+    // 2) Make sure the CPU provides what the runtime (and rustc) assume
+    check_cpu(cpu::features())?;
+    phase::advance(Phase::Validated);
+
+    // 3) Enable FPU/SSE before anything (even the .data copy) may use it;
+    //    a `soft-float` build never touches either
+    #[cfg(not(feature = "soft-float"))]
+    cpu::enable_sse();
+    // Under `mem-selftest`, catch aliased RAM before the copies below rely on it
+    #[cfg(feature = "mem-selftest")]
+    check_memory_sanity()?;
+
+    // 4) Copy .data from flash (load‐address) to RAM; under `reloc` every
+    //    linker symbol is shifted by the load bias first
+    let data_start = sym_addr(addr_of!(__data_start));
+    let data_size  = sym_addr(addr_of!(__data_end)) - data_start;
+    copy_data(
+        sym_addr(&__data_load) as *const u8,
+        data_start as *mut u8,
+        data_size,
+    );
+    phase::advance(Phase::DataCopied);
+
+    // 5) Zero .bss
+    let bss_start = sym_addr(addr_of!(__bss_start));
+    let bss_size  = sym_addr(addr_of!(__bss_end)) - bss_start;
+    let done = phase::current();
+    zero_region(bss_start as *mut u8, bss_size);
+    // ENTERED and the phase tracker live in .bss too: set them again
+    ENTERED.store(true, Ordering::Release);
+    phase::restore(done);
+    phase::advance(Phase::BssZeroed);
+    // Paint the boot stack below this frame (it lives in `.stack`, not .bss)
+    #[cfg(feature = "stack-probe")]
+    stack::paint_stack();
+
+    // 6) Route the log macros to COM1 (`log` keeps its state in .bss)
+    logging::init_logging(LOG_LEVEL);
+    // ...and measure the TSC for `delay::delay_ms` (warns if there is no PIT)
+    delay::calibrate();
+
+    // 7) Allow no-execute mappings before any table uses them (W^X is
+    //    then unavailable if the CPU lacks NX)
+    if !paging::enable_nx() {
+        logging::warn!("CPU lacks NX: no-execute mappings (and W^X) unavailable");
+    }
+    // ...then switch to our identity + higher-half tables; the tables live
+    // in .bss, and every later page-table edit (stack guard) must land in them
+    #[cfg(feature = "higher-half")]
+    paging::map_higher_half();
+
+    // 8) Load our own GDT/TSS; both live in .bss, so not before step 5
+    gdt::init();
+
+    // 9) Catch CPU exceptions; the kernel may replace entries later
+    idt::init();
+    // From here on a hang before `kernel_main` reboots the machine
+    #[cfg(feature = "boot-watchdog")]
+    watchdog::arm();
+
+    // 10) Parse boot info for the matching protocol (must follow .bss zeroing)
+    let info = BootInfo::parse(magic, mbi_addr);
+    check_mbi_in_ram(mbi_addr, info.memory_map_or_empty())?;
+    // ...and find the kernel's symbols for panic backtraces
+    #[cfg(feature = "backtrace")]
+    if !backtrace::init_symbols(&info) {
+        logging::warn!("backtrace: no ELF symbol table, addresses stay raw");
+    }
+
+    // 11) Initialize heap allocator (there is none under `no-heap`)
+    #[cfg(not(feature = "no-heap"))]
+    let heap = Some(init_heap(&info)?);
+    #[cfg(feature = "no-heap")]
+    let heap = None;
+    // ...and hand the remaining RAM to the frame allocator
+    init_frames(&info, heap);
+    phase::advance(Phase::HeapReady);
+
+    // 12) Unmap the guard page below the boot stack
+    if !stack::install_stack_guard(sym_addr(&stack_space)) {
+        logging::warn!("boot stack guard page not installed");
+    }
+    Ok(info)
+}
+
+/// Set up `ALLOCATOR` once its range is known to be clear of the image:
+/// the linker's range, or under `heap-from-mmap` the largest free RAM the
+/// memory map offers; then reserve the image and boot data inside it.
+/// Returns the whole range handed to `ALLOCATOR.init`: `reserve` may
+/// split it into several regions, all of them inside this range.
+///
+/// SAFETY: call once from `init_runtime`, after `info` was parsed.
+#[cfg(not(feature = "no-heap"))]
+unsafe fn init_heap(info: &BootInfo) -> Result<(usize, usize), InitError> {
+    let image = kernel_image();
+    let kernel = (image.text.0, image.data.1);
+    let linker_heap = (
+        sym_addr(addr_of!(__heap_start)),
+        sym_addr(addr_of!(__heap_end)),
+    );
+    let boot_data = boot_data_ranges(info);
+    #[cfg(feature = "heap-from-mmap")]
+    let heap = select_heap(info.memory_map_or_empty(), &image, &boot_data).unwrap_or(linker_heap);
+    #[cfg(not(feature = "heap-from-mmap"))]
+    let heap = linker_heap;
+    check_heap(heap, kernel)?;
+    ALLOCATOR.init(heap.0, heap.1);
+    for &(start, end) in core::iter::once(&kernel).chain(boot_data.iter()) {
+        if !ALLOCATOR.reserve(start, end) {
+            logging::warn!("heap: out of regions reserving [{:#x}, {:#x})", start, end);
+        }
+    }
+    Ok(heap)
+}
+
+/// Seed `frame::allocate_frame` from the memory map, keeping out low
+/// memory, the kernel image, the heap range from `init_heap` (all of it,
+/// whatever regions `reserve` split it into) and the boot data.
+///
+/// SAFETY: call once from `init_runtime`, after the heap is set up.
+unsafe fn init_frames(info: &BootInfo, heap: Option<(usize, usize)>) {
+    let mut frames = frame::FrameAllocator::new(info.memory_map_or_empty());
+    let image = kernel_image();
+    frames.mark_used(0, HEAP_MIN_ADDR);
+    frames.mark_used(image.text.0 as u64, image.data.1 as u64);
+    if let Some((start, end)) = heap {
+        frames.mark_used(start as u64, end as u64);
+    }
+    for (start, end) in boot_data_ranges(info) {
+        if !frames.mark_used(start as u64, end as u64) {
+            logging::warn!("frames: out of slots reserving [{:#x}, {:#x})", start, end);
+        }
+    }
+    frame::init(frames);
+}
+
+/// TSC at `rust_start` entry and just before `kernel_main`. Both are
+/// stored after .bss is zeroed (the entry stamp is held until then).
+static RUNTIME_TSC_START: AtomicU64 = AtomicU64::new(0);
+static RUNTIME_TSC_END:   AtomicU64 = AtomicU64::new(0);
+
+/// Cycles the runtime spent between `rust_start` and `kernel_main`, as raw
+/// TSC ticks. Divide by `delay::tsc_khz()` (once calibrated) for
+/// milliseconds.
+pub fn runtime_elapsed_cycles() -> u64 {
+    RUNTIME_TSC_END.load(Ordering::Relaxed).wrapping_sub(RUNTIME_TSC_START.load(Ordering::Relaxed))
+}
+
+/// Write to the early console (COM1) without `fmt`; `runtime_core` uses
+/// it for output that must not depend on the panic path
+pub(crate) fn early_write(s: &str) {
+    serial::write_str(s);
+}
+
+/// Index of the executing CPU for `runtime_core` (per-CPU heap arenas):
+/// its initial APIC ID
+pub(crate) fn cpu_id() -> usize {
+    cpu::apic_id() as usize
+}
+
+/// Stop this CPU for good: interrupts off, then `hlt`
+pub(crate) fn halt() -> ! {
+    loop {
+        unsafe { core::arch::asm!("cli", "hlt"); }
+    }
+}
+
+/// Entry point called from assembly stub (`entry.S`):
+///   EDI = multiboot_magic, ESI = mbi_ptr
+#[no_mangle]
+pub extern "C" fn rust_start(magic: u32, mbi_addr: usize) -> ! {
+    if ENTERED.swap(true, Ordering::AcqRel) {
+        halt()
+    }
+
+    let start = cpu::rdtsc();
+
+    if let BootProtocol::Unknown(magic) = BootProtocol::from_magic(magic) {
+        unsupported_protocol(magic)
+    }
+
+    // SAFETY: ENTERED guarantees this is the first and only call
+    match unsafe { init_runtime(magic, mbi_addr) } {
+        Ok(info) => unsafe { BOOT_INFO = info },
+        Err(err) => panic!("{}", err),
+    }
+    RUNTIME_TSC_START.store(start, Ordering::Relaxed);
+
+    // Made it: the kernel (or test suite) owns the PIT and interrupts from here
+    #[cfg(feature = "boot-watchdog")]
+    unsafe { watchdog::disarm() };
+
+    // Test builds run the in-kernel test suite instead of the kernel
+    #[cfg(all(test, feature = "testing"))]
+    test_main();
+
+    RUNTIME_TSC_END.store(cpu::rdtsc(), Ordering::Relaxed);
+
+    // Dump what the runtime found (after the stamp, so it is not counted)
+    #[cfg(feature = "banner")]
+    banner::print_banner(boot_info());
+
+    // 13) Call the kernel’s main function (does not return), through the
+    //     higher-half alias under `higher-half`
+    phase::advance(Phase::KernelEntered);
+    #[cfg(feature = "higher-half")]
+    unsafe { paging::enter_higher_half(higher_half_start, 0) }
+    #[cfg(not(feature = "higher-half"))]
+    unsafe { kernel_main(boot_info()) }
+}
+
+/// Continuation of `rust_start` at `paging::KERNEL_OFFSET`: from here on
+/// RIP-relative addresses (`boot_info()` included) resolve high
+#[cfg(feature = "higher-half")]
+extern "C" fn higher_half_start(_: usize) -> ! {
+    unsafe { kernel_main(boot_info()) }
+}
+
+/// Pause before a `panic_reboot` reset. The UART keeps shifting out its
+/// FIFO after the last write returns, so without this the tail of the
+/// panic message is lost (and a watching human has no time to read it).
+#[cfg(feature = "panic_reboot")]
+const PANIC_REBOOT_DELAY_US: usize = 2_000_000;
+
+/// Tone `panic-beep` sounds once the report is out
+#[cfg(feature = "panic-beep")]
+const PANIC_BEEP_HZ: u32 = 880;
+#[cfg(feature = "panic-beep")]
+const PANIC_BEEP_MS: u32 = 500;
+
+/// Panic handler prints info over COM1 then halts (or reboots under
+/// `panic_reboot`)
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // Panicked while panicking: anything below may fault again, so halt
+    if runtime_core::panic::enter() > 1 {
+        halt()
+    }
+
+    // A failing in-kernel test reports itself and moves on to the next,
+    // before anything below (hook, halting the other cores) touches state
+    // the remaining tests still need
+    #[cfg(feature = "testing")]
+    testing::on_panic(info);
+
+    // A panic during init leaves the boot watchdog ticking with IF=1: stop
+    // it before it resets the machine over the report
+    #[cfg(feature = "boot-watchdog")]
+    unsafe { watchdog::disarm() };
+
+    // Then the kernel's hook; the depth guard catches it faulting
+    runtime_core::panic::run_hook(info);
+
+    // Freeze the other cores before they run further on corrupted state
+    #[cfg(feature = "smp")]
+    smp::halt_other_cpus();
+
+    // From here on nothing may allocate (checked in debug builds)
+    runtime_core::panic::begin();
+
+    // To the kernel's console, else COM1 (serial init is idempotent, so
+    // this works even if nothing set it up)
+    match runtime_core::console::console() {
+        Some(_) => runtime_core::panic::report(&mut runtime_core::console::ConsoleWriter, info),
+        None    => runtime_core::panic::report(&mut serial::com1(), info),
+    }
+    #[cfg(feature = "vga-panic")]
+    vga::panic_print(format_args!("{}", runtime_core::panic::Report(info)));
+    #[cfg(feature = "backtrace")]
+    backtrace::print(&mut serial::com1());
+
+    // Let the UART FIFO empty before QEMU exits or the CPU halts
+    serial::com1().drain();
+
+    // Headless machines without a serial cable at least get a beep
+    #[cfg(feature = "panic-beep")]
+    delay::beep(PANIC_BEEP_HZ, PANIC_BEEP_MS);
+
+    // Under test, report failure to the QEMU harness instead of hanging
+    #[cfg(feature = "testing")]
+    qemu::exit(qemu::QemuExitCode::Failed);
+
+    // Unattended machines come back up instead of hanging
+    #[cfg(feature = "panic_reboot")]
+    {
+        reboot::io_delay_us(PANIC_REBOOT_DELAY_US);
+        reboot::triple_fault_reboot()
+    }
+
+    // Otherwise (or if QEMU didn't exit) just spin with HLT
+    #[cfg(not(feature = "panic_reboot"))]
+    loop {
+        unsafe { core::arch::asm!("hlt"); }
+    }
+}
+
+// Minimal lang-items to satisfy `no_std` linking
+#[lang = "eh_personality"] extern fn eh_personality() {}
+#[lang = "oom"] fn oom(_: Layout) -> ! { loop { unsafe { core::arch::asm!("hlt"); } } }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multiboot::{MemoryRegion, RegionKind};
+
+    #[test_case]
+    fn mbi_pointer_must_be_non_null_and_aligned() {
+        assert_eq!(check_mbi_pointer(MULTIBOOT_MAGIC, 0x9500), Ok(()));
+        assert_eq!(check_mbi_pointer(MULTIBOOT_MAGIC, 0x9504), Ok(()));
+        assert_eq!(check_mbi_pointer(MULTIBOOT2_MAGIC, 0x9508), Ok(()));
+        assert_eq!(
+            check_mbi_pointer(MULTIBOOT_MAGIC, 0),
+            Err(InitError::BadMbi { addr: 0, problem: MbiProblem::Null })
+        );
+        assert_eq!(
+            check_mbi_pointer(MULTIBOOT_MAGIC, 0x9502),
+            Err(InitError::BadMbi { addr: 0x9502, problem: MbiProblem::Misaligned(4) })
+        );
+        // Multiboot2 promises 8-byte alignment
+        assert_eq!(
+            check_mbi_pointer(MULTIBOOT2_MAGIC, 0x9504),
+            Err(InitError::BadMbi { addr: 0x9504, problem: MbiProblem::Misaligned(8) })
+        );
+    }
+
+    #[test_case]
+    fn mbi_must_lie_in_usable_ram() {
+        let mut map = MemoryMap::new();
+        map.push(MemoryRegion { base: 0, length: 0x9fc00, kind: RegionKind::Usable });
+        map.push(MemoryRegion { base: 0xf0000, length: 0x10000, kind: RegionKind::Reserved });
+        assert_eq!(check_mbi_in_ram(0x9500, &map), Ok(()));
+        assert_eq!(
+            check_mbi_in_ram(0xf0100, &map),
+            Err(InitError::BadMbi { addr: 0xf0100, problem: MbiProblem::OutsideRam })
+        );
+        // nothing to check against
+        assert_eq!(check_mbi_in_ram(0xf0100, &MemoryMap::new()), Ok(()));
+    }
+
+    #[test_case]
+    fn cpu_must_have_every_required_feature() {
+        let all = cpu::CpuFeatures::from_cpuid(!0, !0, !0, !0);
+        assert_eq!(check_cpu(all), Ok(()));
+        assert_eq!(check_cpu(cpu::CpuFeatures::REQUIRED), Ok(()));
+        let none = cpu::CpuFeatures::empty();
+        assert_eq!(check_cpu(none), Err(InitError::CpuTooOld(none)));
+    }
+
+    #[test_case]
+    fn entry_flag_survives_bss_zeroing() {
+        // set again after .bss was cleared, so a second entry still halts
+        assert!(ENTERED.load(Ordering::Acquire));
+    }
+
+    #[test_case]
+    #[cfg(not(feature = "no-heap"))]
+    fn heap_must_not_overlap_the_kernel() {
+        let kernel = (0x10_0000, 0x18_0000);
+        assert_eq!(check_heap((0x20_0000, 0x40_0000), kernel), Ok(()));
+        assert_eq!(check_heap((0x18_0000, 0x40_0000), kernel), Ok(()));
+        let heap = (0x17_f000, 0x40_0000);
+        assert_eq!(check_heap(heap, kernel), Err(InitError::HeapOverlapsKernel { heap, kernel }));
+    }
+
+    #[test_case]
+    fn magic_selects_the_boot_protocol() {
+        assert_eq!(BootProtocol::from_magic(0x2BAD_B002), BootProtocol::Multiboot1);
+        assert_eq!(BootProtocol::from_magic(0x36D7_6289), BootProtocol::Multiboot2);
+        assert_eq!(BootProtocol::from_magic(0x1BAD_B002), BootProtocol::Unknown(0x1BAD_B002));
+        assert_eq!(check_magic(MULTIBOOT_MAGIC), Ok(()));
+        assert_eq!(check_magic(MULTIBOOT2_MAGIC), Ok(()));
+        assert_eq!(check_magic(0), Err(InitError::BadMagic(0)));
+    }
+
+    #[test_case]
+    fn unknown_magic_names_the_expected_ones() {
+        use core::fmt::Write;
+        let mut out = crate::runtime_core::FmtBuf::<192>::new();
+        write!(out, "{}", InitError::BadMagic(0xdead_beef)).unwrap();
+        let text = out.as_str();
+        assert!(text.contains("magic 0xdeadbeef"));
+        assert!(text.contains("0x2badb002 (Multiboot1) or 0x36d76289 (Multiboot2)"));
+    }
+}
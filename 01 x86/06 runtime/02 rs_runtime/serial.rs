@@ -0,0 +1,367 @@
+// rooster-os/arch/x86/runtime/rs_runtime/serial.rs
+//! Minimal 16550 UART driver for COM1, used for early and panic output.
+//!   • 38400 baud, 8 data bits, no parity, 1 stop bit (8N1)
+//!   • Register access goes through `Uart`: `PortUart` (I/O ports, the
+//!     PC default) or `MmioUart` (memory-mapped, e.g. some QEMU machines)
+//!   • `select_mmio` moves the log/panic path to an MMIO UART
+//!   • Lock-free: every call is a short sequence of port writes, so the
+//!     panic handler can use it even if another CPU was mid-write
+//!   • Lazily initialized on first use; `init` is idempotent
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::port::{inb, outb};
+use crate::Console;
+
+/// I/O base of the first serial port
+pub const COM1: u16 = 0x3F8;
+
+/// Line-status register bit: transmit holding register empty
+const LSR_THR_EMPTY: u8 = 1 << 5;
+/// Line-status register bit: holding and shift registers both empty
+const LSR_TX_EMPTY:  u8 = 1 << 6;
+
+/// Upper bound on `drain` polls (~1 s of port reads), so a wedged or
+/// missing UART cannot hang the caller
+const DRAIN_SPIN_LIMIT: usize = 1_000_000;
+
+// 16550 register offsets (in registers, not bytes)
+const REG_DATA:    usize = 0; // THR / divisor low (DLAB)
+const REG_IER:     usize = 1; // interrupt enable / divisor high (DLAB)
+const REG_FCR:     usize = 2;
+const REG_LCR:     usize = 3;
+const REG_MCR:     usize = 4;
+const REG_LSR:     usize = 5;
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// MMIO base (0 = use COM1) and register stride chosen by `select_mmio`
+static MMIO_BASE:   AtomicUsize = AtomicUsize::new(0);
+static MMIO_STRIDE: AtomicUsize = AtomicUsize::new(1);
+
+/// Access to the eight 16550 registers, wherever they live
+pub trait Uart {
+    fn read_reg(&self, reg: usize) -> u8;
+    fn write_reg(&self, reg: usize, value: u8);
+}
+
+/// 16550 behind I/O ports starting at `base`
+#[derive(Clone, Copy)]
+pub struct PortUart {
+    base: u16,
+}
+
+impl PortUart {
+    pub const fn new(base: u16) -> Self {
+        PortUart { base }
+    }
+}
+
+impl Uart for PortUart {
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { inb(self.base + reg as u16) }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { outb(self.base + reg as u16, value) }
+    }
+}
+
+/// 16550 mapped at `base`, register `n` at `base + n * stride` (stride 1
+/// for byte-packed registers, 4 for the common 32-bit spacing)
+#[derive(Clone, Copy)]
+pub struct MmioUart {
+    base:   usize,
+    stride: usize,
+}
+
+impl MmioUart {
+    /// SAFETY: `base` must be the mapped (uncached) register block of a
+    /// 16550 with the given stride
+    pub const unsafe fn new(base: usize, stride: usize) -> Self {
+        MmioUart { base, stride }
+    }
+}
+
+impl Uart for MmioUart {
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { ((self.base + reg * self.stride) as *const u8).read_volatile() }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { ((self.base + reg * self.stride) as *mut u8).write_volatile(value) }
+    }
+}
+
+/// The UART behind the log/panic path: COM1 unless `select_mmio` ran
+#[derive(Clone, Copy)]
+pub enum BootUart {
+    Port(PortUart),
+    Mmio(MmioUart),
+}
+
+impl Uart for BootUart {
+    fn read_reg(&self, reg: usize) -> u8 {
+        match self {
+            BootUart::Port(uart) => uart.read_reg(reg),
+            BootUart::Mmio(uart) => uart.read_reg(reg),
+        }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        match self {
+            BootUart::Port(uart) => uart.write_reg(reg, value),
+            BootUart::Mmio(uart) => uart.write_reg(reg, value),
+        }
+    }
+}
+
+/// A 16550 driven through `U` (I/O ports by default)
+#[derive(Clone, Copy)]
+pub struct SerialPort<U: Uart = PortUart> {
+    uart: U,
+}
+
+impl SerialPort<PortUart> {
+    /// The UART at I/O base `base`
+    pub const fn new(base: u16) -> Self {
+        SerialPort { uart: PortUart::new(base) }
+    }
+}
+
+impl<U: Uart> SerialPort<U> {
+    pub const fn with_uart(uart: U) -> Self {
+        SerialPort { uart }
+    }
+
+    /// Program the UART for 38400 8N1 with FIFOs enabled
+    pub fn init(&self) {
+        let u = &self.uart;
+        u.write_reg(REG_IER,  0x00); // disable UART interrupts
+        u.write_reg(REG_LCR,  0x80); // DLAB on: next two writes set the divisor
+        u.write_reg(REG_DATA, 0x03); // divisor low byte  (115200 / 3 = 38400)
+        u.write_reg(REG_IER,  0x00); // divisor high byte
+        u.write_reg(REG_LCR,  0x03); // DLAB off, 8 bits, no parity, 1 stop bit
+        u.write_reg(REG_FCR,  0xC7); // enable and clear FIFOs, 14-byte threshold
+        u.write_reg(REG_MCR,  0x0B); // DTR + RTS + OUT2
+    }
+
+    fn line_status(&self) -> u8 {
+        self.uart.read_reg(REG_LSR)
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        while self.line_status() & LSR_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.uart.write_reg(REG_DATA, byte)
+    }
+
+    /// Write `s`, turning `\n` into `\r\n`
+    fn write_text(&self, s: &str) {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+    }
+
+    /// Wait until the last written byte has left the shift register, so
+    /// nothing is lost if the caller halts or resets right after
+    pub fn drain(&self) {
+        for _ in 0..DRAIN_SPIN_LIMIT {
+            if self.line_status() & LSR_TX_EMPTY != 0 {
+                return;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<U: Uart> fmt::Write for SerialPort<U> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_text(s);
+        Ok(())
+    }
+}
+
+/// As a `print!`/`println!` sink: `static COM: SerialPort =
+/// SerialPort::new(COM1)`, then `serial::init()` and `set_console(&COM)`
+impl<U: Uart + Sync> Console for SerialPort<U> {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        self.write_text(s);
+        Ok(())
+    }
+}
+
+/// Send the log/panic path to the MMIO 16550 at `base` instead of COM1;
+/// it is programmed on next use.
+///
+/// SAFETY: as for `MmioUart::new`; the block must stay mapped for good
+pub unsafe fn select_mmio(base: usize, stride: usize) {
+    MMIO_STRIDE.store(stride, Ordering::Relaxed);
+    MMIO_BASE.store(base, Ordering::Release);
+    INITIALIZED.store(false, Ordering::Release);
+}
+
+fn boot_uart() -> SerialPort<BootUart> {
+    match MMIO_BASE.load(Ordering::Acquire) {
+        0    => SerialPort::with_uart(BootUart::Port(PortUart::new(COM1))),
+        base => SerialPort::with_uart(BootUart::Mmio(unsafe {
+            MmioUart::new(base, MMIO_STRIDE.load(Ordering::Relaxed))
+        })),
+    }
+}
+
+/// Initialize the boot UART (COM1 by default) once; later calls
+/// (including from the panic handler) are no-ops
+pub fn init() {
+    if !INITIALIZED.swap(true, Ordering::AcqRel) {
+        boot_uart().init();
+    }
+}
+
+/// An initialized handle to the boot UART (COM1 unless `select_mmio`
+/// chose another), for APIs that take a `fmt::Write`
+pub fn com1() -> SerialPort<BootUart> {
+    init();
+    boot_uart()
+}
+
+/// Write a string to the boot UART, initializing it first if needed
+pub fn write_str(s: &str) {
+    com1().write_text(s);
+}
+
+/// Write pre-formatted arguments to the boot UART. Formatting goes
+/// straight to the port, so this never touches the heap.
+pub fn write_fmt(args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut com1(), args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::fmt::Write;
+
+    /// A 16550 whose line status is `lsr` (ready to send by default); it
+    /// logs every register write and counts line-status reads
+    struct MockUart {
+        writes: [Cell<(usize, u8)>; 32],
+        len:    Cell<usize>,
+        lsr:    Cell<u8>,
+        polls:  Cell<usize>,
+    }
+
+    impl MockUart {
+        fn new() -> Self {
+            MockUart {
+                writes: Default::default(),
+                len:    Cell::new(0),
+                lsr:    Cell::new(LSR_THR_EMPTY | LSR_TX_EMPTY),
+                polls:  Cell::new(0),
+            }
+        }
+
+        /// Bytes written to the data register, in order
+        fn sent(&self, out: &mut [u8]) -> usize {
+            let mut n = 0;
+            for w in &self.writes[..self.len.get()] {
+                if let (REG_DATA, byte) = w.get() {
+                    out[n] = byte;
+                    n += 1;
+                }
+            }
+            n
+        }
+    }
+
+    impl Uart for MockUart {
+        fn read_reg(&self, reg: usize) -> u8 {
+            if reg != REG_LSR {
+                return 0;
+            }
+            self.polls.set(self.polls.get() + 1);
+            self.lsr.get()
+        }
+
+        fn write_reg(&self, reg: usize, value: u8) {
+            let n = self.len.get();
+            self.writes[n].set((reg, value));
+            self.len.set(n + 1);
+        }
+    }
+
+    #[test_case]
+    fn init_programs_38400_8n1() {
+        let port = SerialPort::with_uart(MockUart::new());
+        port.init();
+        let log = &port.uart.writes[..port.uart.len.get()];
+        let expected = [
+            (REG_IER, 0x00), (REG_LCR, 0x80), (REG_DATA, 0x03), (REG_IER, 0x00),
+            (REG_LCR, 0x03), (REG_FCR, 0xC7), (REG_MCR, 0x0B),
+        ];
+        assert_eq!(log.len(), expected.len());
+        for (w, &e) in log.iter().zip(&expected) {
+            assert_eq!(w.get(), e);
+        }
+    }
+
+    #[test_case]
+    fn newlines_go_out_as_crlf() {
+        let mut port = SerialPort::with_uart(MockUart::new());
+        write!(port, "ok\nPANIC: {}\n", 7).unwrap();
+        let mut out = [0; 32];
+        let n = port.uart.sent(&mut out);
+        assert_eq!(&out[..n], b"ok\r\nPANIC: 7\r\n");
+    }
+
+    #[test_case]
+    fn drain_returns_once_the_transmitter_is_empty() {
+        let port = SerialPort::with_uart(MockUart::new());
+        port.drain();
+        assert_eq!(port.uart.polls.get(), 1);
+    }
+
+    #[test_case]
+    fn drain_gives_up_on_a_uart_that_never_empties() {
+        let port = SerialPort::with_uart(MockUart::new());
+        // holding register free, shift register never done
+        port.uart.lsr.set(LSR_THR_EMPTY);
+        port.drain();
+        assert_eq!(port.uart.polls.get(), DRAIN_SPIN_LIMIT);
+    }
+
+    #[test_case]
+    fn mmio_registers_are_spaced_by_the_stride() {
+        // eight 32-bit registers, line status reporting ready
+        let mut regs = [0u32; 8];
+        regs[REG_LSR] = (LSR_THR_EMPTY | LSR_TX_EMPTY) as u32;
+        let uart = unsafe { MmioUart::new(regs.as_mut_ptr() as usize, 4) };
+        let port = SerialPort::with_uart(uart);
+        port.init();
+        port.write_byte(b'R');
+        assert_eq!(regs[REG_DATA], b'R' as u32);
+        assert_eq!(regs[REG_FCR], 0xC7);
+        assert_eq!(regs[REG_LCR], 0x03);
+        assert_eq!(regs[REG_MCR], 0x0B);
+    }
+
+    #[test_case]
+    fn the_panic_report_goes_out_without_the_heap() {
+        // once `begin` runs, any heap allocation trips the debug check, so
+        // this only passes if formatting never allocates
+        let mut port = SerialPort::with_uart(MockUart::new());
+        crate::runtime_core::panic::begin();
+        crate::runtime_core::panic::report(&mut port, "out of memory");
+        let written = writeln!(port, "{:#x}", 0xdead);
+        crate::runtime_core::panic::end();
+        assert!(written.is_ok());
+        let mut out = [0; 32];
+        let n = port.uart.sent(&mut out);
+        assert_eq!(&out[..n], b"PANIC: out of memory\r\n0xdead\r\n");
+    }
+}
@@ -0,0 +1,70 @@
+// rooster-os/arch/x86/runtime/rs_runtime/smp.rs
+//! Multi-core support for the panic path (`smp` feature).
+//!   • The kernel registers the local APIC MMIO base during AP bringup
+//!   • `halt_other_cpus` broadcasts an NMI to every other core, whose
+//!     NMI handler then parks it with interrupts off
+//!   • With no LAPIC base registered (UP boot) it is a no-op
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::idt::{self, HandlerFunc, InterruptStackFrame};
+
+/// IDT vector of the non-maskable interrupt
+pub const NMI_VECTOR: u8 = 2;
+
+// Local APIC interrupt command register (xAPIC MMIO offsets)
+const LAPIC_ICR_LOW:  usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+
+const ICR_DELIVERY_NMI:       u32 = 0b100 << 8;
+const ICR_DELIVERY_PENDING:   u32 = 1 << 12;
+const ICR_LEVEL_ASSERT:       u32 = 1 << 14;
+const ICR_ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+
+/// Spins to wait for the ICR to accept the IPI before giving up
+const ICR_SPIN_LIMIT: usize = 1_000_000;
+
+/// Identity-mapped LAPIC MMIO base; 0 until the kernel sets it
+static LAPIC_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Register the local APIC base (normally `cpu::lapic_base()`) so a
+/// panic can stop the other cores. Call during AP bringup.
+pub fn set_lapic_base(base: usize) {
+    LAPIC_BASE.store(base, Ordering::Release);
+}
+
+/// NMI handler for parked cores: never returns to the interrupted code
+extern "x86-interrupt" fn nmi_halt(_frame: InterruptStackFrame) {
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)); }
+    }
+}
+
+/// Stop every other core by sending it an NMI. Safe to call from the
+/// panic handler: does nothing if no LAPIC base was registered, and
+/// gives up rather than hangs if the ICR never reports delivery.
+pub fn halt_other_cpus() {
+    let base = LAPIC_BASE.load(Ordering::Acquire);
+    if base == 0 {
+        return;
+    }
+
+    // SAFETY: `base` was registered as the identity-mapped LAPIC; the
+    // IDT is shared, so every core picks up the parking NMI handler.
+    unsafe {
+        idt::set_handler(NMI_VECTOR, nmi_halt as HandlerFunc as usize);
+
+        let icr_low  = (base + LAPIC_ICR_LOW)  as *mut u32;
+        let icr_high = (base + LAPIC_ICR_HIGH) as *mut u32;
+        icr_high.write_volatile(0);
+        icr_low.write_volatile(ICR_DELIVERY_NMI | ICR_LEVEL_ASSERT | ICR_ALL_EXCLUDING_SELF);
+
+        for _ in 0..ICR_SPIN_LIMIT {
+            if icr_low.read_volatile() & ICR_DELIVERY_PENDING == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// rooster-os/arch/x86/runtime/rs_runtime/stack.rs
+//! Boot-stack protection: an unmapped guard page below the stack turns
+//! an overflow into a page fault instead of silent memory corruption.
+//! Under `stack-probe` the unused stack is painted with a sentinel right
+//! after .bss is zeroed, so its peak depth can be read back later. The
+//! stack itself lives in entry.S's `.stack` section, outside .bss.
+
+use crate::paging::{self, PAGE_SIZE, PF_PRESENT};
+
+/// Mark the 4 KiB page directly below `stack_bottom` as not present.
+/// Returns false if the page could not be located in the page tables.
+///
+/// SAFETY: paging must be enabled (always true in long mode) with the
+/// tables identity-mapped, `stack_bottom` must be page aligned, and the
+/// page below it must hold nothing live — entry.S reserves `stack_guard`
+/// for exactly this purpose.
+pub unsafe fn install_stack_guard(stack_bottom: usize) -> bool {
+    debug_assert!(stack_bottom % PAGE_SIZE == 0, "stack bottom not page aligned");
+    let guard = stack_bottom - PAGE_SIZE;
+
+    match paging::get_pte(guard) {
+        Some(pte) => {
+            *pte &= !PF_PRESENT;
+            paging::flush_tlb(guard);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Word `paint_stack` fills the unused boot stack with
+#[cfg(feature = "stack-probe")]
+pub const STACK_SENTINEL: u64 = 0x57AC_C0DE_57AC_C0DE;
+
+/// Bytes left unpainted right below the painter's own RSP
+#[cfg(feature = "stack-probe")]
+const PAINT_MARGIN: usize = 256;
+
+/// Boot stack as `[bottom, top)`
+#[cfg(feature = "stack-probe")]
+fn boot_stack() -> (usize, usize) {
+    // SAFETY: only the addresses of the linker symbols are taken
+    unsafe { (crate::sym_addr(&crate::stack_space), crate::sym_addr(&crate::stack_top)) }
+}
+
+/// Fill the boot stack from its bottom up to just below the current RSP
+/// with `STACK_SENTINEL`.
+///
+/// SAFETY: call once, on the boot stack; nothing may live below the
+/// current frame.
+#[cfg(feature = "stack-probe")]
+#[inline(never)]
+pub unsafe fn paint_stack() {
+    let (lo, _) = boot_stack();
+    let rsp: usize;
+    core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack));
+    let end = rsp.saturating_sub(PAINT_MARGIN) & !7;
+
+    let mut p = lo;
+    while p < end {
+        (p as *mut u64).write_volatile(STACK_SENTINEL);
+        p += 8;
+    }
+}
+
+/// How many of `words` (lowest address first) still hold the sentinel
+/// before the first disturbed one
+#[cfg(feature = "stack-probe")]
+pub fn untouched_words(words: impl Iterator<Item = u64>) -> usize {
+    words.take_while(|&w| w == STACK_SENTINEL).count()
+}
+
+/// Peak depth of the boot stack in bytes: everything above the lowest
+/// word that no longer holds the sentinel. This only measures the primary
+/// boot stack (`stack_space` in entry.S), not AP, IST or task stacks, and
+/// can underestimate by a word or so if a frame happened to store the
+/// sentinel value itself.
+#[cfg(feature = "stack-probe")]
+pub fn stack_high_watermark() -> usize {
+    let (lo, hi) = boot_stack();
+    // SAFETY: `[lo, hi)` is the boot stack, mapped and readable
+    let words = (lo..hi).step_by(8).map(|a| unsafe { (a as *const u64).read_volatile() });
+    hi - lo - 8 * untouched_words(words)
+}
+
+#[cfg(all(test, feature = "stack-probe"))]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn untouched_words_counts_the_leading_sentinels() {
+        let words = [STACK_SENTINEL, STACK_SENTINEL, 0, STACK_SENTINEL];
+        assert_eq!(untouched_words(words.iter().copied()), 2);
+    }
+
+    #[test_case]
+    fn untouched_words_of_a_fully_painted_stack() {
+        let words = [STACK_SENTINEL; 8];
+        assert_eq!(untouched_words(words.iter().copied()), 8);
+    }
+
+    #[test_case]
+    fn untouched_words_stops_at_the_lowest_word() {
+        let words = [1, STACK_SENTINEL, STACK_SENTINEL];
+        assert_eq!(untouched_words(words.iter().copied()), 0);
+        assert_eq!(untouched_words(core::iter::empty()), 0);
+    }
+}
@@ -0,0 +1,112 @@
+// rooster-os/arch/x86/runtime/rs_runtime/testing.rs
+//! In-kernel test runner for `#![test_runner]` / `#[test_case]`.
+//!   • Prints `name... [ok]` / `[failed]` per test over serial
+//!   • A panicking test is reported as failed and the run continues
+//!     with the next test (from the panic handler, on the same stack)
+//!   • Exits QEMU with `Success` or `Failed` once every test has run
+//!
+//! Build with `cargo test --features testing` under QEMU with the
+//! debug-exit device (see `qemu.rs`). A test module looks like:
+//!
+//! ```ignore
+//! #[cfg(test)]
+//! mod tests {
+//!     #[test_case]
+//!     fn trivial_assertion() {
+//!         assert_eq!(1 + 1, 2);
+//!     }
+//! }
+//! ```
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::qemu::{self, QemuExitCode};
+use crate::serial;
+
+/// Anything the runner can execute
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial::write_fmt(format_args!("{}...\t", core::any::type_name::<T>()));
+        self();
+        serial::write_str("[ok]\n");
+    }
+}
+
+/// Tests handed to `test_runner`; kept so the panic handler can resume
+static mut TESTS: Option<&'static [&'static dyn Testable]> = None;
+static NEXT:    AtomicUsize = AtomicUsize::new(0);
+static FAILED:  AtomicUsize = AtomicUsize::new(0);
+static RUNNING: AtomicBool  = AtomicBool::new(false);
+
+/// Entry point named in `#![test_runner(crate::testing::test_runner)]`
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial::write_fmt(format_args!("running {} tests\n", tests.len()));
+    // SAFETY: the harness passes a slice of statics, and tests run on
+    // the boot CPU only
+    unsafe {
+        TESTS = Some(core::mem::transmute::<&[&dyn Testable], &'static [&'static dyn Testable]>(tests));
+    }
+    NEXT.store(0, Ordering::SeqCst);
+    FAILED.store(0, Ordering::SeqCst);
+    RUNNING.store(true, Ordering::SeqCst);
+    run_remaining();
+}
+
+/// Run every test not yet started, then report and exit QEMU
+fn run_remaining() {
+    let tests = unsafe { TESTS.unwrap_or(&[]) };
+    loop {
+        let i = NEXT.fetch_add(1, Ordering::SeqCst);
+        match tests.get(i) {
+            Some(test) => test.run(),
+            None       => break,
+        }
+    }
+    RUNNING.store(false, Ordering::SeqCst);
+
+    let failed = FAILED.load(Ordering::SeqCst);
+    serial::write_fmt(format_args!(
+        "test result: {} passed; {} failed\n",
+        tests.len() - failed,
+        failed
+    ));
+    qemu::exit(if failed == 0 { QemuExitCode::Success } else { QemuExitCode::Failed });
+}
+
+/// Called first thing in the panic handler, right after its recursion
+/// guard. If a test is running it is marked failed and the remaining
+/// tests run; otherwise this returns and the normal panic path continues.
+pub fn on_panic(info: &PanicInfo) {
+    if !RUNNING.load(Ordering::SeqCst) {
+        return;
+    }
+    FAILED.fetch_add(1, Ordering::SeqCst);
+    serial::write_fmt(format_args!("[failed]\n  {}\n", info));
+    // the next test runs inside this handler: a panic there is not nested
+    crate::runtime_core::panic::leave();
+    run_remaining();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn trivial_assertion() {
+        assert_eq!(1 + 1, 2);
+    }
+
+    #[test_case]
+    fn runner_is_active_during_a_test() {
+        assert!(RUNNING.load(Ordering::SeqCst));
+        // this test has been claimed, so the cursor is already past it
+        let tests = unsafe { TESTS.unwrap_or(&[]) };
+        let next = NEXT.load(Ordering::SeqCst);
+        assert!((1..=tests.len()).contains(&next));
+    }
+}
@@ -0,0 +1,191 @@
+// rooster-os/arch/x86/runtime/rs_runtime/vga.rs
+//! VGA text-mode console at 0xB8000 for early on-screen output.
+//!   • 80x25 cells, software cursor mirrored to the hardware cursor
+//!   • Scrolls up one line when output runs past the last row
+//!   • `set_console(&vga::WRITER)` makes it the `print!`/`println!` sink
+
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::port::outb;
+use crate::{Console, Spinlock};
+
+const BUFFER_ADDR:   usize = 0xB8000;
+const BUFFER_WIDTH:  usize = 80;
+const BUFFER_HEIGHT: usize = 25;
+
+/// Standard 16-colour VGA palette
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black      = 0,
+    Blue       = 1,
+    Green      = 2,
+    Cyan       = 3,
+    Red        = 4,
+    Magenta    = 5,
+    Brown      = 6,
+    LightGray  = 7,
+    DarkGray   = 8,
+    LightBlue  = 9,
+    LightGreen = 10,
+    LightCyan  = 11,
+    LightRed   = 12,
+    Pink       = 13,
+    Yellow     = 14,
+    White      = 15,
+}
+
+/// Foreground/background attribute byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+impl ColorCode {
+    pub const fn new(fg: Color, bg: Color) -> Self {
+        ColorCode((bg as u8) << 4 | (fg as u8))
+    }
+}
+
+/// One character cell as the hardware expects it
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct ScreenChar {
+    ascii: u8,
+    color: ColorCode,
+}
+
+/// Text writer with cursor tracking over the VGA buffer
+pub struct Writer {
+    row:    usize,
+    col:    usize,
+    color:  ColorCode,
+    buffer: *mut ScreenChar,
+}
+
+// SAFETY: the buffer is fixed MMIO; access is serialized by `WRITER`'s lock
+unsafe impl Send for Writer {}
+
+impl Writer {
+    pub const fn new(color: ColorCode) -> Self {
+        Writer { row: 0, col: 0, color, buffer: BUFFER_ADDR as *mut ScreenChar }
+    }
+
+    pub fn set_color(&mut self, color: ColorCode) {
+        self.color = color;
+    }
+
+    fn cell(&self, row: usize, col: usize) -> *mut ScreenChar {
+        // SAFETY: callers keep row < BUFFER_HEIGHT and col < BUFFER_WIDTH
+        unsafe { self.buffer.add(row * BUFFER_WIDTH + col) }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            byte  => {
+                if self.col >= BUFFER_WIDTH {
+                    self.new_line();
+                }
+                let ch = ScreenChar { ascii: byte, color: self.color };
+                unsafe { write_volatile(self.cell(self.row, self.col), ch) };
+                self.col += 1;
+            }
+        }
+        self.update_cursor();
+    }
+
+    /// Advance to the next row, scrolling instead of writing past the
+    /// last row of the buffer
+    fn new_line(&mut self) {
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+        self.col = 0;
+    }
+
+    fn scroll(&mut self) {
+        for row in 1..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                unsafe {
+                    let ch = read_volatile(self.cell(row, col));
+                    write_volatile(self.cell(row - 1, col), ch);
+                }
+            }
+        }
+        self.clear_row(BUFFER_HEIGHT - 1);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let blank = ScreenChar { ascii: b' ', color: self.color };
+        for col in 0..BUFFER_WIDTH {
+            unsafe { write_volatile(self.cell(row, col), blank) };
+        }
+    }
+
+    /// Blank the whole screen and home the cursor
+    pub fn clear(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.row = 0;
+        self.col = 0;
+        self.update_cursor();
+    }
+
+    /// Mirror the software cursor into the CRT controller
+    fn update_cursor(&self) {
+        let pos = (self.row * BUFFER_WIDTH + self.col.min(BUFFER_WIDTH - 1)) as u16;
+        unsafe {
+            outb(0x3D4, 0x0F);
+            outb(0x3D5, (pos & 0xFF) as u8);
+            outb(0x3D4, 0x0E);
+            outb(0x3D5, (pos >> 8) as u8);
+        }
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            // printable ASCII or newline; anything else shows as ■
+            match byte {
+                0x20..=0x7e | b'\n' | b'\r' => self.write_byte(byte),
+                _ => self.write_byte(0xfe),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub static WRITER: Spinlock<Writer> =
+    Spinlock::new(Writer::new(ColorCode::new(Color::LightGray, Color::Black)));
+
+/// `set_console(&vga::WRITER)` routes `print!`/`println!` here. While
+/// panicking a held lock means we panicked mid-print: skip the output
+/// rather than deadlock.
+impl Console for Spinlock<Writer> {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        if crate::runtime_core::panic::in_progress() {
+            match self.try_lock() {
+                Some(mut writer) => fmt::Write::write_str(&mut *writer, s),
+                None             => Ok(()),
+            }
+        } else {
+            fmt::Write::write_str(&mut *self.lock(), s)
+        }
+    }
+}
+
+/// Print panic output without risking a deadlock: if the writer is
+/// already locked (we panicked mid-print) the output is skipped
+pub fn panic_print(args: fmt::Arguments) {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.set_color(ColorCode::new(Color::White, Color::Red));
+        let _ = fmt::Write::write_fmt(&mut *writer, args);
+    }
+}
@@ -0,0 +1,103 @@
+// rooster-os/arch/x86/runtime/rs_runtime/watchdog.rs
+//! Boot watchdog (`boot-watchdog` feature): reboots if `kernel_main` is
+//! not reached within `TIMEOUT_MS` (default 5 s).
+//!   • PIT channel 0 ticks at 100 Hz on IRQ0, routed through the legacy
+//!     8259 PIC remapped to `TIMER_VECTOR`; every other IRQ stays masked
+//!   • On expiry prints "hang before kernel_main" to COM1 and resets
+//!   • `disarm` masks IRQ0, clears the vector and disables interrupts
+//!     again; the panic handler calls it too, so a panic during init is
+//!     reported instead of being cut short by a reset
+//!
+//! It needs the IDT, so `rust_start` arms it right after `idt::init`; the
+//! steps before that are straight-line and run with interrupts off.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::delay::PIT_HZ;
+use crate::idt::{self, HandlerFunc, InterruptStackFrame};
+use crate::port::outb;
+use crate::{reboot, serial};
+
+/// Time the runtime gets from arming to `kernel_main`
+pub const TIMEOUT_MS: u32 = 5_000;
+
+/// Vector IRQ0 is remapped to (the first one past the CPU exceptions)
+pub const TIMER_VECTOR: u8 = 0x20;
+
+/// Divisor of the PIT clock (`delay::PIT_HZ`) for a 100 Hz tick (10 ms)
+const TICK_HZ:     u32 = 100;
+const PIT_DIVISOR: u16 = (PIT_HZ / TICK_HZ) as u16;
+
+// PIT ports and the channel 0 mode byte (lobyte/hibyte, rate generator)
+const PIT_CH0:        u16 = 0x40;
+const PIT_CMD:        u16 = 0x43;
+const PIT_CH0_RATE:   u8  = 0x34;
+/// Channel 0 in one-shot mode with no count loaded: stops the ticks
+const PIT_CH0_ONESHOT: u8 = 0x30;
+
+// 8259 PIC command/data ports and commands
+const PIC1_CMD:  u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_CMD:  u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+const PIC_INIT:  u8  = 0x11; // ICW1: init, ICW4 follows
+const PIC_8086:  u8  = 0x01; // ICW4: 8086 mode
+const PIC_EOI:   u8  = 0x20;
+
+/// Ticks left before the watchdog fires
+static TICKS_LEFT: AtomicU32 = AtomicU32::new(0);
+/// Between `arm` and `disarm`
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+/// Program the PIC and PIT, install the timer handler and enable
+/// interrupts.
+///
+/// SAFETY: the IDT must be loaded; nothing else may own the PIC or PIT.
+pub unsafe fn arm() {
+    TICKS_LEFT.store(TIMEOUT_MS / (1000 / TICK_HZ), Ordering::Relaxed);
+    ARMED.store(true, Ordering::Relaxed);
+    idt::set_handler(TIMER_VECTOR, timer_tick as HandlerFunc as usize);
+
+    // remap the PICs to TIMER_VECTOR.. / TIMER_VECTOR + 8.., cascade on IRQ2
+    outb(PIC1_CMD,  PIC_INIT);
+    outb(PIC2_CMD,  PIC_INIT);
+    outb(PIC1_DATA, TIMER_VECTOR);
+    outb(PIC2_DATA, TIMER_VECTOR + 8);
+    outb(PIC1_DATA, 1 << 2);
+    outb(PIC2_DATA, 2);
+    outb(PIC1_DATA, PIC_8086);
+    outb(PIC2_DATA, PIC_8086);
+    // only IRQ0 unmasked
+    outb(PIC1_DATA, !1);
+    outb(PIC2_DATA, 0xFF);
+
+    outb(PIT_CMD, PIT_CH0_RATE);
+    outb(PIT_CH0, (PIT_DIVISOR & 0xFF) as u8);
+    outb(PIT_CH0, (PIT_DIVISOR >> 8) as u8);
+
+    core::arch::asm!("sti", options(nomem, nostack));
+}
+
+/// Stop the watchdog: interrupts off, IRQ0 masked, PIT stopped, vector
+/// cleared. The PIC stays remapped (and fully masked). Does nothing if
+/// the watchdog is not armed, so the panic handler may call it anywhere.
+///
+/// SAFETY: nothing but the watchdog may be using the PIC or PIT.
+pub unsafe fn disarm() {
+    if !ARMED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    core::arch::asm!("cli", options(nomem, nostack));
+    outb(PIC1_DATA, 0xFF);
+    outb(PIT_CMD, PIT_CH0_ONESHOT);
+    idt::clear_handler(TIMER_VECTOR);
+}
+
+extern "x86-interrupt" fn timer_tick(_frame: InterruptStackFrame) {
+    if TICKS_LEFT.fetch_sub(1, Ordering::Relaxed) <= 1 {
+        serial::write_str("hang before kernel_main\n");
+        serial::com1().drain();
+        reboot::triple_fault_reboot();
+    }
+    unsafe { outb(PIC1_CMD, PIC_EOI) };
+}
@@ -0,0 +1,90 @@
+// rooster-os/arch/aarch64/runtime/rs_runtime/dtb.rs
+//! Flattened device tree (DTB) header validation for the handoff in x0.
+//!   • Checks pointer alignment, the big-endian magic, the header version
+//!     and that the declared blocks fit inside `totalsize`
+//!   • Parsing the tree itself is left to the kernel
+
+use core::fmt;
+
+/// `magic` field value (stored big-endian)
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// Oldest layout whose header carries every field below
+const FDT_MIN_COMPAT_VERSION: u32 = 16;
+
+/// Sanity limit on `totalsize`; real blobs are tens of KiB
+const FDT_MAX_SIZE: u32 = 2 * 1024 * 1024;
+
+/// DTB header; every field is big-endian in memory
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FdtHeader {
+    magic:             u32,
+    totalsize:         u32,
+    off_dt_struct:     u32,
+    off_dt_strings:    u32,
+    off_mem_rsvmap:    u32,
+    version:           u32,
+    last_comp_version: u32,
+    boot_cpuid_phys:   u32,
+    size_dt_strings:   u32,
+    size_dt_struct:    u32,
+}
+
+/// Why a DTB pointer was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DtbError {
+    Null,
+    /// The spec requires 8-byte alignment
+    Misaligned,
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    BadSize(u32),
+}
+
+impl fmt::Display for DtbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DtbError::Null                  => write!(f, "null pointer"),
+            DtbError::Misaligned            => write!(f, "not 8-byte aligned"),
+            DtbError::BadMagic(m)           => write!(f, "bad magic {:#x}", m),
+            DtbError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            DtbError::BadSize(s)            => write!(f, "implausible size {:#x}", s),
+        }
+    }
+}
+
+/// Check the blob at `addr`; on success returns its `totalsize`.
+///
+/// SAFETY: if `addr` is non-null and aligned, the 40-byte header there
+/// must be readable.
+pub unsafe fn validate(addr: usize) -> Result<usize, DtbError> {
+    if addr == 0 {
+        return Err(DtbError::Null);
+    }
+    if addr % 8 != 0 {
+        return Err(DtbError::Misaligned);
+    }
+
+    let raw = core::ptr::read(addr as *const FdtHeader);
+    let magic = u32::from_be(raw.magic);
+    if magic != FDT_MAGIC {
+        return Err(DtbError::BadMagic(magic));
+    }
+    let last_comp = u32::from_be(raw.last_comp_version);
+    if last_comp > FDT_MIN_COMPAT_VERSION {
+        return Err(DtbError::UnsupportedVersion(last_comp));
+    }
+
+    let total = u32::from_be(raw.totalsize);
+    let header = core::mem::size_of::<FdtHeader>() as u32;
+    let fits = |off: u32, len: u32| off.checked_add(len).is_some_and(|end| end <= total);
+    if total < header
+        || total > FDT_MAX_SIZE
+        || !fits(u32::from_be(raw.off_dt_struct), u32::from_be(raw.size_dt_struct))
+        || !fits(u32::from_be(raw.off_dt_strings), u32::from_be(raw.size_dt_strings))
+    {
+        return Err(DtbError::BadSize(total));
+    }
+    Ok(total as usize)
+}
@@ -0,0 +1,48 @@
+// rooster-os/arch/aarch64/runtime/rs_runtime/interrupts.rs
+//! Masking interrupts around critical sections.
+//!   • `InterruptGuard` saves DAIF and masks IRQ/FIQ (`msr daifset, #3`);
+//!     dropping it writes the saved DAIF back, so guards nest
+//!   • `without_interrupts(f)` runs `f` under a guard; `runtime_core`
+//!     takes the allocator locks inside one
+
+use core::arch::asm;
+
+/// Current DAIF
+#[inline]
+pub fn daif() -> u64 {
+    let daif: u64;
+    // SAFETY: reading DAIF has no side effects
+    unsafe { asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags)) };
+    daif
+}
+
+/// IRQs and FIQs stay masked while this is alive
+pub struct InterruptGuard {
+    saved: u64,
+}
+
+impl InterruptGuard {
+    /// Save DAIF, then mask IRQ and FIQ
+    #[inline]
+    pub fn new() -> Self {
+        let saved = daif();
+        // SAFETY: masking interrupts cannot break memory safety
+        unsafe { asm!("msr daifset, #3", options(nomem, nostack)) };
+        InterruptGuard { saved }
+    }
+}
+
+impl Drop for InterruptGuard {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: only puts back the mask bits saved in `new`
+        unsafe { asm!("msr daif, {}", in(reg) self.saved, options(nomem, nostack)) };
+    }
+}
+
+/// Run `f` with IRQs and FIQs masked, then restore DAIF
+#[inline]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = InterruptGuard::new();
+    f()
+}
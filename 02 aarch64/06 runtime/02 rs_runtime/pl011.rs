@@ -0,0 +1,70 @@
+// rooster-os/arch/aarch64/runtime/rs_runtime/pl011.rs
+//! Minimal polled PL011 UART driver, used for early and panic output.
+//!   • Assumes the firmware already configured baud rate and pins
+//!     (`enable_uart=1` in config.txt)
+//!   • Lock-free like the x86 serial driver, so the panic handler can
+//!     use it at any time
+
+use core::fmt;
+
+use crate::Console;
+
+/// UART0 MMIO base: Pi 4 (BCM2711) low-peripheral mode. On a Pi 3
+/// (BCM2837) use 0x3F20_1000.
+pub const UART0_BASE: usize = 0xFE20_1000;
+
+// Register offsets
+const UART_DR: usize = 0x00;
+const UART_FR: usize = 0x18;
+
+/// Flag-register bit: transmit FIFO full
+const FR_TXFF: u32 = 1 << 5;
+
+/// A PL011 at a fixed MMIO base
+#[derive(Clone, Copy)]
+pub struct Pl011 {
+    base: usize,
+}
+
+impl Pl011 {
+    pub const fn new(base: usize) -> Self {
+        Pl011 { base }
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        let fr = (self.base + UART_FR) as *const u32;
+        let dr = (self.base + UART_DR) as *mut u32;
+        // SAFETY: `base` is the identity-mapped PL011 register block
+        unsafe {
+            while fr.read_volatile() & FR_TXFF != 0 {
+                core::hint::spin_loop();
+            }
+            dr.write_volatile(byte as u32);
+        }
+    }
+}
+
+impl fmt::Write for Pl011 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// As a `print!`/`println!` sink: `static UART: Pl011 =
+/// Pl011::new(UART0_BASE)`, then `set_console(&UART)`
+impl Console for Pl011 {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        fmt::Write::write_str(&mut { *self }, s)
+    }
+}
+
+/// A handle to UART0, for APIs that take a `fmt::Write`
+pub fn uart0() -> Pl011 {
+    Pl011::new(UART0_BASE)
+}
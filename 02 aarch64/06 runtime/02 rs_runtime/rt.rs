@@ -0,0 +1,151 @@
+// rooster-os/arch/aarch64/runtime/rs_runtime/rt.rs
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+#![feature(core_intrinsics)]
+
+//! Rust “runtime” for RoosterOS on AArch64 (Raspberry Pi 3/4).
+//!   • Validates the device-tree blob (DTB) handed over in x0
+//!   • Copies .data from its load address to RAM
+//!   • Zeroes .bss
+//!   • Initializes the heap allocator as GlobalAlloc (not under `no-heap`,
+//!     for kernels that never allocate: any `alloc` use then fails to link)
+//!   • Provides panic and alloc‐error handlers (panics print to the PL011)
+//!   • Transfers control to `kernel_main(dtb_addr) -> !`
+//!
+//! Entry convention (see entry.S): the firmware enters `_start` on every
+//! core with x0 = physical address of the DTB (x1-x3 = 0). Secondary
+//! cores are parked in the stub; only core 0 reaches `rust_start`, with
+//! the DTB address as its first argument.
+//!
+//! The allocators, OOM policy and panic formatting are shared with the
+//! x86 runtime through `runtime_core`.
+
+use core::panic::PanicInfo;
+
+pub mod dtb;
+pub mod interrupts;
+pub mod pl011;
+
+#[path = "../../../00 common/06 runtime/runtime_core/mod.rs"]
+pub mod runtime_core;
+
+pub use runtime_core::heap::{
+    set_oom_handler, AllocError, BumpAllocator, EmergencyHeap, HeapStats, LinkedListAllocator,
+    OomAction, PerCpuHeap, SlabAllocator, EMERGENCY_HEAP, EMERGENCY_HEAP_SIZE, MAX_HEAP_REGIONS,
+    MIN_ALIGN,
+};
+#[cfg(not(feature = "no-heap"))]
+pub use runtime_core::heap::{alloc_aligned, free_aligned, ALLOCATOR};
+#[cfg(feature = "percpu-heap")]
+pub use runtime_core::heap::init_local_arena;
+pub use runtime_core::buddy::BuddyAllocator;
+pub use runtime_core::console::{set_console, Console, TeeConsole};
+pub use runtime_core::hexdump::hexdump;
+pub use runtime_core::panic::set_panic_hook;
+pub use runtime_core::sync::{IrqSpinlockGuard, Once, Spinlock, SpinlockGuard};
+pub use interrupts::without_interrupts;
+use runtime_core::mem::{copy_data, zero_region};
+
+// The call-site walk in x86's alloc_track reads RBP; there is no AArch64
+// equivalent yet
+#[cfg(feature = "track-allocs")]
+compile_error!("the track-allocs feature is only supported on x86_64");
+
+// Symbols provided by the linker script
+extern "C" {
+    // Kernel entry point: receives the validated DTB address
+    fn kernel_main(dtb_addr: usize) -> !;
+
+    // Data segment: load‐address, start, end
+    static __data_load: u8;
+    static mut __data_start: u8;
+    static mut __data_end:   u8;
+
+    // BSS segment: start, end
+    static mut __bss_start: u8;
+    static mut __bss_end:   u8;
+
+    // Heap region: start, end
+    static mut __heap_start: u8;
+    static mut __heap_end:   u8;
+}
+
+/// Write to the early console (UART0) without `fmt`; `runtime_core` uses
+/// it for output that must not depend on the panic path
+pub(crate) fn early_write(s: &str) {
+    let _ = core::fmt::Write::write_str(&mut pl011::uart0(), s);
+}
+
+/// Index of the executing core for `runtime_core` (per-CPU heap arenas):
+/// MPIDR_EL1.Aff0, the same value entry.S parks secondaries by
+pub(crate) fn cpu_id() -> usize {
+    let mpidr: usize;
+    unsafe { core::arch::asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack)) };
+    mpidr & 0xff
+}
+
+/// Park the current core: `wfe` with interrupts masked
+pub(crate) fn halt() -> ! {
+    loop {
+        unsafe { core::arch::asm!("msr daifset, #0xf", "wfe", options(nomem, nostack)); }
+    }
+}
+
+/// Entry point called from assembly stub (`entry.S`):
+///   x0 = DTB physical address
+#[no_mangle]
+pub extern "C" fn rust_start(dtb_addr: usize) -> ! {
+    // 1) Validate the device tree handoff
+    if let Err(err) = unsafe { dtb::validate(dtb_addr) } {
+        panic!("Bad device tree blob at {:#x}: {}", dtb_addr, err);
+    }
+
+    unsafe {
+        // 2) Copy .data from its load address to RAM
+        let data_size = (&__data_end as *const _ as usize)
+                      - (&__data_start as *const _ as usize);
+        copy_data(
+            &__data_load as *const u8,
+            &mut __data_start as *mut u8,
+            data_size,
+        );
+
+        // 3) Zero .bss
+        let bss_size = (&__bss_end as *const _ as usize)
+                     - (&__bss_start as *const _ as usize);
+        zero_region(&mut __bss_start as *mut u8, bss_size);
+
+        // 4) Initialize heap allocator (there is none under `no-heap`)
+        #[cfg(not(feature = "no-heap"))]
+        ALLOCATOR.init(
+            &__heap_start as *const _ as usize,
+            &__heap_end   as *const _ as usize,
+        );
+    }
+
+    // 5) Call the kernel’s main function (does not return)
+    unsafe { kernel_main(dtb_addr) }
+}
+
+/// Panic handler prints info over the PL011 UART then parks the core
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // Panicked while panicking: skip the output that may fault again
+    if runtime_core::panic::enter() > 1 {
+        halt()
+    }
+
+    // The kernel's hook first; the guard above catches it faulting
+    runtime_core::panic::run_hook(info);
+
+    // From here on nothing may allocate (checked in debug builds)
+    runtime_core::panic::begin();
+
+    // To the kernel's console, else straight to UART0
+    match runtime_core::console::console() {
+        Some(_) => runtime_core::panic::report(&mut runtime_core::console::ConsoleWriter, info),
+        None    => runtime_core::panic::report(&mut pl011::uart0(), info),
+    }
+    halt()
+}